@@ -1,27 +1,40 @@
 use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
 
-use super::service::AIService;
+use crate::ai_service::{create_provider, CancelRegistry, ProviderConfig, TokenUsage};
+use crate::database::Database;
+use crate::error::AppError;
+use super::service::{AIService, UsageRecorder};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AIProcessRequest {
     pub prompt: String,
     pub image_data: String,
+    #[serde(default = "default_provider")]
+    pub provider: String,
     pub api_url: String,
     pub api_key: String,
     pub model: String,
     pub style_prompt: Option<String>,
+    /// 用于取消请求的 ID
+    #[serde(default)]
+    pub request_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AIProcessResponse {
     pub content: String,
     pub tokens_used: u32,
+    pub usage: TokenUsage,
     pub model: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StyleGenerationRequest {
     pub content: String,
+    #[serde(default = "default_provider")]
+    pub provider: String,
     pub api_url: String,
     pub api_key: String,
     pub model: String,
@@ -33,24 +46,47 @@ pub struct StyleGenerationResponse {
     pub prompt: String,
 }
 
+type DatabaseState = Mutex<Database>;
+
+fn default_provider() -> String {
+    "openai".to_string()
+}
+
 /// AI处理图片接口
 #[tauri::command]
 pub async fn process_image(
+    app: AppHandle,
+    db: State<'_, DatabaseState>,
+    cancel_registry: State<'_, CancelRegistry>,
     request: AIProcessRequest,
-) -> Result<AIProcessResponse, String> {
-    let service = AIService::new();
+) -> Result<AIProcessResponse, AppError> {
+    let config = ProviderConfig::new(&request.provider, request.api_url, request.api_key)
+        .map_err(AppError::Validation)?;
+    let (preprocess, metadata_whitelist) = {
+        let db = db.lock()?;
+        let setting = db.setting().get_or_create_default()
+            .map_err(|e| AppError::database("Failed to get settings", e))?;
+        let preprocess = db.preprocess_setting().get_options(config.kind)
+            .map_err(|e| AppError::database("Failed to get preprocess settings", e))?;
+        (preprocess, setting.metadata_whitelist)
+    };
+    let cancel_guard = cancel_registry.register(request.request_id.into_iter().collect());
+    let service = AIService::new(create_provider(config))
+        .with_cancel_token(cancel_guard.token())
+        .with_preprocess(preprocess)
+        .with_metadata_whitelist(metadata_whitelist)
+        .with_usage_recorder(UsageRecorder::new(app, None));
     let service_response = service.process_image(
         request.prompt,
         request.image_data,
-        request.api_url,
-        request.api_key,
         request.model,
         request.style_prompt,
     ).await?;
 
     Ok(AIProcessResponse {
         content: service_response.content,
-        tokens_used: service_response.tokens_used,
+        tokens_used: service_response.usage.total(),
+        usage: service_response.usage,
         model: service_response.model,
     })
 }
@@ -58,13 +94,15 @@ pub async fn process_image(
 /// 生成风格接口
 #[tauri::command]
 pub async fn generate_style(
+    app: AppHandle,
     request: StyleGenerationRequest,
-) -> Result<StyleGenerationResponse, String> {
-    let service = AIService::new();
+) -> Result<StyleGenerationResponse, AppError> {
+    let config = ProviderConfig::new(&request.provider, request.api_url, request.api_key)
+        .map_err(AppError::Validation)?;
+    let service = AIService::new(create_provider(config))
+        .with_usage_recorder(UsageRecorder::new(app, None));
     let service_response = service.generate_style_from_content(
         request.content,
-        request.api_url,
-        request.api_key,
         request.model,
     ).await?;
 
@@ -72,4 +110,4 @@ pub async fn generate_style(
         name: service_response.name,
         prompt: service_response.prompt,
     })
-}
\ No newline at end of file
+}
@@ -1,9 +1,30 @@
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::ai_service::{AIError, AIProvider, ChatMessage, DeltaCallback, RetryConfig, TokenUsage};
+use crate::database::{
+    Database, UsageEvent, USAGE_OPERATION_EDIT_IMAGE, USAGE_OPERATION_GENERATE_STYLE,
+    USAGE_OPERATION_PROCESS_IMAGE,
+};
+use crate::error::AppError;
+use crate::image_ops::preprocess::{preprocess_image, PreprocessOptions};
+use crate::image_ops::privacy::{strip_image_metadata, MetadataKind};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AIProcessResponse {
     pub content: String,
-    pub tokens_used: u32,
+    pub usage: TokenUsage,
+    pub model: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AIImageEditResponse {
+    pub image_data: String,
+    pub usage: TokenUsage,
     pub model: String,
 }
 
@@ -13,11 +34,119 @@ pub struct StyleGenerationResponse {
     pub prompt: String,
 }
 
-pub struct AIService;
+type DatabaseState = Mutex<Database>;
+
+/// 将每次成功的供应商调用写入 usage_event 表，gallery_id 为调用所属的图库记录
+pub struct UsageRecorder {
+    app: AppHandle,
+    gallery_id: Option<String>,
+}
+
+impl UsageRecorder {
+    pub fn new(app: AppHandle, gallery_id: Option<String>) -> Self {
+        Self { app, gallery_id }
+    }
+
+    // 记录失败不影响调用结果，只写日志
+    fn record(&self, provider: &str, operation: &str, model: &str, usage: TokenUsage, started: Instant) {
+        let event = UsageEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            operation: operation.to_string(),
+            gallery_id: self.gallery_id.clone(),
+            input_tokens: usage.input_tokens as i64,
+            output_tokens: usage.output_tokens as i64,
+            latency_ms: started.elapsed().as_millis() as i64,
+            create_at: Utc::now().timestamp_millis(),
+        };
+
+        let db = self.app.state::<DatabaseState>();
+        let result = db.lock()
+            .map_err(|e| e.to_string())
+            .and_then(|db| db.usage().create(&event).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::warn!("记录 {} 调用用量失败: {}", operation, e);
+        }
+    }
+}
+
+pub struct AIService {
+    provider: Arc<dyn AIProvider>,
+    cancel_token: CancellationToken,
+    retry_config: RetryConfig,
+    preprocess: PreprocessOptions,
+    metadata_whitelist: Vec<MetadataKind>,
+    usage_recorder: Option<UsageRecorder>,
+}
 
 impl AIService {
-    pub fn new() -> Self {
-        Self
+    pub fn new(provider: Arc<dyn AIProvider>) -> Self {
+        let preprocess = PreprocessOptions::for_provider(provider.kind());
+        Self {
+            provider,
+            cancel_token: CancellationToken::new(),
+            retry_config: RetryConfig::default(),
+            preprocess,
+            metadata_whitelist: Vec::new(),
+            usage_recorder: None,
+        }
+    }
+
+    /// 绑定取消令牌，之后的所有调用都可以被取消
+    pub fn with_cancel_token(mut self, cancel_token: CancellationToken) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    /// 替换默认的重试策略，由任务队列自行重试时可关闭请求级重试
+    pub fn with_retry_config(mut self, retry_config: RetryConfig) -> Self {
+        self.retry_config = retry_config;
+        self
+    }
+
+    /// 替换供应商默认的图片预处理配置
+    pub fn with_preprocess(mut self, preprocess: PreprocessOptions) -> Self {
+        self.preprocess = preprocess;
+        self
+    }
+
+    /// 上传前保留的元数据类别，默认全部移除
+    pub fn with_metadata_whitelist(mut self, metadata_whitelist: Vec<MetadataKind>) -> Self {
+        self.metadata_whitelist = metadata_whitelist;
+        self
+    }
+
+    /// 记录每次调用的用量，未设置时不记录
+    pub fn with_usage_recorder(mut self, usage_recorder: UsageRecorder) -> Self {
+        self.usage_recorder = Some(usage_recorder);
+        self
+    }
+
+    fn record_usage(&self, operation: &str, model: &str, usage: TokenUsage, started: Instant) {
+        if let Some(recorder) = &self.usage_recorder {
+            recorder.record(self.provider.kind().as_str(), operation, model, usage, started);
+        }
+    }
+
+    /// 上传前预处理图片并移除白名单外的元数据，返回 data URL；已处理过的图片再次调用只会读取文件头
+    pub async fn preprocess_image(&self, image_data: String) -> Result<String, AIError> {
+        let options = self.preprocess.clone();
+        let metadata_whitelist = self.metadata_whitelist.clone();
+        let accepted_types = self.provider.kind().accepted_image_types();
+        tauri::async_runtime::spawn_blocking(move || {
+            let image_data = preprocess_image(&image_data, &options, accepted_types)?;
+            strip_image_metadata(&image_data, &metadata_whitelist)
+        })
+            .await
+            .map_err(|e| AIError::new("internal_error", format!("图片预处理失败: {}", e)))?
+            .map_err(|e| AIError::new("image_error", e))
+    }
+
+    fn client(&self) -> crate::ai_service::AIService {
+        crate::ai_service::AIService::new(self.provider.clone())
+            .with_cancel_token(self.cancel_token.clone())
+            .with_retry_config(self.retry_config.clone())
     }
 
     /// 处理图片
@@ -25,49 +154,130 @@ impl AIService {
         &self,
         prompt: String,
         image_data: String,
-        api_url: String,
-        api_key: String,
         model: String,
         style_prompt: Option<String>,
-    ) -> Result<AIProcessResponse, String> {
-        // 处理图片数据
-        let processed_image_data = crate::ai_service::extract_image_base64(&image_data)
-            .map_err(|e| format!("Failed to extract image data: {}", e))?;
+    ) -> Result<AIProcessResponse, AppError> {
+        let image_data = self.preprocess_image(image_data).await?;
+        let request = build_process_request(prompt, image_data, model, style_prompt.as_deref(), Vec::new());
 
-        // 创建处理后的提示词
-        let processed_prompt = crate::ai_service::create_image_processing_prompt(&prompt,
-            style_prompt.as_deref()
-        );
+        let started = Instant::now();
+        let ai_response = self.client().call_ai(request).await?;
+        self.record_usage(USAGE_OPERATION_PROCESS_IMAGE, &ai_response.model, ai_response.usage, started);
 
-        let request = crate::ai_service::AIRequest {
-            model,
-            prompt: processed_prompt,
-            image_data: Some(processed_image_data),
-            max_tokens: Some(1000),
-            temperature: Some(0.7),
-        };
+        Ok(AIProcessResponse {
+            content: ai_response.content,
+            usage: ai_response.usage,
+            model: ai_response.model,
+        })
+    }
 
-        // 使用现有的ai_service模块
-        let ai_service = crate::ai_service::AIService::new();
-        let ai_response = ai_service.call_ai(request, &api_url, &api_key)
-            .await
-            .map_err(|e| format!("AI API call failed: {}", e.message))?;
+    /// 流式处理图片，每段增量文本通过 on_delta 回调，history 为之前的对话轮次
+    ///
+    /// image_data 须已经过 preprocess_image 处理，与 edit_image 共用同一份预处理结果
+    pub async fn process_image_stream(
+        &self,
+        prompt: String,
+        image_data: String,
+        model: String,
+        style_prompt: Option<String>,
+        history: Vec<ChatMessage>,
+        on_delta: &DeltaCallback<'_>,
+    ) -> Result<AIProcessResponse, AIError> {
+        let request = build_process_request(prompt, image_data, model, style_prompt.as_deref(), history);
+
+        let started = Instant::now();
+        let ai_response = self.client().call_ai_stream(request, on_delta).await?;
+        self.record_usage(USAGE_OPERATION_PROCESS_IMAGE, &ai_response.model, ai_response.usage, started);
 
         Ok(AIProcessResponse {
             content: ai_response.content,
-            tokens_used: ai_response.tokens_used,
+            usage: ai_response.usage,
             model: ai_response.model,
         })
     }
 
+    /// 调用图片编辑接口生成 n 张候选结果图
+    ///
+    /// 供应商支持原生多图时按其上限分批请求，否则每张图并发发起一次请求；
+    /// 同一请求返回多张图时用量平均分摊到每张候选图；image_data 须已经过 preprocess_image 处理
+    pub async fn edit_image(
+        &self,
+        prompt: String,
+        image_data: String,
+        image_model: String,
+        style_prompt: Option<String>,
+        n: u32,
+    ) -> Result<Vec<AIImageEditResponse>, AIError> {
+        let processed_prompt = crate::ai_service::create_image_edit_prompt(&prompt,
+            style_prompt.as_deref()
+        );
+
+        let client = self.client();
+        let batch_size = client.max_images_per_request();
+        let mut tasks = tokio::task::JoinSet::new();
+        let mut remaining = n.max(1);
+        let mut batch_index = 0;
+        while remaining > 0 {
+            let count = remaining.min(batch_size);
+            let request = crate::ai_service::AIImageRequest {
+                model: image_model.clone(),
+                prompt: processed_prompt.clone(),
+                image_data: image_data.clone(),
+                size: None,
+                n: count,
+            };
+            let client = client.clone();
+            tasks.spawn(async move { (batch_index, Instant::now(), client.edit_image(request).await) });
+            remaining -= count;
+            batch_index += 1;
+        }
+
+        // 等待全部请求结束再返回错误，已成功的请求同样计费，需要记录用量
+        let mut batches = Vec::new();
+        let mut first_error = None;
+        while let Some(result) = tasks.join_next().await {
+            let response = result
+                .map_err(|e| AIError::new("internal_error", format!("AI image edit failed: {}", e)))
+                .and_then(|(index, started, response)| response.map(|response| (index, started, response)));
+            match response {
+                Ok((index, started, response)) => {
+                    self.record_usage(USAGE_OPERATION_EDIT_IMAGE, &response.model, response.usage, started);
+                    batches.push((index, response));
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+        // 按发起顺序排列，保证候选图顺序稳定
+        batches.sort_by_key(|(index, _)| *index);
+
+        Ok(batches
+            .into_iter()
+            .flat_map(|(_, response)| {
+                let usages = response.usage.split(response.images.len() as u32);
+                let model = response.model;
+                response.images
+                    .into_iter()
+                    .zip(usages)
+                    .map(move |(image_data, usage)| AIImageEditResponse {
+                        image_data,
+                        usage,
+                        model: model.clone(),
+                    })
+            })
+            .collect())
+    }
+
     /// 根据内容生成风格
     pub async fn generate_style_from_content(
         &self,
         content: String,
-        api_url: String,
-        api_key: String,
         model: String,
-    ) -> Result<StyleGenerationResponse, String> {
+    ) -> Result<StyleGenerationResponse, AppError> {
         let prompt = format!(
             "Based on the following user request for image processing, generate a style name and description suitable for an AI image processing style library. \
             Return only a JSON object with 'name' and 'prompt' fields. \
@@ -81,17 +291,18 @@ impl AIService {
             image_data: None,
             max_tokens: Some(200),
             temperature: Some(0.7),
+            history: Vec::new(),
         };
 
-        let ai_service = crate::ai_service::AIService::new();
-        let ai_response = ai_service.call_ai(request, &api_url, &api_key)
-            .await
-            .map_err(|e| format!("AI API call failed: {}", e.message))?;
+        let started = Instant::now();
+        let ai_response = self.client().call_ai(request).await?;
+        self.record_usage(USAGE_OPERATION_GENERATE_STYLE, &ai_response.model, ai_response.usage, started);
 
         // 解析AI响应以获取风格信息
         // 这里简化处理，实际应该解析JSON响应
-        let style_name = extract_style_name(&ai_response.content)?;
-        let style_prompt = extract_style_prompt(&ai_response.content)?;
+        let invalid_response = |details| AppError::Provider { details, retryable: false, retry_after_ms: None };
+        let style_name = extract_style_name(&ai_response.content).map_err(invalid_response)?;
+        let style_prompt = extract_style_prompt(&ai_response.content).map_err(invalid_response)?;
 
         Ok(StyleGenerationResponse {
             name: style_name,
@@ -100,6 +311,27 @@ impl AIService {
     }
 }
 
+// image_data 保持 data URL 形式，供应商据此得到预处理后的真实格式
+fn build_process_request(
+    prompt: String,
+    image_data: String,
+    model: String,
+    style_prompt: Option<&str>,
+    history: Vec<ChatMessage>,
+) -> crate::ai_service::AIRequest {
+    // 创建处理后的提示词
+    let processed_prompt = crate::ai_service::create_image_processing_prompt(&prompt, style_prompt);
+
+    crate::ai_service::AIRequest {
+        model,
+        prompt: processed_prompt,
+        image_data: Some(image_data),
+        max_tokens: Some(1000),
+        temperature: Some(0.7),
+        history,
+    }
+}
+
 fn extract_style_name(content: &str) -> Result<String, String> {
     // 简化实现：从内容中提取风格名称
     // 实际应该解析JSON响应
@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::provider::{classify_http_error, generic_api_error, parse_response, send_request, AIProvider, ProviderConfig, ProviderKind};
+use super::{normalize_history, split_image_data, AIError, AIRequest, AIResponse, TokenUsage};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// Messages API 要求必须指定 max_tokens
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+// Anthropic Messages API 请求结构
+#[derive(Debug, Serialize)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicMessage {
+    role: String,
+    content: Vec<AnthropicContent>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicContent {
+    Text { text: String },
+    Image { source: AnthropicImageSource },
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicImageSource {
+    #[serde(rename = "type")]
+    source_type: String,
+    media_type: String,
+    data: String,
+}
+
+// Anthropic Messages API 响应结构
+#[derive(Debug, Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicResponseContent>,
+    stop_reason: Option<String>,
+    usage: AnthropicUsage,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicResponseContent {
+    #[serde(rename = "type")]
+    content_type: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+    #[serde(default)]
+    cache_creation_input_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: u32,
+}
+
+impl From<AnthropicUsage> for TokenUsage {
+    fn from(usage: AnthropicUsage) -> Self {
+        // input_tokens 不包含缓存部分，需要加回才是完整输入
+        TokenUsage {
+            input_tokens: usage.input_tokens + usage.cache_creation_input_tokens + usage.cache_read_input_tokens,
+            output_tokens: usage.output_tokens,
+            cached_input_tokens: usage.cache_read_input_tokens,
+            image_input_tokens: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicErrorResponse {
+    error: AnthropicErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicErrorDetail {
+    #[serde(rename = "type")]
+    error_type: String,
+    message: String,
+}
+
+/// Anthropic Messages 接口，使用 x-api-key 鉴权，图片以 base64 source 传递
+pub struct AnthropicProvider {
+    client: reqwest::Client,
+    config: ProviderConfig,
+}
+
+impl AnthropicProvider {
+    pub fn new(client: reqwest::Client, config: ProviderConfig) -> Self {
+        Self { client, config }
+    }
+}
+
+#[async_trait]
+impl AIProvider for AnthropicProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Anthropic
+    }
+
+    async fn chat(&self, request: AIRequest) -> Result<AIResponse, AIError> {
+        let mut content = Vec::new();
+
+        // Anthropic 建议图片放在文字之前
+        if let Some(image_data) = &request.image_data {
+            let (media_type, data) = split_image_data(image_data)
+                .map_err(|e| AIError::new("image_error", e))?;
+            content.push(AnthropicContent::Image {
+                source: AnthropicImageSource {
+                    source_type: "base64".to_string(),
+                    media_type,
+                    data: data.to_string(),
+                },
+            });
+        }
+
+        content.push(AnthropicContent::Text { text: request.prompt });
+
+        let mut messages: Vec<AnthropicMessage> = normalize_history(&request.history)
+            .into_iter()
+            .map(|message| AnthropicMessage {
+                role: message.role,
+                content: vec![AnthropicContent::Text { text: message.content }],
+            })
+            .collect();
+        messages.push(AnthropicMessage {
+            role: "user".to_string(),
+            content,
+        });
+
+        let anthropic_request = AnthropicRequest {
+            model: request.model.clone(),
+            max_tokens: request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            messages,
+            temperature: request.temperature,
+        };
+
+        let url = format!("{}/messages", self.config.base_url());
+
+        let response_text = send_request(
+            self.client
+                .post(&url)
+                .header("x-api-key", &self.config.api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .header("Content-Type", "application/json")
+                .json(&anthropic_request),
+            parse_anthropic_error,
+        ).await?;
+
+        let anthropic_response: AnthropicResponse = parse_response(&response_text)?;
+
+        let content: String = anthropic_response
+            .content
+            .iter()
+            .filter(|c| c.content_type == "text")
+            .map(|c| c.text.as_str())
+            .collect();
+
+        if content.is_empty() {
+            return Err(AIError::new("empty_response", "AI 返回了空响应"));
+        }
+
+        Ok(AIResponse {
+            content,
+            usage: anthropic_response.usage.into(),
+            model: request.model,
+            finish_reason: anthropic_response.stop_reason.unwrap_or_default(),
+        })
+    }
+}
+
+fn parse_anthropic_error(status: reqwest::StatusCode, response_text: &str) -> AIError {
+    if let Ok(error_response) = serde_json::from_str::<AnthropicErrorResponse>(response_text) {
+        let error = error_response.error;
+        classify_http_error(status, Some(&error.error_type), None, error.message)
+    } else {
+        generic_api_error(status, response_text)
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// 进行中的 AI 请求登记表，可按请求 ID 或图库 ID 取消。同一个 ID 可以对应多个
+/// 同时进行的请求（如同一批次的多个任务），取消时全部取消
+#[derive(Default)]
+pub struct CancelRegistry {
+    tokens: Mutex<HashMap<String, Vec<(u64, CancellationToken)>>>,
+    next_id: AtomicU64,
+}
+
+impl CancelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 以一个或多个 ID 登记请求，返回的守卫在释放时自动注销
+    pub fn register(&self, keys: Vec<String>) -> CancelGuard<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+        if let Ok(mut tokens) = self.tokens.lock() {
+            for key in &keys {
+                tokens.entry(key.clone()).or_default().push((id, token.clone()));
+            }
+        }
+        CancelGuard {
+            registry: self,
+            id,
+            keys,
+            token,
+        }
+    }
+
+    /// 取消指定 ID 对应的请求，返回是否找到了进行中的请求
+    pub fn cancel(&self, key: &str) -> bool {
+        let tokens = self.tokens.lock().ok().and_then(|tokens| tokens.get(key).cloned());
+        match tokens {
+            Some(tokens) => {
+                for (_, token) in tokens {
+                    token.cancel();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct CancelGuard<'a> {
+    registry: &'a CancelRegistry,
+    id: u64,
+    keys: Vec<String>,
+    token: CancellationToken,
+}
+
+impl CancelGuard<'_> {
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+impl Drop for CancelGuard<'_> {
+    // 只注销自己登记的令牌，同一 ID 下其它仍在进行的请求保持可取消
+    fn drop(&mut self) {
+        if let Ok(mut tokens) = self.registry.tokens.lock() {
+            for key in &self.keys {
+                if let Some(entries) = tokens.get_mut(key) {
+                    entries.retain(|(id, _)| *id != self.id);
+                    if entries.is_empty() {
+                        tokens.remove(key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_key() {
+        let registry = CancelRegistry::new();
+        let first = registry.register(vec!["job-1".to_string(), "batch".to_string()]);
+        let second = registry.register(vec!["job-2".to_string(), "batch".to_string()]);
+
+        // 先结束的请求不能注销仍在进行的请求
+        drop(first);
+        assert!(registry.cancel("batch"));
+        assert!(second.is_cancelled());
+
+        drop(second);
+        assert!(!registry.cancel("batch"));
+        assert!(!registry.cancel("job-2"));
+    }
+}
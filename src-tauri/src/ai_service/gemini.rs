@@ -0,0 +1,291 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::provider::{classify_http_error, generic_api_error, parse_response, send_request, AIProvider, ProviderConfig, ProviderKind};
+use super::{normalize_history, split_image_data, AIError, AIImageRequest, AIImageResponse, AIRequest, AIResponse, TokenUsage};
+
+// Gemini generateContent 请求结构
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    contents: Vec<GeminiContent>,
+    generation_config: GeminiGenerationConfig,
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiContent {
+    role: String,
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiPart {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inline_data: Option<GeminiInlineData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiInlineData {
+    mime_type: String,
+    data: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_modalities: Option<Vec<String>>,
+}
+
+// Gemini generateContent 响应结构
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+    usage_metadata: Option<GeminiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiCandidate {
+    content: Option<GeminiResponseContent>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiResponseContent {
+    #[serde(default)]
+    parts: Vec<GeminiResponsePart>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponsePart {
+    text: Option<String>,
+    inline_data: Option<GeminiInlineData>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+    // 思考模型的推理 token 单独计数，但按输出计费
+    #[serde(default)]
+    thoughts_token_count: u32,
+    #[serde(default)]
+    cached_content_token_count: u32,
+    #[serde(default)]
+    prompt_tokens_details: Vec<GeminiModalityCount>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModalityCount {
+    modality: String,
+    #[serde(default)]
+    token_count: u32,
+}
+
+impl From<GeminiUsage> for TokenUsage {
+    fn from(usage: GeminiUsage) -> Self {
+        TokenUsage {
+            input_tokens: usage.prompt_token_count,
+            output_tokens: usage.candidates_token_count + usage.thoughts_token_count,
+            cached_input_tokens: usage.cached_content_token_count,
+            image_input_tokens: usage
+                .prompt_tokens_details
+                .iter()
+                .filter(|d| d.modality == "IMAGE")
+                .map(|d| d.token_count)
+                .sum(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiErrorResponse {
+    error: GeminiErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiErrorDetail {
+    code: Option<u16>,
+    message: String,
+    status: Option<String>,
+}
+
+/// Gemini generateContent 接口，使用 x-goog-api-key 鉴权，图片以 inlineData 传递
+pub struct GeminiProvider {
+    client: reqwest::Client,
+    config: ProviderConfig,
+}
+
+impl GeminiProvider {
+    pub fn new(client: reqwest::Client, config: ProviderConfig) -> Self {
+        Self { client, config }
+    }
+
+    async fn generate_content(
+        &self,
+        model: &str,
+        gemini_request: &GeminiRequest,
+    ) -> Result<GeminiResponse, AIError> {
+        let url = format!("{}/models/{}:generateContent", self.config.base_url(), model);
+
+        let response_text = send_request(
+            self.client
+                .post(&url)
+                .header("x-goog-api-key", &self.config.api_key)
+                .header("Content-Type", "application/json")
+                .json(gemini_request),
+            parse_gemini_error,
+        ).await?;
+
+        parse_response(&response_text)
+    }
+}
+
+#[async_trait]
+impl AIProvider for GeminiProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Gemini
+    }
+
+    async fn chat(&self, request: AIRequest) -> Result<AIResponse, AIError> {
+        // Gemini 中助手角色名为 model
+        let mut contents: Vec<GeminiContent> = normalize_history(&request.history)
+            .into_iter()
+            .map(|message| GeminiContent {
+                role: if message.is_assistant() { "model" } else { "user" }.to_string(),
+                parts: vec![GeminiPart {
+                    text: Some(message.content),
+                    inline_data: None,
+                }],
+            })
+            .collect();
+        contents.push(GeminiContent {
+            role: "user".to_string(),
+            parts: build_parts(request.prompt, request.image_data.as_deref())?,
+        });
+
+        let gemini_request = GeminiRequest {
+            contents,
+            generation_config: GeminiGenerationConfig {
+                max_output_tokens: request.max_tokens,
+                temperature: request.temperature,
+                response_modalities: None,
+            },
+        };
+
+        let gemini_response = self.generate_content(&request.model, &gemini_request).await?;
+        let usage = gemini_response.usage_metadata.map(TokenUsage::from).unwrap_or_default();
+
+        let Some(candidate) = gemini_response.candidates.into_iter().next() else {
+            return Err(AIError::new("empty_response", "AI 返回了空响应"));
+        };
+
+        let content: String = candidate
+            .content
+            .map(|c| c.parts.into_iter().filter_map(|p| p.text).collect())
+            .unwrap_or_default();
+
+        if content.is_empty() {
+            return Err(AIError::new("empty_response", "AI 返回了空响应"));
+        }
+
+        Ok(AIResponse {
+            content,
+            usage,
+            model: request.model,
+            finish_reason: candidate.finish_reason.unwrap_or_default(),
+        })
+    }
+
+    async fn edit_image(&self, request: AIImageRequest) -> Result<AIImageResponse, AIError> {
+        // 图片生成模型需要显式声明输出图片
+        let gemini_request = GeminiRequest {
+            contents: vec![GeminiContent {
+                role: "user".to_string(),
+                parts: build_parts(request.prompt, Some(&request.image_data))?,
+            }],
+            generation_config: GeminiGenerationConfig {
+                response_modalities: Some(vec!["TEXT".to_string(), "IMAGE".to_string()]),
+                ..Default::default()
+            },
+        };
+
+        let gemini_response = self.generate_content(&request.model, &gemini_request).await?;
+        let usage = gemini_response.usage_metadata.map(TokenUsage::from).unwrap_or_default();
+
+        let mut image_data = None;
+        let mut texts = Vec::new();
+        for part in gemini_response
+            .candidates
+            .into_iter()
+            .filter_map(|c| c.content)
+            .flat_map(|c| c.parts)
+        {
+            if let Some(text) = part.text {
+                texts.push(text);
+            }
+            if let (None, Some(inline_data)) = (&image_data, part.inline_data) {
+                image_data = Some(format!("data:{};base64,{}", inline_data.mime_type, inline_data.data));
+            }
+        }
+
+        let Some(image_data) = image_data else {
+            return Err(AIError::new("empty_response", "AI 没有返回图片"));
+        };
+
+        Ok(AIImageResponse {
+            images: vec![image_data],
+            usage,
+            model: request.model,
+            revised_prompt: if texts.is_empty() { None } else { Some(texts.join("")) },
+        })
+    }
+}
+
+fn build_parts(prompt: String, image_data: Option<&str>) -> Result<Vec<GeminiPart>, AIError> {
+    let mut parts = vec![GeminiPart {
+        text: Some(prompt),
+        inline_data: None,
+    }];
+
+    if let Some(image_data) = image_data {
+        let (mime_type, data) = split_image_data(image_data)
+            .map_err(|e| AIError::new("image_error", e))?;
+        parts.push(GeminiPart {
+            text: None,
+            inline_data: Some(GeminiInlineData {
+                mime_type,
+                data: data.to_string(),
+            }),
+        });
+    }
+
+    Ok(parts)
+}
+
+fn parse_gemini_error(status: reqwest::StatusCode, response_text: &str) -> AIError {
+    if let Ok(error_response) = serde_json::from_str::<GeminiErrorResponse>(response_text) {
+        // 网关转发时外层状态码可能与错误体不一致，以错误体中的 code 为准
+        let error = error_response.error;
+        let status = error.code.and_then(|code| reqwest::StatusCode::from_u16(code).ok()).unwrap_or(status);
+        classify_http_error(status, error.status.as_deref(), None, error.message)
+    } else {
+        generic_api_error(status, response_text)
+    }
+}
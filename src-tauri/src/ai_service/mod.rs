@@ -0,0 +1,598 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+pub mod cancel;
+pub mod provider;
+mod sse;
+mod openai;
+mod anthropic;
+mod gemini;
+mod ollama;
+
+pub use cancel::CancelRegistry;
+pub use provider::{AIProvider, ProviderConfig, ProviderKind, create_provider};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIRequest {
+    pub model: String,
+    pub prompt: String,
+    pub image_data: Option<String>, // base64 encoded image
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    /// 之前的对话轮次，按时间顺序排列，不含本次 prompt
+    #[serde(default)]
+    pub history: Vec<ChatMessage>,
+}
+
+/// 对话历史中的一条纯文本消息，role 为 user 或 assistant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn is_assistant(&self) -> bool {
+        self.role == "assistant"
+    }
+}
+
+/// 整理对话历史：丢弃开头的 assistant 消息并合并相邻的同角色消息，
+/// 部分供应商要求 user/assistant 严格交替且以 user 开头
+pub fn normalize_history(history: &[ChatMessage]) -> Vec<ChatMessage> {
+    let mut normalized: Vec<ChatMessage> = Vec::new();
+    for message in history.iter().skip_while(|m| m.is_assistant()) {
+        if message.content.trim().is_empty() {
+            continue;
+        }
+        match normalized.last_mut() {
+            Some(last) if last.is_assistant() == message.is_assistant() => {
+                last.content.push_str("\n\n");
+                last.content.push_str(&message.content);
+            }
+            _ => normalized.push(ChatMessage {
+                role: if message.is_assistant() { "assistant" } else { "user" }.to_string(),
+                content: message.content.clone(),
+            }),
+        }
+    }
+    // 本次 prompt 以 user 身份发送，历史需以 assistant 结尾才能保持交替
+    if normalized.last().is_some_and(|m| !m.is_assistant()) {
+        normalized.pop();
+    }
+    normalized
+}
+
+/// 单次调用的 token 用量，cached/image 为 input 中的明细部分
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cached_input_tokens: u32,
+    pub image_input_tokens: u32,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u32 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// 将一次调用的用量平均分给 parts 个结果，余数计入第一个
+    pub fn split(self, parts: u32) -> Vec<TokenUsage> {
+        let parts = parts.max(1);
+        let share = |value: u32, index: u32| value / parts + if index == 0 { value % parts } else { 0 };
+        (0..parts)
+            .map(|index| TokenUsage {
+                input_tokens: share(self.input_tokens, index),
+                output_tokens: share(self.output_tokens, index),
+                cached_input_tokens: share(self.cached_input_tokens, index),
+                image_input_tokens: share(self.image_input_tokens, index),
+            })
+            .collect()
+    }
+}
+
+impl std::ops::Add for TokenUsage {
+    type Output = TokenUsage;
+
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+            cached_input_tokens: self.cached_input_tokens + other.cached_input_tokens,
+            image_input_tokens: self.image_input_tokens + other.image_input_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIResponse {
+    pub content: String,
+    pub usage: TokenUsage,
+    pub model: String,
+    pub finish_reason: String,
+}
+
+/// 流式输出的增量文本回调
+pub type DeltaCallback<'a> = dyn Fn(&str) + Send + Sync + 'a;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIImageRequest {
+    pub model: String,
+    pub prompt: String,
+    pub image_data: String, // data URL 或 base64 编码的原图
+    pub size: Option<String>,
+    /// 期望返回的图片数量，供应商可能返回更少
+    pub n: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIImageResponse {
+    pub images: Vec<String>, // data URL 格式的结果图
+    pub usage: TokenUsage,
+    pub model: String,
+    pub revised_prompt: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIError {
+    /// 统一的错误类型，HTTP 错误的归类见 provider::classify_http_error
+    pub error_type: String,
+    pub message: String,
+    /// 供应商原始的错误码或错误类型，没有时为 HTTP 状态码
+    pub code: Option<String>,
+    /// 服务端通过 Retry-After 要求的最短等待时间（毫秒）
+    #[serde(default)]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl AIError {
+    pub fn new(error_type: &str, message: impl Into<String>) -> Self {
+        Self {
+            error_type: error_type.to_string(),
+            message: message.into(),
+            code: None,
+            retry_after_ms: None,
+        }
+    }
+
+    /// 是否值得重试，鉴权、额度、参数类错误重试也不会成功
+    pub fn is_retryable(&self) -> bool {
+        !should_not_retry(self)
+    }
+
+    pub fn cancelled() -> Self {
+        Self::new("cancelled", "请求已取消")
+    }
+}
+
+#[derive(Clone)]
+pub struct AIService {
+    provider: Arc<dyn AIProvider>,
+    cancel_token: CancellationToken,
+    retry_config: RetryConfig,
+}
+
+/// 单次调用内最多按 Retry-After 等待的时间（毫秒），要求更久时直接返回错误，由任务队列稍后重试
+const MAX_INLINE_RETRY_AFTER_MS: u64 = 60_000;
+
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub backoff_factor: f64,
+    /// 随机抖动占退避时间的比例，避免多个请求同时重试
+    pub jitter: f64,
+    /// 单次请求（含读取响应）的超时时间（毫秒），图片编辑接口通常需要一分钟以上
+    pub request_timeout_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 1000,
+            max_delay_ms: 10000,
+            backoff_factor: 2.0,
+            jitter: 0.2,
+            request_timeout_ms: 180_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// 第 attempt 次重试前的等待时间（毫秒），按指数退避并限制上限
+    pub fn delay_ms(&self, attempt: u32) -> u64 {
+        let delay = (self.base_delay_ms as f64) * self.backoff_factor.powi(attempt as i32);
+        (delay as u64).min(self.max_delay_ms)
+    }
+
+    /// 实际等待时间：退避时间随机减少至多 jitter 比例，且不短于服务端要求的 Retry-After
+    pub fn retry_delay_ms(&self, attempt: u32, retry_after_ms: Option<u64>) -> u64 {
+        let delay = self.delay_ms(attempt) as f64;
+        let jitter = delay * self.jitter.clamp(0.0, 1.0) * rand::random::<f64>();
+        ((delay - jitter) as u64).max(retry_after_ms.unwrap_or_default())
+    }
+}
+
+impl AIService {
+    pub fn new(provider: Arc<dyn AIProvider>) -> Self {
+        Self {
+            provider,
+            cancel_token: CancellationToken::new(),
+            retry_config: RetryConfig::default(),
+        }
+    }
+
+    /// 绑定取消令牌，取消时中断进行中的请求和重试等待
+    pub fn with_cancel_token(mut self, cancel_token: CancellationToken) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    /// 替换默认的重试策略
+    pub fn with_retry_config(mut self, retry_config: RetryConfig) -> Self {
+        self.retry_config = retry_config;
+        self
+    }
+
+    pub async fn call_ai(&self, request: AIRequest) -> Result<AIResponse, AIError> {
+        self.call_ai_with_retry(request, self.retry_config.clone()).await
+    }
+
+    pub async fn call_ai_with_retry(
+        &self,
+        request: AIRequest,
+        retry_config: RetryConfig,
+    ) -> Result<AIResponse, AIError> {
+        with_retry(&retry_config, &self.cancel_token, || self.provider.chat(request.clone())).await
+    }
+
+    /// 流式调用。数据流开始后出错不再重试，避免重复推送已发送的增量；
+    /// 已推送过增量后超时按 stream_error 返回
+    pub async fn call_ai_stream(
+        &self,
+        request: AIRequest,
+        on_delta: &DeltaCallback<'_>,
+    ) -> Result<AIResponse, AIError> {
+        let streamed = AtomicBool::new(false);
+        let forward = |delta: &str| {
+            streamed.store(true, Ordering::Relaxed);
+            on_delta(delta);
+        };
+        let on_timeout = || {
+            if streamed.load(Ordering::Relaxed) {
+                AIError::new("stream_error", "数据流读取超时，已输出的内容不完整")
+            } else {
+                timeout_error(&self.retry_config)
+            }
+        };
+        with_retry_on_timeout(&self.retry_config, &self.cancel_token, on_timeout, || {
+            self.provider.chat_stream(request.clone(), &forward)
+        })
+        .await
+    }
+
+    /// 单次请求最多能返回的图片数量
+    pub fn max_images_per_request(&self) -> u32 {
+        self.provider.max_images_per_request().max(1)
+    }
+
+    pub async fn edit_image(&self, request: AIImageRequest) -> Result<AIImageResponse, AIError> {
+        with_retry(&self.retry_config, &self.cancel_token, || {
+            self.provider.edit_image(request.clone())
+        })
+        .await
+    }
+}
+
+// 带重试地执行一次 AI 调用，取消时丢弃进行中的请求 future
+async fn with_retry<T, F, Fut>(
+    retry_config: &RetryConfig,
+    cancel_token: &CancellationToken,
+    call: F,
+) -> Result<T, AIError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, AIError>>,
+{
+    with_retry_on_timeout(retry_config, cancel_token, || timeout_error(retry_config), call).await
+}
+
+// 同 with_retry，单次请求超时时返回 on_timeout 给出的错误，由错误类型决定是否重试
+async fn with_retry_on_timeout<T, F, Fut, E>(
+    retry_config: &RetryConfig,
+    cancel_token: &CancellationToken,
+    on_timeout: E,
+    mut call: F,
+) -> Result<T, AIError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, AIError>>,
+    E: Fn() -> AIError,
+{
+    let mut last_error = None;
+
+    for attempt in 0..=retry_config.max_retries {
+        let timeout = tokio::time::Duration::from_millis(retry_config.request_timeout_ms);
+        let result = tokio::select! {
+            _ = cancel_token.cancelled() => return Err(AIError::cancelled()),
+            result = tokio::time::timeout(timeout, call()) => result.unwrap_or_else(|_| Err(on_timeout())),
+        };
+
+        match result {
+            Ok(response) => return Ok(response),
+            Err(error) => {
+                last_error = Some(error.clone());
+
+                // 如果是最后一次尝试，直接返回错误
+                if attempt == retry_config.max_retries {
+                    break;
+                }
+
+                // 对于某些错误类型，不进行重试
+                if should_not_retry(&error) {
+                    break;
+                }
+
+                // 服务端要求等待太久时不在本次调用内等待
+                if error.retry_after_ms.is_some_and(|ms| ms > MAX_INLINE_RETRY_AFTER_MS) {
+                    break;
+                }
+
+                // 计算延迟时间
+                let delay_ms = retry_config.retry_delay_ms(attempt, error.retry_after_ms);
+
+                log::warn!("AI API 调用失败，{}ms 后重试 ({}/{}): {}",
+                           delay_ms, attempt + 1, retry_config.max_retries, error.message);
+
+                tokio::select! {
+                    _ = cancel_token.cancelled() => return Err(AIError::cancelled()),
+                    _ = tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms)) => {}
+                }
+            }
+        }
+    }
+
+    Err(last_error.unwrap())
+}
+
+fn timeout_error(retry_config: &RetryConfig) -> AIError {
+    AIError::new("timeout_error", format!("请求超时（{} 秒）", retry_config.request_timeout_ms / 1000))
+}
+
+// 图片处理相关的辅助函数
+/// 将 data URL（或裸 base64）拆分为 MIME 类型和 base64 数据，裸 base64 按文件头识别，无法识别时按 JPEG 处理
+pub fn split_image_data(image_data: &str) -> Result<(String, &str), String> {
+    match image_data.strip_prefix("data:") {
+        Some(rest) => {
+            let comma_pos = rest.find(',').ok_or_else(|| "无效的图片数据格式".to_string())?;
+            let mime_type = rest[..comma_pos].split(';').next().unwrap_or_default();
+            let mime_type = if mime_type.starts_with("image/") { mime_type } else { "image/jpeg" };
+            Ok((mime_type.to_string(), rest[comma_pos + 1..].trim()))
+        }
+        None => {
+            let base64_data = image_data.trim();
+            // 只解码开头一段用于识别文件头，长度取 4 的倍数
+            let prefix = &base64_data[..base64_data.len().min(32) / 4 * 4];
+            let mime_type = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, prefix)
+                .ok()
+                .and_then(|bytes| crate::image_ops::preprocess::sniff_mime_type(&bytes))
+                .unwrap_or("image/jpeg");
+            Ok((mime_type.to_string(), base64_data))
+        }
+    }
+}
+
+/// 将 data URL（或裸 base64）解码为 MIME 类型和图片字节
+pub fn decode_image_data(image_data: &str) -> Result<(String, Vec<u8>), String> {
+    let (mime_type, base64_data) = split_image_data(image_data)?;
+
+    let bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, base64_data)
+        .map_err(|e| format!("图片 base64 解码失败: {}", e))?;
+
+    Ok((mime_type, bytes))
+}
+
+pub fn create_image_processing_prompt(user_prompt: &str, style: Option<&str>) -> String {
+    let base_prompt = "请根据用户的要求处理这张图片。";
+
+    let style_prompt = if let Some(style) = style {
+        format!("应用 {} 风格。", style)
+    } else {
+        String::new()
+    };
+
+    format!("{} {} 用户要求: {}", base_prompt, style_prompt, user_prompt)
+}
+
+pub fn create_image_edit_prompt(user_prompt: &str, style: Option<&str>) -> String {
+    match style {
+        Some(style) => format!("{}\n\nApply the following style: {}", user_prompt, style),
+        None => user_prompt.to_string(),
+    }
+}
+
+// 判断是否应该重试的辅助函数，限流、超时、服务端错误和网络错误都会重试
+fn should_not_retry(error: &AIError) -> bool {
+    matches!(error.error_type.as_str(),
+        "auth_error" | "quota_error" | "invalid_request_error" | "not_found_error" | "image_error" | "parse_error"
+            | "unsupported_error" | "stream_error" | "cancelled"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_image_data() {
+        let (mime_type, bytes) = decode_image_data("data:image/webp;base64,UklGRg==").unwrap();
+        assert_eq!(mime_type, "image/webp");
+        assert_eq!(bytes, b"RIFF");
+
+        let (mime_type, _) = decode_image_data("UklGRg==").unwrap();
+        assert_eq!(mime_type, "image/jpeg");
+        let (mime_type, _) = decode_image_data("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJ").unwrap();
+        assert_eq!(mime_type, "image/png");
+        assert!(decode_image_data("data:image/png;base64").is_err());
+    }
+
+    #[test]
+    fn test_create_image_processing_prompt() {
+        let prompt = create_image_processing_prompt("让图片更亮一些", Some("复古"));
+        assert!(prompt.contains("复古"));
+        assert!(prompt.contains("让图片更亮一些"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_interrupts_retry_delay() {
+        let cancel_token = CancellationToken::new();
+        let retry_config = RetryConfig {
+            base_delay_ms: 60_000,
+            ..RetryConfig::default()
+        };
+
+        let canceller = cancel_token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+
+        let result: Result<(), AIError> = with_retry(&retry_config, &cancel_token, || async {
+            Err(AIError::new("network_error", "connection reset"))
+        })
+        .await;
+
+        assert_eq!(result.unwrap_err().error_type, "cancelled");
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let retry_config = RetryConfig::default();
+        for attempt in 0..4 {
+            let delay = retry_config.delay_ms(attempt);
+            let jittered = retry_config.retry_delay_ms(attempt, None);
+            assert!(jittered <= delay && jittered >= delay * 4 / 5, "{} {}", delay, jittered);
+        }
+        assert_eq!(retry_config.delay_ms(10), 10000);
+        assert_eq!(retry_config.retry_delay_ms(0, Some(30_000)), 30_000);
+
+        let error = |error_type: &str| AIError::new(error_type, "upstream");
+        assert!(!error("auth_error").is_retryable());
+        assert!(!error("quota_error").is_retryable());
+        assert!(!error("invalid_request_error").is_retryable());
+        assert!(error("rate_limit_error").is_retryable());
+        assert!(error("timeout_error").is_retryable());
+        assert!(error("server_error").is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let retry_config = RetryConfig {
+            max_retries: 0,
+            request_timeout_ms: 10,
+            ..RetryConfig::default()
+        };
+
+        let result: Result<(), AIError> = with_retry(&retry_config, &CancellationToken::new(), || async {
+            tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+            Ok(())
+        })
+        .await;
+
+        assert_eq!(result.unwrap_err().error_type, "timeout_error");
+    }
+
+    // 推送一段增量后不再返回的流式供应商
+    struct StalledStreamProvider {
+        calls: std::sync::atomic::AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl AIProvider for StalledStreamProvider {
+        fn kind(&self) -> ProviderKind {
+            ProviderKind::OpenAI
+        }
+
+        async fn chat(&self, _request: AIRequest) -> Result<AIResponse, AIError> {
+            Err(AIError::new("unsupported_error", "only streaming"))
+        }
+
+        async fn chat_stream(&self, _request: AIRequest, on_delta: &DeltaCallback<'_>) -> Result<AIResponse, AIError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            on_delta("partial");
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_timeout_after_delta() {
+        let provider = Arc::new(StalledStreamProvider { calls: Default::default() });
+        let service = AIService::new(provider.clone()).with_retry_config(RetryConfig {
+            max_retries: 2,
+            request_timeout_ms: 10,
+            ..RetryConfig::default()
+        });
+        let request = AIRequest {
+            model: "gpt-4o".to_string(),
+            prompt: "hello".to_string(),
+            image_data: None,
+            max_tokens: None,
+            temperature: None,
+            history: Vec::new(),
+        };
+
+        // 已推送的增量不能重复推送，超时后不再重试
+        let result = service.call_ai_stream(request, &|_: &str| {}).await;
+        assert_eq!(result.unwrap_err().error_type, "stream_error");
+        assert_eq!(provider.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_normalize_history() {
+        let message = |role: &str, content: &str| ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        };
+        let history = vec![
+            message("assistant", "欢迎"),
+            message("user", "更亮一些"),
+            message("user", "再暖一点"),
+            message("assistant", "已调整"),
+            message("user", "被取消的请求"),
+        ];
+
+        assert_eq!(
+            normalize_history(&history),
+            vec![message("user", "更亮一些\n\n再暖一点"), message("assistant", "已调整")]
+        );
+    }
+
+    #[test]
+    fn test_token_usage_split() {
+        let usage = TokenUsage {
+            input_tokens: 10,
+            output_tokens: 7,
+            cached_input_tokens: 0,
+            image_input_tokens: 3,
+        };
+        let parts = usage.split(3);
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].output_tokens, 3);
+        assert_eq!(parts[1].output_tokens, 2);
+        assert_eq!(parts.into_iter().fold(TokenUsage::default(), |sum, part| sum + part), usage);
+    }
+
+    #[test]
+    fn test_provider_kind_parse() {
+        assert_eq!("openai".parse::<ProviderKind>().unwrap(), ProviderKind::OpenAI);
+        assert_eq!("Gemini".parse::<ProviderKind>().unwrap(), ProviderKind::Gemini);
+        assert!("unknown".parse::<ProviderKind>().is_err());
+        assert_eq!(ProviderKind::Anthropic.as_str(), "anthropic");
+    }
+}
@@ -0,0 +1,138 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::provider::{classify_http_error, generic_api_error, parse_response, send_request, AIProvider, ProviderConfig, ProviderKind};
+use super::{normalize_history, split_image_data, AIError, AIRequest, AIResponse, TokenUsage};
+
+// Ollama /api/chat 请求结构
+#[derive(Debug, Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+// Ollama /api/chat 响应结构
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    message: OllamaResponseMessage,
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: u32,
+    #[serde(default)]
+    eval_count: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponseMessage {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaErrorResponse {
+    error: String,
+}
+
+/// 本地 Ollama 接口，默认无需鉴权，图片以不带前缀的 base64 数组传递
+pub struct OllamaProvider {
+    client: reqwest::Client,
+    config: ProviderConfig,
+}
+
+impl OllamaProvider {
+    pub fn new(client: reqwest::Client, config: ProviderConfig) -> Self {
+        Self { client, config }
+    }
+}
+
+#[async_trait]
+impl AIProvider for OllamaProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Ollama
+    }
+
+    async fn chat(&self, request: AIRequest) -> Result<AIResponse, AIError> {
+        let mut images = Vec::new();
+        if let Some(image_data) = &request.image_data {
+            let (_, data) = split_image_data(image_data)
+                .map_err(|e| AIError::new("image_error", e))?;
+            images.push(data.to_string());
+        }
+
+        let mut messages: Vec<OllamaMessage> = normalize_history(&request.history)
+            .into_iter()
+            .map(|message| OllamaMessage {
+                role: message.role,
+                content: message.content,
+                images: Vec::new(),
+            })
+            .collect();
+        messages.push(OllamaMessage {
+            role: "user".to_string(),
+            content: request.prompt,
+            images,
+        });
+
+        let ollama_request = OllamaRequest {
+            model: request.model.clone(),
+            messages,
+            stream: false,
+            options: OllamaOptions {
+                temperature: request.temperature,
+                num_predict: request.max_tokens,
+            },
+        };
+
+        let url = format!("{}/api/chat", self.config.base_url());
+
+        let mut builder = self.client.post(&url).json(&ollama_request);
+        // 通过反向代理访问时可能需要鉴权
+        if !self.config.api_key.is_empty() {
+            builder = builder.header("Authorization", format!("Bearer {}", self.config.api_key));
+        }
+
+        let response_text = send_request(builder, parse_ollama_error).await?;
+
+        let ollama_response: OllamaResponse = parse_response(&response_text)?;
+
+        if ollama_response.message.content.is_empty() {
+            return Err(AIError::new("empty_response", "AI 返回了空响应"));
+        }
+
+        Ok(AIResponse {
+            content: ollama_response.message.content,
+            usage: TokenUsage {
+                input_tokens: ollama_response.prompt_eval_count,
+                output_tokens: ollama_response.eval_count,
+                ..Default::default()
+            },
+            model: request.model,
+            finish_reason: ollama_response.done_reason.unwrap_or_default(),
+        })
+    }
+}
+
+// Ollama 的错误响应只有一条错误信息，按状态码归类
+fn parse_ollama_error(status: reqwest::StatusCode, response_text: &str) -> AIError {
+    match serde_json::from_str::<OllamaErrorResponse>(response_text) {
+        Ok(error_response) => classify_http_error(status, None, None, error_response.error),
+        Err(_) => generic_api_error(status, response_text),
+    }
+}
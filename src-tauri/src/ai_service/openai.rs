@@ -0,0 +1,424 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::provider::{
+    check_status, classify_http_error, generic_api_error, parse_response, request_error, send_request, AIProvider,
+    ProviderConfig, ProviderKind,
+};
+use super::sse::SseDecoder;
+use super::{decode_image_data, normalize_history, split_image_data, AIError, DeltaCallback, TokenUsage, AIImageRequest, AIImageResponse, AIRequest, AIResponse};
+
+// OpenAI API 请求结构
+#[derive(Debug, Serialize)]
+struct OpenAIMessage {
+    role: String,
+    content: Vec<OpenAIContent>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+enum OpenAIContent {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "image_url")]
+    ImageUrl { image_url: OpenAIImageUrl },
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIImageUrl {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIRequest {
+    model: String,
+    messages: Vec<OpenAIMessage>,
+    max_tokens: Option<u32>,
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAIStreamOptions>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIStreamOptions {
+    include_usage: bool,
+}
+
+// OpenAI API 响应结构
+#[derive(Debug, Deserialize)]
+struct OpenAIResponse {
+    choices: Vec<OpenAIChoice>,
+    usage: OpenAIUsage,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIChoice {
+    message: OpenAIResponseMessage,
+    finish_reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIResponseMessage {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+    prompt_tokens_details: Option<OpenAITokenDetails>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenAITokenDetails {
+    #[serde(default)]
+    cached_tokens: u32,
+    #[serde(default)]
+    image_tokens: u32,
+}
+
+impl From<OpenAIUsage> for TokenUsage {
+    fn from(usage: OpenAIUsage) -> Self {
+        let details = usage.prompt_tokens_details.unwrap_or_default();
+        TokenUsage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+            cached_input_tokens: details.cached_tokens,
+            image_input_tokens: details.image_tokens,
+        }
+    }
+}
+
+// OpenAI 流式响应结构，最后一个分片的 choices 为空并携带 usage
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAIStreamChoice>,
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChoice {
+    #[serde(default)]
+    delta: OpenAIStreamDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenAIStreamDelta {
+    content: Option<String>,
+}
+
+// OpenAI 图片编辑响应结构
+#[derive(Debug, Deserialize)]
+struct OpenAIImageResponse {
+    data: Vec<OpenAIImageData>,
+    usage: Option<OpenAIImageUsage>,
+}
+
+// 图片接口的 usage 字段命名与对话接口不同
+#[derive(Debug, Deserialize)]
+struct OpenAIImageUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+    input_tokens_details: Option<OpenAITokenDetails>,
+}
+
+impl From<OpenAIImageUsage> for TokenUsage {
+    fn from(usage: OpenAIImageUsage) -> Self {
+        let details = usage.input_tokens_details.unwrap_or_default();
+        TokenUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cached_input_tokens: details.cached_tokens,
+            image_input_tokens: details.image_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIImageData {
+    b64_json: Option<String>,
+    url: Option<String>,
+    revised_prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIErrorResponse {
+    error: OpenAIErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIErrorDetail {
+    message: String,
+    #[serde(rename = "type")]
+    error_type: String,
+    code: Option<String>,
+}
+
+/// OpenAI 及兼容接口（chat/completions、images/edits），使用 Bearer 鉴权
+pub struct OpenAIProvider {
+    client: reqwest::Client,
+    config: ProviderConfig,
+}
+
+impl OpenAIProvider {
+    pub fn new(client: reqwest::Client, config: ProviderConfig) -> Self {
+        Self { client, config }
+    }
+
+    async fn download_image(&self, url: &str) -> Result<String, AIError> {
+        let response = self.client.get(url).send().await
+            .map_err(|e| request_error(e, "下载结果图片失败"))?;
+
+        let mime_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| v.starts_with("image/"))
+            .unwrap_or("image/png")
+            .to_string();
+
+        let bytes = response.bytes().await
+            .map_err(|e| request_error(e, "读取结果图片失败"))?;
+
+        Ok(format!("data:{};base64,{}", mime_type,
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &bytes)))
+    }
+}
+
+#[async_trait]
+impl AIProvider for OpenAIProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::OpenAI
+    }
+
+    // images/edits 接口的 n 参数上限
+    fn max_images_per_request(&self) -> u32 {
+        10
+    }
+
+    async fn chat(&self, request: AIRequest) -> Result<AIResponse, AIError> {
+        let openai_request = build_chat_request(&request, false);
+
+        let url = format!("{}/chat/completions", self.config.base_url());
+
+        let response_text = send_request(
+            self.client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", self.config.api_key))
+                .header("Content-Type", "application/json")
+                .json(&openai_request),
+            parse_openai_error,
+        ).await?;
+
+        let openai_response: OpenAIResponse = parse_response(&response_text)?;
+
+        let Some(choice) = openai_response.choices.into_iter().next() else {
+            return Err(AIError::new("empty_response", "AI 返回了空响应"));
+        };
+
+        Ok(AIResponse {
+            content: choice.message.content,
+            usage: openai_response.usage.into(),
+            model: request.model,
+            finish_reason: choice.finish_reason,
+        })
+    }
+
+    async fn chat_stream(
+        &self,
+        request: AIRequest,
+        on_delta: &DeltaCallback<'_>,
+    ) -> Result<AIResponse, AIError> {
+        let openai_request = build_chat_request(&request, true);
+        let url = format!("{}/chat/completions", self.config.base_url());
+
+        let response = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .header("Content-Type", "application/json")
+            .header("Accept", "text/event-stream")
+            .json(&openai_request)
+            .send()
+            .await
+            .map_err(|e| request_error(e, "网络请求失败"))?;
+        let mut response = check_status(response, parse_openai_error).await?;
+
+        let mut decoder = SseDecoder::new();
+        let mut stream = StreamState::default();
+
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| AIError::new("stream_error", format!("读取流式响应失败: {}", e)))?
+        {
+            for data in decoder.feed(&chunk) {
+                stream.apply(&data, on_delta)?;
+            }
+        }
+        if let Some(data) = decoder.finish() {
+            stream.apply(&data, on_delta)?;
+        }
+
+        if stream.content.is_empty() {
+            return Err(AIError::new("empty_response", "AI 返回了空响应"));
+        }
+
+        Ok(AIResponse {
+            content: stream.content,
+            usage: stream.usage,
+            model: request.model,
+            finish_reason: stream.finish_reason,
+        })
+    }
+
+    async fn edit_image(&self, request: AIImageRequest) -> Result<AIImageResponse, AIError> {
+        let (mime_type, image_bytes) = decode_image_data(&request.image_data)
+            .map_err(|e| AIError::new("image_error", e))?;
+
+        let extension = mime_type.trim_start_matches("image/").to_string();
+        let image_part = reqwest::multipart::Part::bytes(image_bytes)
+            .file_name(format!("image.{}", extension))
+            .mime_str(&mime_type)
+            .map_err(|e| AIError::new("image_error", format!("不支持的图片格式 {}: {}", mime_type, e)))?;
+
+        let mut form = reqwest::multipart::Form::new()
+            .text("model", request.model.clone())
+            .text("prompt", request.prompt)
+            .part("image", image_part);
+
+        if let Some(size) = request.size {
+            form = form.text("size", size);
+        }
+        if request.n > 1 {
+            form = form.text("n", request.n.to_string());
+        }
+
+        let url = format!("{}/images/edits", self.config.base_url());
+
+        let response_text = send_request(
+            self.client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", self.config.api_key))
+                .multipart(form),
+            parse_openai_error,
+        ).await?;
+
+        let image_response: OpenAIImageResponse = parse_response(&response_text)?;
+
+        if image_response.data.is_empty() {
+            return Err(AIError::new("empty_response", "AI 没有返回图片"));
+        }
+
+        let revised_prompt = image_response.data[0].revised_prompt.clone();
+        let mut images = Vec::with_capacity(image_response.data.len());
+        for image in image_response.data {
+            // gpt-image-1 直接返回 base64，dall-e 系列默认返回临时下载地址
+            let image_data = if let Some(b64_json) = image.b64_json {
+                format!("data:image/png;base64,{}", b64_json)
+            } else if let Some(url) = image.url {
+                self.download_image(&url).await?
+            } else {
+                return Err(AIError::new("empty_response", "AI 返回的图片数据为空"));
+            };
+            images.push(image_data);
+        }
+
+        Ok(AIImageResponse {
+            images,
+            usage: image_response.usage.map(TokenUsage::from).unwrap_or_default(),
+            model: request.model,
+            revised_prompt,
+        })
+    }
+}
+
+fn build_chat_request(request: &AIRequest, stream: bool) -> OpenAIRequest {
+    let mut content = vec![OpenAIContent::Text {
+        text: request.prompt.clone(),
+    }];
+
+    // 如果有图片数据，添加到内容中
+    if let Some(image_data) = &request.image_data {
+        // 裸 base64 按文件头识别真实类型后再包装为 data URL
+        let image_url = match split_image_data(image_data) {
+            Ok((mime_type, data)) if !image_data.starts_with("data:") => format!("data:{};base64,{}", mime_type, data),
+            _ => image_data.clone(),
+        };
+
+        content.push(OpenAIContent::ImageUrl {
+            image_url: OpenAIImageUrl { url: image_url },
+        });
+    }
+
+    let mut messages: Vec<OpenAIMessage> = normalize_history(&request.history)
+        .into_iter()
+        .map(|message| OpenAIMessage {
+            role: message.role,
+            content: vec![OpenAIContent::Text { text: message.content }],
+        })
+        .collect();
+    messages.push(OpenAIMessage {
+        role: "user".to_string(),
+        content,
+    });
+
+    OpenAIRequest {
+        model: request.model.clone(),
+        messages,
+        max_tokens: request.max_tokens,
+        temperature: request.temperature,
+        stream,
+        // 要求在最后一个分片中返回用量
+        stream_options: stream.then_some(OpenAIStreamOptions { include_usage: true }),
+    }
+}
+
+// 流式响应的累积状态
+#[derive(Debug, Default)]
+struct StreamState {
+    content: String,
+    finish_reason: String,
+    usage: TokenUsage,
+}
+
+impl StreamState {
+    fn apply(&mut self, data: &str, on_delta: &DeltaCallback<'_>) -> Result<(), AIError> {
+        if data == "[DONE]" {
+            return Ok(());
+        }
+
+        let chunk: OpenAIStreamChunk = parse_response(data)?;
+        for choice in chunk.choices {
+            if let Some(delta) = choice.delta.content.filter(|d| !d.is_empty()) {
+                on_delta(&delta);
+                self.content.push_str(&delta);
+            }
+            if let Some(finish_reason) = choice.finish_reason {
+                self.finish_reason = finish_reason;
+            }
+        }
+        if let Some(usage) = chunk.usage {
+            self.usage = usage.into();
+        }
+        Ok(())
+    }
+}
+
+// 解析 OpenAI 兼容接口的错误响应
+fn parse_openai_error(status: reqwest::StatusCode, response_text: &str) -> AIError {
+    if let Ok(error_response) = serde_json::from_str::<OpenAIErrorResponse>(response_text) {
+        let error = error_response.error;
+        classify_http_error(status, Some(&error.error_type), error.code.as_deref(), error.message)
+    } else {
+        generic_api_error(status, response_text)
+    }
+}
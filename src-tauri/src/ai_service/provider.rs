@@ -0,0 +1,324 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{AIError, AIImageRequest, AIImageResponse, AIRequest, AIResponse, DeltaCallback};
+use super::anthropic::AnthropicProvider;
+use super::gemini::GeminiProvider;
+use super::ollama::OllamaProvider;
+use super::openai::OpenAIProvider;
+
+/// AI 供应商类型，与 setting.provider 中保存的值一一对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    OpenAI,
+    Anthropic,
+    Gemini,
+    Ollama,
+}
+
+impl ProviderKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAI => "openai",
+            ProviderKind::Anthropic => "anthropic",
+            ProviderKind::Gemini => "gemini",
+            ProviderKind::Ollama => "ollama",
+        }
+    }
+
+    /// 未配置接口地址时使用的默认地址
+    pub fn default_api_url(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAI => "https://api.openai.com/v1",
+            ProviderKind::Anthropic => "https://api.anthropic.com/v1",
+            ProviderKind::Gemini => "https://generativelanguage.googleapis.com/v1beta",
+            ProviderKind::Ollama => "http://localhost:11434",
+        }
+    }
+
+    /// 是否实现了图片编辑接口，Anthropic 和 Ollama 只能输出文字
+    pub fn supports_image_edit(&self) -> bool {
+        matches!(self, ProviderKind::OpenAI | ProviderKind::Gemini)
+    }
+
+    /// 本地部署的 Ollama 默认不需要 API 密钥
+    pub fn requires_api_key(&self) -> bool {
+        !matches!(self, ProviderKind::Ollama)
+    }
+
+    /// 供应商接受的输入图片格式
+    pub fn accepted_image_types(&self) -> &'static [&'static str] {
+        match self {
+            ProviderKind::OpenAI | ProviderKind::Gemini => &["image/png", "image/jpeg", "image/webp"],
+            ProviderKind::Anthropic => &["image/jpeg", "image/png", "image/gif", "image/webp"],
+            ProviderKind::Ollama => &["image/png", "image/jpeg"],
+        }
+    }
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "openai" => Ok(ProviderKind::OpenAI),
+            "anthropic" => Ok(ProviderKind::Anthropic),
+            "gemini" => Ok(ProviderKind::Gemini),
+            "ollama" => Ok(ProviderKind::Ollama),
+            other => Err(format!("不支持的AI供应商: {}", other)),
+        }
+    }
+}
+
+/// 创建供应商实例所需的连接配置
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    pub api_url: String,
+    pub api_key: String,
+}
+
+impl ProviderConfig {
+    pub fn new(provider: &str, api_url: String, api_key: String) -> Result<Self, String> {
+        Ok(Self {
+            kind: provider.parse()?,
+            api_url,
+            api_key,
+        })
+    }
+
+    /// 去掉末尾斜杠的接口地址，为空时回退到供应商默认地址
+    pub fn base_url(&self) -> String {
+        let api_url = self.api_url.trim();
+        if api_url.is_empty() {
+            self.kind.default_api_url().to_string()
+        } else {
+            api_url.trim_end_matches('/').to_string()
+        }
+    }
+}
+
+/// AI 供应商接口，每个实现负责各自的请求格式、鉴权方式和图片编码
+#[async_trait]
+pub trait AIProvider: Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    /// 发送一次（可带图片的）对话请求
+    async fn chat(&self, request: AIRequest) -> Result<AIResponse, AIError>;
+
+    /// 流式对话，每收到一段文本调用一次 on_delta；不支持流式的供应商一次性回调完整内容
+    async fn chat_stream(
+        &self,
+        request: AIRequest,
+        on_delta: &DeltaCallback<'_>,
+    ) -> Result<AIResponse, AIError> {
+        let response = self.chat(request).await?;
+        on_delta(&response.content);
+        Ok(response)
+    }
+
+    /// 单次图片编辑请求最多能返回的图片数量，不支持原生多图的供应商返回 1
+    fn max_images_per_request(&self) -> u32 {
+        1
+    }
+
+    /// 根据提示词编辑图片，不支持图片输出的供应商保持默认实现
+    async fn edit_image(&self, _request: AIImageRequest) -> Result<AIImageResponse, AIError> {
+        Err(AIError::new(
+            "unsupported_error",
+            format!("{} 不支持图片编辑", self.kind()),
+        ))
+    }
+}
+
+/// 建立连接的超时时间，整个请求的超时由 RetryConfig::request_timeout_ms 控制
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+pub fn create_provider(config: ProviderConfig) -> Arc<dyn AIProvider> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_default();
+    match config.kind {
+        ProviderKind::OpenAI => Arc::new(OpenAIProvider::new(client, config)),
+        ProviderKind::Anthropic => Arc::new(AnthropicProvider::new(client, config)),
+        ProviderKind::Gemini => Arc::new(GeminiProvider::new(client, config)),
+        ProviderKind::Ollama => Arc::new(OllamaProvider::new(client, config)),
+    }
+}
+
+/// 将供应商的错误响应体解析为 AIError
+pub(super) type ErrorParser = fn(StatusCode, &str) -> AIError;
+
+/// 发送请求并读取完整响应体，非 2xx 响应交给 parse_error 解析
+pub(super) async fn send_request(
+    request: reqwest::RequestBuilder,
+    parse_error: ErrorParser,
+) -> Result<String, AIError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|e| request_error(e, "网络请求失败"))?;
+    let response = check_status(response, parse_error).await?;
+
+    response
+        .text()
+        .await
+        .map_err(|e| request_error(e, "读取响应失败"))
+}
+
+/// 非 2xx 响应时读取响应体并转为错误，附带 Retry-After 指定的等待时间
+pub(super) async fn check_status(
+    response: reqwest::Response,
+    parse_error: ErrorParser,
+) -> Result<reqwest::Response, AIError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after_ms = parse_retry_after(response.headers(), Utc::now());
+    let response_text = response.text().await.unwrap_or_default();
+    let mut error = parse_error(status, &response_text);
+    error.retry_after_ms = retry_after_ms;
+    Err(error)
+}
+
+// 请求超时单独归类，其余发送或读取失败都视为网络错误
+pub(super) fn request_error(error: reqwest::Error, context: &str) -> AIError {
+    if error.is_timeout() {
+        AIError::new("timeout_error", format!("{}: 请求超时", context))
+    } else {
+        AIError::new("network_error", format!("{}: {}", context, error))
+    }
+}
+
+/// 解析 retry-after-ms 或 Retry-After（秒数或 HTTP 日期）响应头，返回需要等待的毫秒数
+pub(super) fn parse_retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<u64> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+
+    if let Some(ms) = header("retry-after-ms").and_then(|value| value.parse::<f64>().ok()) {
+        return (ms >= 0.0).then_some(ms as u64);
+    }
+    let value = header(RETRY_AFTER.as_str())?;
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds >= 0.0).then_some((seconds * 1000.0) as u64);
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - now).num_milliseconds().max(0) as u64)
+}
+
+pub(super) fn parse_response<T: serde::de::DeserializeOwned>(response_text: &str) -> Result<T, AIError> {
+    serde_json::from_str(response_text)
+        .map_err(|e| AIError::new("parse_error", format!("解析响应失败: {}", e)))
+}
+
+/// 按 HTTP 状态码和供应商错误类型、错误码归类为统一的错误类型，供应商原始的错误码保留在 code 中。
+/// 统一类型为 auth_error、quota_error、rate_limit_error、timeout_error、server_error、
+/// not_found_error、invalid_request_error，无法归类时为 api_error
+pub(super) fn classify_http_error(
+    status: StatusCode,
+    vendor_type: Option<&str>,
+    vendor_code: Option<&str>,
+    message: String,
+) -> AIError {
+    let vendor = format!("{} {}", vendor_type.unwrap_or_default(), vendor_code.unwrap_or_default()).to_lowercase();
+    let has = |keywords: &[&str]| keywords.iter().any(|k| vendor.contains(k));
+
+    // 额度不足也常以 429 返回，需先于限流判断
+    let error_type = if status == StatusCode::PAYMENT_REQUIRED || has(&["quota", "billing", "insufficient"]) {
+        "quota_error"
+    } else if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+        || has(&["auth", "permission", "api_key", "unauthenticated"])
+    {
+        "auth_error"
+    } else if status == StatusCode::TOO_MANY_REQUESTS || has(&["rate_limit", "resource_exhausted"]) {
+        "rate_limit_error"
+    } else if matches!(status, StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT)
+        || has(&["timeout", "deadline_exceeded"])
+    {
+        "timeout_error"
+    } else if status.is_server_error() || has(&["overloaded", "unavailable", "server_error", "internal"]) {
+        "server_error"
+    } else if status == StatusCode::NOT_FOUND || has(&["not_found"]) {
+        "not_found_error"
+    } else if status.is_client_error() {
+        "invalid_request_error"
+    } else {
+        "api_error"
+    };
+
+    AIError {
+        error_type: error_type.to_string(),
+        message,
+        code: Some(vendor_code.or(vendor_type).unwrap_or(status.as_str()).to_string()),
+        retry_after_ms: None,
+    }
+}
+
+/// 无法识别供应商错误格式时的通用错误
+pub(super) fn generic_api_error(status: StatusCode, response_text: &str) -> AIError {
+    classify_http_error(status, None, None, format!("API 调用失败 ({}): {}", status, response_text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_classify_http_error() {
+        let classify = |status: u16, vendor_type: Option<&str>, vendor_code: Option<&str>| {
+            let status = StatusCode::from_u16(status).unwrap();
+            classify_http_error(status, vendor_type, vendor_code, String::new())
+        };
+
+        let error = classify(429, Some("insufficient_quota"), Some("insufficient_quota"));
+        assert_eq!((error.error_type.as_str(), error.code.as_deref()), ("quota_error", Some("insufficient_quota")));
+        assert_eq!(classify(429, Some("requests"), Some("rate_limit_exceeded")).error_type, "rate_limit_error");
+        assert_eq!(classify(429, Some("RESOURCE_EXHAUSTED"), Some("429")).error_type, "rate_limit_error");
+        assert_eq!(classify(401, Some("invalid_request_error"), Some("invalid_api_key")).error_type, "auth_error");
+        assert_eq!(classify(403, Some("PERMISSION_DENIED"), None).error_type, "auth_error");
+        assert_eq!(classify(402, None, None).error_type, "quota_error");
+        assert_eq!(classify(529, Some("overloaded_error"), None).error_type, "server_error");
+        assert_eq!(classify(504, None, None).error_type, "timeout_error");
+        assert_eq!(classify(400, Some("invalid_request_error"), None).error_type, "invalid_request_error");
+        assert_eq!(classify(404, Some("not_found_error"), None).error_type, "not_found_error");
+
+        let error = generic_api_error(StatusCode::BAD_GATEWAY, "<html>");
+        assert_eq!((error.error_type.as_str(), error.code.as_deref()), ("server_error", Some("502")));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2026 07:28:00 GMT").unwrap().with_timezone(&Utc);
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+
+        assert_eq!(parse_retry_after(&headers(&[("retry-after", "20")]), now), Some(20_000));
+        assert_eq!(parse_retry_after(&headers(&[("retry-after", "20"), ("retry-after-ms", "1500.5")]), now), Some(1500));
+        assert_eq!(parse_retry_after(&headers(&[("retry-after", "Wed, 21 Oct 2026 07:28:30 GMT")]), now), Some(30_000));
+        assert_eq!(parse_retry_after(&headers(&[("retry-after", "Wed, 21 Oct 2026 07:27:00 GMT")]), now), Some(0));
+        assert_eq!(parse_retry_after(&headers(&[("retry-after", "soon")]), now), None);
+        assert_eq!(parse_retry_after(&HeaderMap::new(), now), None);
+    }
+}
@@ -0,0 +1,81 @@
+/// Server-Sent Events 解码器，按网络分片增量输入，输出完整事件的 data 内容
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data_lines: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 输入一段字节，返回其中已完整结束的事件数据
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(newline_pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline_pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                // 空行表示一个事件结束
+                if !self.data_lines.is_empty() {
+                    events.push(self.data_lines.join("\n"));
+                    self.data_lines.clear();
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data_lines.push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+            // 注释行（以冒号开头）以及 event/id/retry 字段不需要处理
+        }
+
+        events
+    }
+
+    /// 流结束时取出尚未以空行结束的最后一个事件
+    pub fn finish(&mut self) -> Option<String> {
+        let remaining = std::mem::take(&mut self.buffer);
+        if !remaining.is_empty() {
+            self.feed(&remaining);
+            self.feed(b"\n");
+        }
+        if self.data_lines.is_empty() {
+            None
+        } else {
+            let data = self.data_lines.join("\n");
+            self.data_lines.clear();
+            Some(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_split_events() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: {\"a\":").is_empty());
+        assert_eq!(decoder.feed(b"1}\n\n: keep-alive\n\ndata: [DONE]\r\n\r\n"), vec!["{\"a\":1}", "[DONE]"]);
+    }
+
+    #[test]
+    fn test_decode_multibyte_split() {
+        let mut decoder = SseDecoder::new();
+        let bytes = "data: 你好\n\n".as_bytes();
+        assert!(decoder.feed(&bytes[..8]).is_empty());
+        assert_eq!(decoder.feed(&bytes[8..]), vec!["你好"]);
+    }
+
+    #[test]
+    fn test_finish_flushes_last_event() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: tail").is_empty());
+        assert_eq!(decoder.finish(), Some("tail".to_string()));
+        assert_eq!(decoder.finish(), None);
+    }
+}
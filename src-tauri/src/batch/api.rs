@@ -0,0 +1,95 @@
+use tauri::{AppHandle, State};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use crate::database::{Batch, BatchFailure, Database};
+use crate::error::AppError;
+use crate::job::queue::JobQueue;
+use crate::gallery::thumbnail::ThumbnailQueue;
+use super::service::BatchService;
+
+/// 批次进度事件，批次内任一任务结束时发送
+pub const BATCH_PROGRESS_EVENT: &str = "batch-progress";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchEditRequest {
+    /// 本地图片文件路径，每个文件生成一条新的图库记录
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// 已有图库记录，以其原图生成一条新的图库记录，原记录不受影响
+    #[serde(default)]
+    pub gallery_ids: Vec<String>,
+    pub prompt: String,
+    #[serde(default)]
+    pub style_name: Option<String>,
+    /// 批次内同时执行的任务数
+    #[serde(default)]
+    pub concurrency: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchEditResponse {
+    pub batch_id: String,
+    pub total: u32,
+    /// 成功入队的输入数，其余输入在入队前已失败
+    pub queued: u32,
+    pub failed: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchProgress {
+    pub batch_id: String,
+    pub total: u32,
+    pub pending: u32,
+    pub running: u32,
+    pub succeeded: u32,
+    pub failed: u32,
+    pub cancelled: u32,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchSummary {
+    pub batch: Batch,
+    pub progress: BatchProgress,
+    pub failures: Vec<BatchFailure>,
+}
+
+type DatabaseState = Mutex<Database>;
+
+/// 批量编辑接口，读取文件较慢，在后台线程执行
+#[tauri::command]
+pub async fn batch_edit(
+    app: AppHandle,
+    db: State<'_, DatabaseState>,
+    job_queue: State<'_, JobQueue>,
+    thumbnails: State<'_, ThumbnailQueue>,
+    request: BatchEditRequest,
+) -> Result<BatchEditResponse, AppError> {
+    let service = BatchService::new();
+    let response = service.batch_edit(&app, db, job_queue, request).await?;
+    thumbnails.notify();
+    Ok(response)
+}
+
+/// 获取批次进度及失败明细接口
+#[tauri::command]
+pub fn get_batch_summary(
+    db: State<'_, DatabaseState>,
+    batch_id: String,
+) -> Result<BatchSummary, AppError> {
+    let service = BatchService::new();
+    service.get_batch_summary(db, &batch_id)
+}
+
+/// 取消批次中未完成的任务接口，返回取消的任务数
+#[tauri::command]
+pub fn cancel_batch(
+    app: AppHandle,
+    db: State<'_, DatabaseState>,
+    batch_id: String,
+) -> Result<u32, AppError> {
+    let service = BatchService::new();
+    service.cancel_batch(&app, db, &batch_id)
+}
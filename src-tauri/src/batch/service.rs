@@ -0,0 +1,241 @@
+use tauri::{AppHandle, Emitter, State};
+use std::sync::Mutex;
+use uuid::Uuid;
+use chrono::Utc;
+
+use crate::database::{
+    Batch, BatchItem, BlobStore, Database, JOB_STATUS_CANCELLED, JOB_STATUS_FAILED, JOB_STATUS_PENDING,
+    JOB_STATUS_RUNNING, JOB_STATUS_SUCCEEDED,
+};
+use crate::error::AppError;
+use crate::gallery::api::ImageEditRequest;
+use crate::gallery::service::{check_provider_setting, enqueue_edit, OriginImage};
+use crate::job::queue::JobQueue;
+use crate::job::service::JobService;
+
+use super::api::{
+    BatchEditRequest, BatchEditResponse, BatchProgress, BatchSummary, BATCH_PROGRESS_EVENT,
+};
+
+/// 单个批次的输入数量上限
+const MAX_BATCH_SIZE: usize = 500;
+
+/// 批次默认并发数和上限，实际并发还受任务队列工作线程数限制
+const DEFAULT_BATCH_CONCURRENCY: u32 = 2;
+const MAX_BATCH_CONCURRENCY: u32 = 8;
+
+type DatabaseState = Mutex<Database>;
+
+pub struct BatchService;
+
+impl BatchService {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// 批量编辑：每个输入对应一条图库记录和一个编辑任务，单个输入失败不影响其它输入
+    pub async fn batch_edit(
+        &self,
+        app: &AppHandle,
+        db: State<'_, DatabaseState>,
+        job_queue: State<'_, JobQueue>,
+        request: BatchEditRequest,
+    ) -> Result<BatchEditResponse, AppError> {
+        let total = request.paths.len() + request.gallery_ids.len();
+        if total == 0 {
+            return Err(AppError::validation("请选择要编辑的图片"));
+        }
+        if total > MAX_BATCH_SIZE {
+            return Err(AppError::Validation(format!("单次最多批量编辑 {} 张图片", MAX_BATCH_SIZE)));
+        }
+        if request.prompt.trim().is_empty() {
+            return Err(AppError::validation("提示词不能为空"));
+        }
+        let concurrency = request.concurrency
+            .unwrap_or(DEFAULT_BATCH_CONCURRENCY)
+            .clamp(1, MAX_BATCH_CONCURRENCY);
+
+        let batch = Batch {
+            id: Uuid::new_v4().to_string(),
+            prompt: request.prompt.clone(),
+            style_name: request.style_name.clone(),
+            concurrency,
+            total: total as u32,
+            create_at: Utc::now().timestamp_millis(),
+        };
+        let blobs = {
+            let db = db.lock()?;
+            check_provider_setting(&db)?;
+            db.batch().create(&batch)
+                .map_err(|e| AppError::database("Failed to create batch", e))?;
+            db.blobs().clone()
+        };
+
+        let inputs = request.paths
+            .iter()
+            .map(|path| (path.clone(), true))
+            .chain(request.gallery_ids.iter().map(|id| (id.clone(), false)));
+
+        let mut queued = 0;
+        for (position, (input, is_path)) in inputs.enumerate() {
+            // 读取、解码和保存文件较慢，在阻塞线程池中执行，期间不持有数据库锁
+            let file_origin = if is_path { Some(store_image_file(blobs.clone(), input.clone()).await) } else { None };
+
+            let db = db.lock()?;
+            // 图库记录以原图作为新记录的输入，避免批量任务改动已有记录的版本和对话
+            let origin = file_origin.unwrap_or_else(|| gallery_origin(&db, &input));
+            let enqueued = origin.and_then(|origin| {
+                let edit_request = ImageEditRequest {
+                    origin_image: None,
+                    gallery_id: None,
+                    base_version_id: None,
+                    prompt: request.prompt.clone(),
+                    style_name: request.style_name.clone(),
+                    request_id: None,
+                    n: 1,
+                };
+                enqueue_edit(&db, edit_request, Some(origin), Some(&batch.id))
+            });
+
+            let item = BatchItem {
+                id: Uuid::new_v4().to_string(),
+                batch_id: batch.id.clone(),
+                position: position as u32,
+                input,
+                gallery_id: enqueued.as_ref().ok().map(|(gallery_id, _)| gallery_id.clone()),
+                job_id: enqueued.as_ref().ok().map(|(_, job)| job.id.clone()),
+                error: enqueued.as_ref().err().map(AppError::to_string),
+            };
+            db.batch().create_item(&item)
+                .map_err(|e| AppError::database("Failed to create batch item", e))?;
+
+            if enqueued.is_ok() {
+                queued += 1;
+                job_queue.notify();
+            }
+        }
+
+        {
+            let db = db.lock()?;
+            emit_batch_progress(app, &db, &batch.id);
+        }
+
+        Ok(BatchEditResponse {
+            batch_id: batch.id,
+            total: batch.total,
+            queued,
+            failed: batch.total - queued,
+        })
+    }
+
+    /// 批次进度、用量汇总及失败的输入
+    pub fn get_batch_summary(
+        &self,
+        db: State<'_, DatabaseState>,
+        batch_id: &str,
+    ) -> Result<BatchSummary, AppError> {
+        let db = db.lock()?;
+        let batch = db.batch().get_by_id(batch_id)
+            .map_err(|e| AppError::database("Failed to get batch", e))?
+            .ok_or_else(|| AppError::not_found("批次不存在"))?;
+        let progress = batch_progress(&db, &batch)?;
+        let failures = db.batch().get_failures(batch_id)
+            .map_err(|e| AppError::database("Failed to get batch failures", e))?;
+
+        Ok(BatchSummary {
+            batch,
+            progress,
+            failures,
+        })
+    }
+
+    /// 取消批次中排队和执行中的任务
+    pub fn cancel_batch(
+        &self,
+        app: &AppHandle,
+        db: State<'_, DatabaseState>,
+        batch_id: &str,
+    ) -> Result<u32, AppError> {
+        let job_ids: Vec<String> = {
+            let db = db.lock()?;
+            db.job().get_by_batch_id(batch_id)
+                .map_err(|e| AppError::database("Failed to get jobs", e))?
+                .into_iter()
+                .filter(|job| !job.is_finished())
+                .map(|job| job.id)
+                .collect()
+        };
+
+        let job_service = JobService::new();
+        let mut cancelled = 0;
+        for job_id in job_ids {
+            if job_service.cancel_job(app, &job_id)? {
+                cancelled += 1;
+            }
+        }
+
+        Ok(cancelled)
+    }
+}
+
+/// 发送批次进度事件，统计失败时只记录日志
+pub fn emit_batch_progress(app: &AppHandle, db: &Database, batch_id: &str) {
+    let progress = db.batch().get_by_id(batch_id)
+        .map_err(|e| AppError::database("Failed to get batch", e))
+        .and_then(|batch| batch.ok_or_else(|| AppError::not_found("批次不存在")))
+        .and_then(|batch| batch_progress(db, &batch));
+
+    match progress {
+        Ok(progress) => {
+            let _ = app.emit(BATCH_PROGRESS_EVENT, progress);
+        }
+        Err(e) => log::warn!("统计批次 {} 进度失败: {}", batch_id, e),
+    }
+}
+
+fn batch_progress(db: &Database, batch: &Batch) -> Result<BatchProgress, AppError> {
+    let counts = db.batch().count_by_status(&batch.id)
+        .map_err(|e| AppError::database("Failed to count batch", e))?;
+
+    let mut progress = BatchProgress {
+        batch_id: batch.id.clone(),
+        total: batch.total,
+        ..Default::default()
+    };
+    for count in counts {
+        match count.status.as_str() {
+            JOB_STATUS_PENDING => progress.pending += count.count,
+            JOB_STATUS_RUNNING => progress.running += count.count,
+            JOB_STATUS_SUCCEEDED => progress.succeeded += count.count,
+            JOB_STATUS_FAILED => progress.failed += count.count,
+            JOB_STATUS_CANCELLED => progress.cancelled += count.count,
+            _ => {}
+        }
+        progress.input_tokens += count.input_tokens;
+        progress.output_tokens += count.output_tokens;
+    }
+
+    Ok(progress)
+}
+
+// 读取本地图片文件并写入 blob 仓库，格式按文件内容识别
+async fn store_image_file(blobs: BlobStore, path: String) -> Result<OriginImage, AppError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let bytes = std::fs::read(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::NotFound(format!("文件不存在: {}", path)),
+            _ => AppError::Validation(format!("读取文件失败: {}", e)),
+        })?;
+        let format = image::guess_format(&bytes).map_err(|_| AppError::validation("不支持的图片格式"))?;
+        OriginImage::store(&blobs, &bytes, Some(format.to_mime_type()))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("读取文件失败: {}", e)))?
+}
+
+// 图库记录的原图已在 blob 仓库中，直接复用
+fn gallery_origin(db: &Database, gallery_id: &str) -> Result<OriginImage, AppError> {
+    let gallery = db.gallery().get_by_id(gallery_id)
+        .map_err(|e| AppError::database("Failed to get gallery", e))?
+        .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+    Ok(OriginImage { image: gallery.origin_image, metadata: gallery.metadata })
+}
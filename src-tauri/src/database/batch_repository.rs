@@ -0,0 +1,154 @@
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use serde::{Deserialize, Serialize};
+
+use super::job_repository::JOB_STATUS_FAILED;
+
+/// 一次批量编辑
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Batch {
+    pub id: String,
+    pub prompt: String,
+    pub style_name: Option<String>,
+    /// 批次内同时执行的任务数上限
+    pub concurrency: u32,
+    pub total: u32,
+    pub create_at: i64,
+}
+
+/// 批次中的一个输入，input 为文件路径或图库 ID
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchItem {
+    pub id: String,
+    pub batch_id: String,
+    pub position: u32,
+    pub input: String,
+    pub gallery_id: Option<String>,
+    pub job_id: Option<String>,
+    pub error: Option<String>,
+}
+
+/// 批次内某一状态的输入数量及用量
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BatchStatusCount {
+    pub status: String,
+    pub count: u32,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+}
+
+/// 失败的输入及原因
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchFailure {
+    pub position: u32,
+    pub input: String,
+    pub gallery_id: Option<String>,
+    pub job_id: Option<String>,
+    pub error: Option<String>,
+}
+
+pub struct BatchRepository<'conn> {
+    conn: &'conn Connection,
+}
+
+impl<'conn> BatchRepository<'conn> {
+    pub fn new(conn: &'conn Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn create(&self, batch: &Batch) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO batch (id, prompt, style_name, concurrency, total, create_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                batch.id,
+                batch.prompt,
+                batch.style_name,
+                batch.concurrency,
+                batch.total,
+                batch.create_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn create_item(&self, item: &BatchItem) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO batch_item (id, batch_id, position, input, gallery_id, job_id, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                item.id,
+                item.batch_id,
+                item.position,
+                item.input,
+                item.gallery_id,
+                item.job_id,
+                item.error
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_by_id(&self, id: &str) -> Result<Option<Batch>> {
+        self.conn
+            .query_row(
+                "SELECT id, prompt, style_name, concurrency, total, create_at FROM batch WHERE id = ?1",
+                [id],
+                |row| {
+                    Ok(Batch {
+                        id: row.get(0)?,
+                        prompt: row.get(1)?,
+                        style_name: row.get(2)?,
+                        concurrency: row.get(3)?,
+                        total: row.get(4)?,
+                        create_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    /// 按状态汇总批次进度，入队前失败或任务已被删除的输入计为失败
+    pub fn count_by_status(&self, batch_id: &str) -> Result<Vec<BatchStatusCount>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(job.status, ?2) AS status, COUNT(*),
+                 COALESCE(SUM(job.input_tokens), 0), COALESCE(SUM(job.output_tokens), 0)
+             FROM batch_item LEFT JOIN job ON job.id = batch_item.job_id
+             WHERE batch_item.batch_id = ?1
+             GROUP BY status ORDER BY status"
+        )?;
+
+        let counts = stmt.query_map(params![batch_id, JOB_STATUS_FAILED], |row| {
+            Ok(BatchStatusCount {
+                status: row.get(0)?,
+                count: row.get(1)?,
+                input_tokens: row.get(2)?,
+                output_tokens: row.get(3)?,
+            })
+        })?;
+
+        counts.collect()
+    }
+
+    /// 失败的输入，按提交顺序排列
+    pub fn get_failures(&self, batch_id: &str) -> Result<Vec<BatchFailure>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT batch_item.position, batch_item.input, batch_item.gallery_id, batch_item.job_id,
+                 COALESCE(batch_item.error, job.error)
+             FROM batch_item LEFT JOIN job ON job.id = batch_item.job_id
+             WHERE batch_item.batch_id = ?1 AND COALESCE(job.status, ?2) = ?2
+             ORDER BY batch_item.position"
+        )?;
+
+        let failures = stmt.query_map(params![batch_id, JOB_STATUS_FAILED], |row| {
+            Ok(BatchFailure {
+                position: row.get(0)?,
+                input: row.get(1)?,
+                gallery_id: row.get(2)?,
+                job_id: row.get(3)?,
+                error: row.get(4)?,
+            })
+        })?;
+
+        failures.collect()
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use crate::ai_service::decode_image_data;
+
+/// 存入 blob 仓库的图片，记录在 gallery 表中
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StoredImage {
+    pub hash: String,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub byte_size: i64,
+}
+
+/// 按 SHA-256 寻址的文件仓库，内容相同的图片只保存一份。只持有根目录，
+/// 可以复制一份在数据库锁之外读写文件
+#[derive(Clone)]
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        fs::create_dir_all(root.as_ref())?;
+        Ok(Self { root: root.as_ref().to_path_buf() })
+    }
+
+    /// 写入字节并返回其哈希，已存在时直接复用
+    pub fn put(&self, bytes: &[u8]) -> io::Result<String> {
+        let hash = hex_digest(bytes);
+        let path = self.path(&hash)?;
+        if path.exists() {
+            return Ok(hash);
+        }
+
+        let dir = path.parent().unwrap_or(&self.root);
+        fs::create_dir_all(dir)?;
+
+        // 先写临时文件再重命名，避免中途退出留下不完整的文件；临时文件名唯一，
+        // 并发写入同一内容时各写各的，重命名后内容一致
+        let tmp_path = dir.join(format!("{}.{}.tmp", hash, uuid::Uuid::new_v4()));
+        let written = fs::File::create(&tmp_path).and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| fs::rename(&tmp_path, &path)) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(hash)?)
+    }
+
+    pub fn remove(&self, hash: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(hash)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// 文件按哈希前两位分目录存放，避免单目录文件过多
+    pub fn path(&self, hash: &str) -> io::Result<PathBuf> {
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("无效的图片哈希: {}", hash)));
+        }
+        Ok(self.root.join(&hash[..2]).join(hash))
+    }
+
+    /// 保存 data URL 格式的图片，并读取尺寸等元信息
+    pub fn put_data_url(&self, data_url: &str) -> Result<StoredImage, String> {
+        let (mime_type, bytes) = decode_image_data(data_url)?;
+        self.put_image(&bytes, Some(&mime_type))
+    }
+
+    /// 保存图片字节，MIME 类型优先按内容识别
+    pub fn put_image(&self, bytes: &[u8], mime_type: Option<&str>) -> Result<StoredImage, String> {
+        let reader = image::ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|e| format!("读取图片失败: {}", e))?;
+        let mime_type = reader
+            .format()
+            .map(|format| format.to_mime_type().to_string())
+            .or_else(|| mime_type.map(str::to_string))
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        let (width, height) = reader.into_dimensions().unwrap_or((0, 0));
+
+        let hash = self.put(bytes).map_err(|e| format!("保存图片失败: {}", e))?;
+
+        Ok(StoredImage {
+            hash,
+            mime_type,
+            width,
+            height,
+            byte_size: bytes.len() as i64,
+        })
+    }
+
+    /// 读取图片并编码为 data URL
+    pub fn get_data_url(&self, hash: &str) -> io::Result<String> {
+        let bytes = self.get(hash)?;
+        let mime_type = image::guess_format(&bytes)
+            .map(|format| format.to_mime_type())
+            .unwrap_or("application/octet-stream");
+        Ok(format!(
+            "data:{};base64,{}",
+            mime_type,
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes)
+        ))
+    }
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
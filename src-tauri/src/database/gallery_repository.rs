@@ -1,14 +1,180 @@
-use rusqlite::{Connection, Result, params};
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension, Result, Row, params, params_from_iter};
 use serde::{Deserialize, Serialize};
 
+use super::blob_store::StoredImage;
+use crate::image_ops::metadata::ImageMetadata;
+
+/// 图库记录状态：pending 为编辑任务排队中，processing 为任务执行中
+pub const GALLERY_STATUS_PENDING: &str = "pending";
+pub const GALLERY_STATUS_PROCESSING: &str = "processing";
+pub const GALLERY_STATUS_SUCCEEDED: &str = "succeeded";
+pub const GALLERY_STATUS_CANCELLED: &str = "cancelled";
+pub const GALLERY_STATUS_FAILED: &str = "failed";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Gallery {
     pub id: String,
-    pub origin_image: String,
-    pub effect_image: String,
+    pub origin_image: StoredImage,
+    pub effect_image: Option<StoredImage>,
     pub total_input_tokens: i64,
-    pub total_ouput_tokens: i64,
+    pub total_output_tokens: i64,
+    pub status: String,
+    pub style_name: Option<String>,
     pub create_at: i64,
+    /// 当前检出的版本，效果图与该版本一致
+    pub current_version_id: Option<String>,
+    /// 原图元数据
+    pub metadata: ImageMetadata,
+    /// 最近一次编辑失败的原因
+    pub error: Option<String>,
+}
+
+impl Gallery {
+    /// 编辑任务排队或执行中，此时不允许再次编辑或切换版本
+    pub fn is_editing(&self) -> bool {
+        self.status == GALLERY_STATUS_PENDING || self.status == GALLERY_STATUS_PROCESSING
+    }
+}
+
+const GALLERY_COLUMNS: &str = "id, origin_hash, origin_mime, origin_width, origin_height, origin_size,
+    effect_hash, effect_mime, effect_width, effect_height, effect_size,
+    total_input_tokens, total_output_tokens, status, style_name, create_at, current_version_id,
+    origin_color_type, taken_at, camera_model, orientation, error";
+
+// 排序和筛选使用的总 token 表达式，需与索引定义保持一致
+const TOTAL_TOKENS_EXPR: &str = "(total_input_tokens + total_output_tokens)";
+// 原图长边，需与索引定义保持一致
+const LONG_EDGE_EXPR: &str = "MAX(origin_width, origin_height)";
+
+/// 图库列表排序方式
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GallerySort {
+    #[default]
+    CreateAtDesc,
+    CreateAtAsc,
+    TokensDesc,
+    TokensAsc,
+}
+
+impl GallerySort {
+    fn key_expr(&self) -> &'static str {
+        match self {
+            Self::CreateAtDesc | Self::CreateAtAsc => "create_at",
+            Self::TokensDesc | Self::TokensAsc => TOTAL_TOKENS_EXPR,
+        }
+    }
+
+    fn is_desc(&self) -> bool {
+        matches!(self, Self::CreateAtDesc | Self::TokensDesc)
+    }
+
+    fn key_of(&self, gallery: &Gallery) -> i64 {
+        match self {
+            Self::CreateAtDesc | Self::CreateAtAsc => gallery.create_at,
+            Self::TokensDesc | Self::TokensAsc => gallery.total_input_tokens + gallery.total_output_tokens,
+        }
+    }
+}
+
+/// 图库列表筛选条件，时间为毫秒时间戳，除拍摄时间外区间均为闭区间
+#[derive(Debug, Clone, Default)]
+pub struct GalleryFilter {
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub style_name: Option<String>,
+    pub min_tokens: Option<i64>,
+    pub max_tokens: Option<i64>,
+    pub status: Option<String>,
+    /// 原图长边像素范围
+    pub min_edge: Option<u32>,
+    pub max_edge: Option<u32>,
+    pub mime_type: Option<String>,
+    /// 拍摄时间范围 [taken_from, taken_until)，与 taken_at 同格式的字符串，可只写日期部分
+    pub taken_from: Option<String>,
+    pub taken_until: Option<String>,
+    /// 相机型号，按包含匹配且不区分大小写
+    pub camera_model: Option<String>,
+}
+
+impl GalleryFilter {
+    fn to_sql(&self) -> (Vec<String>, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        if let Some(start_time) = self.start_time {
+            conditions.push("create_at >= ?".to_string());
+            values.push(Value::Integer(start_time));
+        }
+        if let Some(end_time) = self.end_time {
+            conditions.push("create_at <= ?".to_string());
+            values.push(Value::Integer(end_time));
+        }
+        if let Some(style_name) = &self.style_name {
+            conditions.push("style_name = ?".to_string());
+            values.push(Value::Text(style_name.clone()));
+        }
+        if let Some(min_tokens) = self.min_tokens {
+            conditions.push(format!("{} >= ?", TOTAL_TOKENS_EXPR));
+            values.push(Value::Integer(min_tokens));
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            conditions.push(format!("{} <= ?", TOTAL_TOKENS_EXPR));
+            values.push(Value::Integer(max_tokens));
+        }
+        if let Some(status) = &self.status {
+            conditions.push("status = ?".to_string());
+            values.push(Value::Text(status.clone()));
+        }
+        if let Some(min_edge) = self.min_edge {
+            conditions.push(format!("{} >= ?", LONG_EDGE_EXPR));
+            values.push(Value::Integer(min_edge as i64));
+        }
+        if let Some(max_edge) = self.max_edge {
+            conditions.push(format!("{} <= ?", LONG_EDGE_EXPR));
+            values.push(Value::Integer(max_edge as i64));
+        }
+        if let Some(mime_type) = &self.mime_type {
+            conditions.push("origin_mime = ?".to_string());
+            values.push(Value::Text(mime_type.clone()));
+        }
+        if let Some(taken_from) = &self.taken_from {
+            conditions.push("taken_at >= ?".to_string());
+            values.push(Value::Text(taken_from.clone()));
+        }
+        if let Some(taken_until) = &self.taken_until {
+            conditions.push("taken_at < ?".to_string());
+            values.push(Value::Text(taken_until.clone()));
+        }
+        if let Some(camera_model) = &self.camera_model {
+            conditions.push("camera_model LIKE ? ESCAPE '\\'".to_string());
+            values.push(Value::Text(format!("%{}%", escape_like(camera_model))));
+        }
+        (conditions, values)
+    }
+}
+
+/// 游标分页位置：上一页最后一条记录的排序键和 ID
+#[derive(Debug, Clone, PartialEq)]
+pub struct GalleryCursor {
+    pub key: i64,
+    pub id: String,
+}
+
+impl GalleryCursor {
+    /// 以某条记录为界的游标，下一页从它之后开始
+    pub fn after(gallery: &Gallery, sort: GallerySort) -> Self {
+        Self { key: sort.key_of(gallery), id: gallery.id.clone() }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.key, self.id)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let (key, id) = cursor.split_once(':')?;
+        Some(Self { key: key.parse().ok()?, id: id.to_string() })
+    }
 }
 
 pub struct GalleryRepository<'conn> {
@@ -22,77 +188,145 @@ impl<'conn> GalleryRepository<'conn> {
     }
 
     pub fn create(&self, gallery: &Gallery) -> Result<()> {
+        let effect = gallery.effect_image.as_ref();
         self.conn.execute(
-            "INSERT INTO gallery (id, origin_image, effect_image, total_input_tokens, total_ouput_tokens, create_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            &format!("INSERT INTO gallery ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)", GALLERY_COLUMNS),
             params![
                 gallery.id,
-                gallery.origin_image,
-                gallery.effect_image,
+                gallery.origin_image.hash,
+                gallery.origin_image.mime_type,
+                gallery.origin_image.width,
+                gallery.origin_image.height,
+                gallery.origin_image.byte_size,
+                effect.map(|e| &e.hash),
+                effect.map(|e| &e.mime_type),
+                effect.map(|e| e.width),
+                effect.map(|e| e.height),
+                effect.map(|e| e.byte_size),
                 gallery.total_input_tokens,
-                gallery.total_ouput_tokens,
-                gallery.create_at
+                gallery.total_output_tokens,
+                gallery.status,
+                gallery.style_name,
+                gallery.create_at,
+                gallery.current_version_id,
+                gallery.metadata.color_type,
+                gallery.metadata.taken_at,
+                gallery.metadata.camera_model,
+                gallery.metadata.orientation,
+                gallery.error
             ],
         )?;
         Ok(())
     }
 
     pub fn get_all(&self) -> Result<Vec<Gallery>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, origin_image, effect_image, total_input_tokens, total_ouput_tokens, create_at
-             FROM gallery ORDER BY create_at DESC"
-        )?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM gallery ORDER BY create_at DESC",
+            GALLERY_COLUMNS
+        ))?;
 
-        let galleries = stmt.query_map([], |row| {
-            Ok(Gallery {
-                id: row.get(0)?,
-                origin_image: row.get(1)?,
-                effect_image: row.get(2)?,
-                total_input_tokens: row.get(3)?,
-                total_ouput_tokens: row.get(4)?,
-                create_at: row.get(5)?,
-            })
-        })?;
+        let galleries = stmt.query_map([], map_gallery)?;
 
         galleries.collect()
     }
 
     pub fn get_by_id(&self, id: &str) -> Result<Option<Gallery>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, origin_image, effect_image, total_input_tokens, total_ouput_tokens, create_at
-             FROM gallery WHERE id = ?1"
-        )?;
-
-        let mut galleries = stmt.query_map([id], |row| {
-            Ok(Gallery {
-                id: row.get(0)?,
-                origin_image: row.get(1)?,
-                effect_image: row.get(2)?,
-                total_input_tokens: row.get(3)?,
-                total_ouput_tokens: row.get(4)?,
-                create_at: row.get(5)?,
-            })
-        })?;
-
-        galleries.next().transpose()
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM gallery WHERE id = ?1", GALLERY_COLUMNS),
+                [id],
+                map_gallery,
+            )
+            .optional()
     }
 
     pub fn update(&self, gallery: &Gallery) -> Result<()> {
+        let effect = gallery.effect_image.as_ref();
         self.conn.execute(
             "UPDATE gallery SET
-             origin_image = ?2, effect_image = ?3, total_input_tokens = ?4,
-             total_ouput_tokens = ?5 WHERE id = ?1",
+             origin_hash = ?2, origin_mime = ?3, origin_width = ?4, origin_height = ?5, origin_size = ?6,
+             effect_hash = ?7, effect_mime = ?8, effect_width = ?9, effect_height = ?10, effect_size = ?11,
+             total_input_tokens = ?12, total_output_tokens = ?13, status = ?14, style_name = ?15,
+             current_version_id = ?16, error = ?17 WHERE id = ?1",
             params![
                 gallery.id,
-                gallery.origin_image,
-                gallery.effect_image,
+                gallery.origin_image.hash,
+                gallery.origin_image.mime_type,
+                gallery.origin_image.width,
+                gallery.origin_image.height,
+                gallery.origin_image.byte_size,
+                effect.map(|e| &e.hash),
+                effect.map(|e| &e.mime_type),
+                effect.map(|e| e.width),
+                effect.map(|e| e.height),
+                effect.map(|e| e.byte_size),
                 gallery.total_input_tokens,
-                gallery.total_ouput_tokens
+                gallery.total_output_tokens,
+                gallery.status,
+                gallery.style_name,
+                gallery.current_version_id,
+                gallery.error
             ],
         )?;
         Ok(())
     }
 
+    /// 按条件分页查询，使用 (排序键, id) 做游标，避免 OFFSET 随页数增长变慢
+    pub fn list(
+        &self,
+        filter: &GalleryFilter,
+        sort: GallerySort,
+        cursor: Option<&GalleryCursor>,
+        limit: u32,
+    ) -> Result<Vec<Gallery>> {
+        let (mut conditions, mut values) = filter.to_sql();
+        let key_expr = sort.key_expr();
+        let (order, cmp) = if sort.is_desc() { ("DESC", "<") } else { ("ASC", ">") };
+
+        if let Some(cursor) = cursor {
+            conditions.push(format!("({0} {1} ? OR ({0} = ? AND id {1} ?))", key_expr, cmp));
+            values.push(Value::Integer(cursor.key));
+            values.push(Value::Integer(cursor.key));
+            values.push(Value::Text(cursor.id.clone()));
+        }
+        values.push(Value::Integer(limit as i64));
+
+        let sql = format!(
+            "SELECT {} FROM gallery {} ORDER BY {} {}, id {} LIMIT ?",
+            GALLERY_COLUMNS,
+            where_clause(&conditions),
+            key_expr,
+            order,
+            order,
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let galleries = stmt.query_map(params_from_iter(values), map_gallery)?;
+
+        galleries.collect()
+    }
+
+    /// 满足筛选条件的记录总数
+    pub fn count(&self, filter: &GalleryFilter) -> Result<i64> {
+        let (conditions, values) = filter.to_sql();
+        self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM gallery {}", where_clause(&conditions)),
+            params_from_iter(values),
+            |row| row.get(0),
+        )
+    }
+
+    /// 图片文件是否仍被任意图库记录或版本引用，删除记录后据此清理 blob
+    pub fn is_blob_referenced(&self, hash: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM gallery WHERE origin_hash = ?1 OR effect_hash = ?1)
+                 OR EXISTS(SELECT 1 FROM gallery_version WHERE image_hash = ?1)
+                 OR EXISTS(SELECT 1 FROM gallery_thumbnail WHERE image_hash = ?1)",
+            [hash],
+            |row| row.get(0),
+        )
+    }
+
     pub fn delete(&self, id: &str) -> Result<()> {
         self.conn.execute("DELETE FROM gallery WHERE id = ?1", [id])?;
         Ok(())
@@ -107,12 +341,89 @@ impl<'conn> GalleryRepository<'conn> {
         Ok(())
     }
 
+    /// 更新状态及失败原因，error 为空时清空上次的失败原因
+    pub fn update_status(&self, id: &str, status: &str, error: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE gallery SET status = ?2, error = ?3 WHERE id = ?1",
+            params![id, status, error],
+        )?;
+        Ok(())
+    }
+
+    /// 应用退出时执行中的编辑任务会重新排队，对应的记录恢复为 pending，返回恢复的记录数
+    pub fn reset_processing(&self) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE gallery SET status = ?1 WHERE status = ?2",
+            params![GALLERY_STATUS_PENDING, GALLERY_STATUS_PROCESSING],
+        )
+    }
+
+    /// 仅当记录处于 from 状态时切换到 to，返回是否切换成功
+    pub fn transition_status(&self, id: &str, from: &str, to: &str) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE gallery SET status = ?3 WHERE id = ?1 AND status = ?2",
+            params![id, from, to],
+        )?;
+        Ok(updated > 0)
+    }
+
     pub fn update_tokens(&self, id: &str, input_tokens: i64, output_tokens: i64) -> Result<()> {
         self.conn.execute(
-            "UPDATE gallery SET total_input_tokens = ?2, total_ouput_tokens = ?3
+            "UPDATE gallery SET total_input_tokens = ?2, total_output_tokens = ?3
              WHERE id = ?1",
             params![id, input_tokens, output_tokens],
         )?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// LIKE 中的通配符按字面匹配
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    }
+}
+
+fn map_gallery(row: &Row) -> Result<Gallery> {
+    let effect_image = match row.get::<_, Option<String>>(6)? {
+        Some(hash) => Some(StoredImage {
+            hash,
+            mime_type: row.get(7)?,
+            width: row.get(8)?,
+            height: row.get(9)?,
+            byte_size: row.get(10)?,
+        }),
+        None => None,
+    };
+
+    Ok(Gallery {
+        id: row.get(0)?,
+        origin_image: StoredImage {
+            hash: row.get(1)?,
+            mime_type: row.get(2)?,
+            width: row.get(3)?,
+            height: row.get(4)?,
+            byte_size: row.get(5)?,
+        },
+        effect_image,
+        total_input_tokens: row.get(11)?,
+        total_output_tokens: row.get(12)?,
+        status: row.get(13)?,
+        style_name: row.get(14)?,
+        create_at: row.get(15)?,
+        current_version_id: row.get(16)?,
+        metadata: ImageMetadata {
+            color_type: row.get(17)?,
+            taken_at: row.get(18)?,
+            camera_model: row.get(19)?,
+            orientation: row.get(20)?,
+        },
+        error: row.get(21)?,
+    })
+}
@@ -0,0 +1,145 @@
+use rusqlite::{Connection, OptionalExtension, Result, Row, params};
+use serde::{Deserialize, Serialize};
+
+use super::blob_store::StoredImage;
+
+/// 版本来源
+pub const VERSION_SOURCE_ORIGIN: &str = "origin";
+pub const VERSION_SOURCE_AI: &str = "ai";
+pub const VERSION_SOURCE_LOCAL: &str = "local";
+
+/// 图库编辑版本树中的一个节点
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GalleryVersion {
+    pub id: String,
+    pub gallery_id: String,
+    pub parent_id: Option<String>,
+    pub source: String,
+    pub prompt: Option<String>,
+    pub style_name: Option<String>,
+    pub model: Option<String>,
+    /// 本地操作参数（JSON）
+    pub params: Option<String>,
+    pub image: StoredImage,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub create_at: i64,
+}
+
+impl GalleryVersion {
+    /// 根节点对应原图
+    pub fn is_root(&self) -> bool {
+        self.parent_id.is_none()
+    }
+}
+
+const VERSION_COLUMNS: &str = "id, gallery_id, parent_id, source, prompt, style_name, model, params,
+    image_hash, image_mime, image_width, image_height, image_size, input_tokens, output_tokens, create_at";
+
+pub struct GalleryVersionRepository<'conn> {
+    conn: &'conn Connection,
+}
+
+#[allow(dead_code)]
+impl<'conn> GalleryVersionRepository<'conn> {
+    pub fn new(conn: &'conn Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn create(&self, version: &GalleryVersion) -> Result<()> {
+        self.conn.execute(
+            &format!(
+                "INSERT INTO gallery_version ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+                VERSION_COLUMNS
+            ),
+            params![
+                version.id,
+                version.gallery_id,
+                version.parent_id,
+                version.source,
+                version.prompt,
+                version.style_name,
+                version.model,
+                version.params,
+                version.image.hash,
+                version.image.mime_type,
+                version.image.width,
+                version.image.height,
+                version.image.byte_size,
+                version.input_tokens,
+                version.output_tokens,
+                version.create_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_by_id(&self, id: &str) -> Result<Option<GalleryVersion>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM gallery_version WHERE id = ?1", VERSION_COLUMNS),
+                [id],
+                map_version,
+            )
+            .optional()
+    }
+
+    pub fn get_by_gallery_id(&self, gallery_id: &str) -> Result<Vec<GalleryVersion>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM gallery_version WHERE gallery_id = ?1 ORDER BY create_at ASC",
+            VERSION_COLUMNS
+        ))?;
+
+        let versions = stmt.query_map([gallery_id], map_version)?;
+
+        versions.collect()
+    }
+
+    /// 最近创建的子版本，用于重做
+    pub fn get_latest_child(&self, parent_id: &str) -> Result<Option<GalleryVersion>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM gallery_version WHERE parent_id = ?1 ORDER BY create_at DESC LIMIT 1",
+                    VERSION_COLUMNS
+                ),
+                [parent_id],
+                map_version,
+            )
+            .optional()
+    }
+
+    /// 图库下所有版本引用的图片哈希
+    pub fn get_image_hashes(&self, gallery_id: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT image_hash FROM gallery_version WHERE gallery_id = ?1"
+        )?;
+
+        let hashes = stmt.query_map([gallery_id], |row| row.get(0))?;
+
+        hashes.collect()
+    }
+}
+
+fn map_version(row: &Row) -> Result<GalleryVersion> {
+    Ok(GalleryVersion {
+        id: row.get(0)?,
+        gallery_id: row.get(1)?,
+        parent_id: row.get(2)?,
+        source: row.get(3)?,
+        prompt: row.get(4)?,
+        style_name: row.get(5)?,
+        model: row.get(6)?,
+        params: row.get(7)?,
+        image: StoredImage {
+            hash: row.get(8)?,
+            mime_type: row.get(9)?,
+            width: row.get(10)?,
+            height: row.get(11)?,
+            byte_size: row.get(12)?,
+        },
+        input_tokens: row.get(13)?,
+        output_tokens: row.get(14)?,
+        create_at: row.get(15)?,
+    })
+}
@@ -0,0 +1,345 @@
+use rusqlite::{Connection, OptionalExtension, Result, Row, params};
+use serde::{Deserialize, Serialize};
+
+/// 任务状态
+pub const JOB_STATUS_PENDING: &str = "pending";
+pub const JOB_STATUS_RUNNING: &str = "running";
+pub const JOB_STATUS_SUCCEEDED: &str = "succeeded";
+pub const JOB_STATUS_FAILED: &str = "failed";
+pub const JOB_STATUS_CANCELLED: &str = "cancelled";
+
+/// 任务类型
+pub const JOB_KIND_IMAGE_EDIT: &str = "image_edit";
+
+/// 后台任务，payload 和 result 为各任务类型自行约定的 JSON
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Job {
+    pub id: String,
+    pub kind: String,
+    pub gallery_id: String,
+    pub request_id: Option<String>,
+    /// 所属批次，批次内同时执行的任务数受批次并发数限制
+    pub batch_id: Option<String>,
+    pub payload: String,
+    pub status: String,
+    pub attempts: u32,
+    pub max_attempts: u32,
+    pub error: Option<String>,
+    pub result: Option<String>,
+    /// 重试任务在此时间之后才会再次执行
+    pub run_after: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub create_at: i64,
+    pub update_at: i64,
+}
+
+impl Job {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            JOB_STATUS_SUCCEEDED | JOB_STATUS_FAILED | JOB_STATUS_CANCELLED
+        )
+    }
+}
+
+const JOB_COLUMNS: &str = "id, kind, gallery_id, request_id, batch_id, payload, status, attempts,
+    max_attempts, error, result, run_after, input_tokens, output_tokens, create_at, update_at";
+
+pub struct JobRepository<'conn> {
+    conn: &'conn Connection,
+}
+
+impl<'conn> JobRepository<'conn> {
+    pub fn new(conn: &'conn Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn create(&self, job: &Job) -> Result<()> {
+        self.conn.execute(
+            &format!(
+                "INSERT INTO job ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+                JOB_COLUMNS
+            ),
+            params![
+                job.id,
+                job.kind,
+                job.gallery_id,
+                job.request_id,
+                job.batch_id,
+                job.payload,
+                job.status,
+                job.attempts,
+                job.max_attempts,
+                job.error,
+                job.result,
+                job.run_after,
+                job.input_tokens,
+                job.output_tokens,
+                job.create_at,
+                job.update_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_by_id(&self, id: &str) -> Result<Option<Job>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM job WHERE id = ?1", JOB_COLUMNS),
+                [id],
+                map_job,
+            )
+            .optional()
+    }
+
+    /// 按创建时间倒序列出任务，status 为空时不过滤
+    pub fn list(&self, status: Option<&str>, limit: u32) -> Result<Vec<Job>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM job WHERE (?1 IS NULL OR status = ?1)
+             ORDER BY create_at DESC, id DESC LIMIT ?2",
+            JOB_COLUMNS
+        ))?;
+
+        let jobs = stmt.query_map(params![status, limit], map_job)?;
+
+        jobs.collect()
+    }
+
+    pub fn get_by_gallery_id(&self, gallery_id: &str) -> Result<Vec<Job>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM job WHERE gallery_id = ?1 ORDER BY create_at ASC",
+            JOB_COLUMNS
+        ))?;
+
+        let jobs = stmt.query_map([gallery_id], map_job)?;
+
+        jobs.collect()
+    }
+
+    pub fn get_by_batch_id(&self, batch_id: &str) -> Result<Vec<Job>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM job WHERE batch_id = ?1 ORDER BY create_at ASC",
+            JOB_COLUMNS
+        ))?;
+
+        let jobs = stmt.query_map([batch_id], map_job)?;
+
+        jobs.collect()
+    }
+
+    /// 领取最早一个到期的待执行任务，标记为执行中并累加尝试次数；
+    /// 所属批次中执行中的任务已达到批次并发数时跳过该批次
+    pub fn claim_next(&self, now: i64) -> Result<Option<Job>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "UPDATE job SET status = ?1, attempts = attempts + 1, update_at = ?3
+                     WHERE id = (
+                         SELECT id FROM job AS pending
+                         WHERE status = ?2 AND run_after <= ?3
+                         AND (
+                             batch_id IS NULL
+                             OR (SELECT COUNT(*) FROM job AS running
+                                 WHERE running.batch_id = pending.batch_id AND running.status = ?1)
+                                < (SELECT concurrency FROM batch WHERE batch.id = pending.batch_id)
+                         )
+                         ORDER BY create_at ASC, id ASC LIMIT 1
+                     )
+                     RETURNING {}",
+                    JOB_COLUMNS
+                ),
+                params![JOB_STATUS_RUNNING, JOB_STATUS_PENDING, now],
+                map_job,
+            )
+            .optional()
+    }
+
+    /// 最近一个待执行任务的到期时间，用于决定工作线程的等待时长
+    pub fn next_run_after(&self) -> Result<Option<i64>> {
+        self.conn.query_row(
+            "SELECT MIN(run_after) FROM job WHERE status = ?1",
+            [JOB_STATUS_PENDING],
+            |row| row.get(0),
+        )
+    }
+
+    /// 结束任务，只更新执行中或待执行的任务，返回是否更新成功
+    pub fn finish(
+        &self,
+        id: &str,
+        status: &str,
+        result: Option<&str>,
+        error: Option<&str>,
+        now: i64,
+    ) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE job SET status = ?2, result = ?3, error = ?4, update_at = ?5
+             WHERE id = ?1 AND status IN (?6, ?7)",
+            params![id, status, result, error, now, JOB_STATUS_PENDING, JOB_STATUS_RUNNING],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// 累加任务的 token 用量
+    pub fn add_tokens(&self, id: &str, input_tokens: i64, output_tokens: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE job SET input_tokens = input_tokens + ?2, output_tokens = output_tokens + ?3
+             WHERE id = ?1",
+            params![id, input_tokens, output_tokens],
+        )?;
+        Ok(())
+    }
+
+    /// 执行失败后放回队列，run_after 之后再次执行
+    pub fn schedule_retry(&self, id: &str, error: &str, run_after: i64, now: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE job SET status = ?2, error = ?3, run_after = ?4, update_at = ?5
+             WHERE id = ?1 AND status = ?6",
+            params![id, JOB_STATUS_PENDING, error, run_after, now, JOB_STATUS_RUNNING],
+        )?;
+        Ok(())
+    }
+
+    /// 失败的任务按原参数重新排队，尝试次数清零，返回是否更新成功
+    pub fn requeue(&self, id: &str, now: i64) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE job SET status = ?2, attempts = 0, error = NULL, result = NULL, run_after = ?3, update_at = ?3
+             WHERE id = ?1 AND status = ?4",
+            params![id, JOB_STATUS_PENDING, now, JOB_STATUS_FAILED],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// 应用退出时仍在执行的任务放回队列，返回恢复的任务数
+    pub fn reset_running(&self, now: i64) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE job SET status = ?1, run_after = ?2, update_at = ?2 WHERE status = ?3",
+            params![JOB_STATUS_PENDING, now, JOB_STATUS_RUNNING],
+        )
+    }
+
+    /// 按任务 ID、图库 ID 或请求 ID 查找未结束的任务
+    pub fn find_unfinished(&self, key: &str) -> Result<Vec<Job>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM job WHERE status IN (?2, ?3)
+             AND (id = ?1 OR gallery_id = ?1 OR request_id = ?1)",
+            JOB_COLUMNS
+        ))?;
+
+        let jobs = stmt.query_map(params![key, JOB_STATUS_PENDING, JOB_STATUS_RUNNING], map_job)?;
+
+        jobs.collect()
+    }
+}
+
+fn map_job(row: &Row) -> Result<Job> {
+    Ok(Job {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        gallery_id: row.get(2)?,
+        request_id: row.get(3)?,
+        batch_id: row.get(4)?,
+        payload: row.get(5)?,
+        status: row.get(6)?,
+        attempts: row.get(7)?,
+        max_attempts: row.get(8)?,
+        error: row.get(9)?,
+        result: row.get(10)?,
+        run_after: row.get(11)?,
+        input_tokens: row.get(12)?,
+        output_tokens: row.get(13)?,
+        create_at: row.get(14)?,
+        update_at: row.get(15)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{migration, BlobStore};
+
+    fn job(id: &str, create_at: i64) -> Job {
+        Job {
+            id: id.to_string(),
+            kind: JOB_KIND_IMAGE_EDIT.to_string(),
+            gallery_id: "g1".to_string(),
+            request_id: None,
+            batch_id: None,
+            payload: "{}".to_string(),
+            status: JOB_STATUS_PENDING.to_string(),
+            attempts: 0,
+            max_attempts: 3,
+            error: None,
+            result: None,
+            run_after: create_at,
+            input_tokens: 0,
+            output_tokens: 0,
+            create_at,
+            update_at: create_at,
+        }
+    }
+
+    #[test]
+    fn test_claim_retry_and_resume() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let blobs = BlobStore::new(std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()))).unwrap();
+        migration::migrate(&mut conn, &blobs).unwrap();
+        // 只测试队列本身，不插入关联的图库记录
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        let jobs = JobRepository::new(&conn);
+        jobs.create(&job("a", 1)).unwrap();
+        jobs.create(&job("b", 2)).unwrap();
+
+        // 先入队的任务先执行
+        let claimed = jobs.claim_next(10).unwrap().unwrap();
+        assert_eq!((claimed.id.as_str(), claimed.status.as_str(), claimed.attempts), ("a", JOB_STATUS_RUNNING, 1));
+
+        // 重试任务在 run_after 之前不会被领取
+        jobs.schedule_retry("a", "timeout", 100, 10).unwrap();
+        assert_eq!(jobs.claim_next(20).unwrap().unwrap().id, "b");
+        assert!(jobs.claim_next(20).unwrap().is_none());
+        assert_eq!(jobs.next_run_after().unwrap(), Some(100));
+
+        // 重启时执行中的任务重新排队
+        assert_eq!(jobs.reset_running(30).unwrap(), 1);
+        assert_eq!(jobs.claim_next(30).unwrap().unwrap().id, "b");
+
+        assert!(jobs.finish("b", JOB_STATUS_SUCCEEDED, Some("{}"), None, 40).unwrap());
+        assert!(!jobs.finish("b", JOB_STATUS_FAILED, None, None, 50).unwrap());
+        assert!(jobs.get_by_id("b").unwrap().unwrap().is_finished());
+
+        // 只有失败的任务可以重新排队
+        assert!(!jobs.requeue("b", 60).unwrap());
+        assert!(jobs.finish("a", JOB_STATUS_FAILED, None, Some("timeout"), 60).unwrap());
+        assert!(jobs.requeue("a", 70).unwrap());
+        let requeued = jobs.claim_next(70).unwrap().unwrap();
+        assert_eq!((requeued.id.as_str(), requeued.attempts, requeued.error), ("a", 1, None));
+    }
+
+    #[test]
+    fn test_claim_respects_batch_concurrency() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let blobs = BlobStore::new(std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()))).unwrap();
+        migration::migrate(&mut conn, &blobs).unwrap();
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute(
+            "INSERT INTO batch (id, prompt, concurrency, total, create_at) VALUES ('batch', 'p', 1, 2, 0)",
+            [],
+        )
+        .unwrap();
+        let jobs = JobRepository::new(&conn);
+        for (id, create_at) in [("a", 1), ("b", 2)] {
+            jobs.create(&Job { batch_id: Some("batch".to_string()), ..job(id, create_at) }).unwrap();
+        }
+        jobs.create(&job("c", 3)).unwrap();
+
+        // 批次并发为 1，a 执行中时跳过 b，先执行批次外的 c
+        assert_eq!(jobs.claim_next(10).unwrap().unwrap().id, "a");
+        assert_eq!(jobs.claim_next(10).unwrap().unwrap().id, "c");
+        assert!(jobs.claim_next(10).unwrap().is_none());
+
+        jobs.finish("a", JOB_STATUS_SUCCEEDED, None, None, 20).unwrap();
+        assert_eq!(jobs.claim_next(20).unwrap().unwrap().id, "b");
+    }
+}
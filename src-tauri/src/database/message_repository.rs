@@ -8,6 +8,11 @@ pub struct Message {
     pub role: String,
     pub content: String,
     pub create_at: i64,
+    /// 用户消息对应编辑的输入版本，助手消息和旧消息为空
+    pub base_version_id: Option<String>,
+    /// 用户消息编辑时应用的风格，助手消息和旧消息为空
+    #[serde(default)]
+    pub style_name: Option<String>,
 }
 
 pub struct MessageRepository<'conn> {
@@ -22,14 +27,16 @@ impl<'conn> MessageRepository<'conn> {
 
     pub fn create(&self, message: &Message) -> Result<()> {
         self.conn.execute(
-            "INSERT INTO message (id, gallery_id, role, content, create_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO message (id, gallery_id, role, content, create_at, base_version_id, style_name)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![
                 message.id,
                 message.gallery_id,
                 message.role,
                 message.content,
-                message.create_at
+                message.create_at,
+                message.base_version_id,
+                message.style_name
             ],
         )?;
         Ok(())
@@ -37,9 +44,9 @@ impl<'conn> MessageRepository<'conn> {
 
     pub fn get_by_gallery_id(&self, gallery_id: &str) -> Result<Vec<Message>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, gallery_id, role, content, create_at
+            "SELECT id, gallery_id, role, content, create_at, base_version_id, style_name
              FROM message WHERE gallery_id = ?1
-             ORDER BY create_at ASC"
+             ORDER BY create_at ASC, rowid ASC"
         )?;
 
         let messages = stmt.query_map([gallery_id], |row| {
@@ -49,6 +56,8 @@ impl<'conn> MessageRepository<'conn> {
                 role: row.get(2)?,
                 content: row.get(3)?,
                 create_at: row.get(4)?,
+                base_version_id: row.get(5)?,
+                style_name: row.get(6)?,
             })
         })?;
 
@@ -57,7 +66,7 @@ impl<'conn> MessageRepository<'conn> {
 
     pub fn get_by_id(&self, id: &str) -> Result<Option<Message>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, gallery_id, role, content, create_at
+            "SELECT id, gallery_id, role, content, create_at, base_version_id, style_name
              FROM message WHERE id = ?1"
         )?;
 
@@ -68,12 +77,38 @@ impl<'conn> MessageRepository<'conn> {
                 role: row.get(2)?,
                 content: row.get(3)?,
                 create_at: row.get(4)?,
+                base_version_id: row.get(5)?,
+                style_name: row.get(6)?,
             })
         })?;
 
         messages.next().transpose()
     }
 
+    pub fn update_content(&self, id: &str, content: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE message SET content = ?2 WHERE id = ?1",
+            params![id, content],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete(&self, id: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM message WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    /// 删除指定消息及之后的消息，用于从指定消息重新生成。
+    /// 按 (create_at, rowid) 比较先后，同一毫秒内的消息按写入顺序区分
+    pub fn delete_from(&self, gallery_id: &str, message_id: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM message WHERE gallery_id = ?1
+             AND (create_at, rowid) >= (SELECT create_at, rowid FROM message WHERE id = ?2)",
+            params![gallery_id, message_id],
+        )?;
+        Ok(())
+    }
+
     pub fn delete_by_gallery_id(&self, gallery_id: &str) -> Result<()> {
         self.conn.execute("DELETE FROM message WHERE gallery_id = ?1", [gallery_id])?;
         Ok(())
@@ -81,18 +116,20 @@ impl<'conn> MessageRepository<'conn> {
 
     pub fn get_latest_by_gallery_id(&self, gallery_id: &str, limit: usize) -> Result<Vec<Message>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, gallery_id, role, content, create_at
+            "SELECT id, gallery_id, role, content, create_at, base_version_id, style_name
              FROM message WHERE gallery_id = ?1
-             ORDER BY create_at DESC LIMIT ?2"
+             ORDER BY create_at DESC, rowid DESC LIMIT ?2"
         )?;
 
-        let messages = stmt.query_map([gallery_id, limit.to_string().as_str()], |row| {
+        let messages = stmt.query_map(params![gallery_id, limit as i64], |row| {
             Ok(Message {
                 id: row.get(0)?,
                 gallery_id: row.get(1)?,
                 role: row.get(2)?,
                 content: row.get(3)?,
                 create_at: row.get(4)?,
+                base_version_id: row.get(5)?,
+                style_name: row.get(6)?,
             })
         })?;
 
@@ -100,4 +137,43 @@ impl<'conn> MessageRepository<'conn> {
         result.reverse(); // Reverse to get chronological order
         Ok(result)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{migration, BlobStore};
+
+    fn message(id: &str, role: &str) -> Message {
+        Message {
+            id: id.to_string(),
+            gallery_id: "g1".to_string(),
+            role: role.to_string(),
+            content: id.to_string(),
+            create_at: 1,
+            base_version_id: None,
+            style_name: None,
+        }
+    }
+
+    #[test]
+    fn test_same_millisecond_order() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let blobs = BlobStore::new(std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()))).unwrap();
+        migration::migrate(&mut conn, &blobs).unwrap();
+        // 只测试消息本身，不插入关联的图库记录
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        let messages = MessageRepository::new(&conn);
+        // 同一毫秒写入的消息按写入顺序排列，id 的字典序与写入顺序相反
+        for (id, role) in [("d", "user"), ("c", "assistant"), ("b", "user"), ("a", "assistant")] {
+            messages.create(&message(id, role)).unwrap();
+        }
+
+        let ids = |list: Vec<Message>| list.into_iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(ids(messages.get_by_gallery_id("g1").unwrap()), ["d", "c", "b", "a"]);
+        assert_eq!(ids(messages.get_latest_by_gallery_id("g1", 2).unwrap()), ["b", "a"]);
+
+        // 只删除指定消息及之后写入的消息
+        messages.delete_from("g1", "b").unwrap();
+        assert_eq!(ids(messages.get_by_gallery_id("g1").unwrap()), ["d", "c"]);
+    }
+}
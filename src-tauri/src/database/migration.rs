@@ -0,0 +1,332 @@
+use rusqlite::{ffi, params, Connection, Error, Result};
+
+use super::blob_store::BlobStore;
+use crate::image_ops::metadata::extract_metadata;
+
+/// 一次结构变更，version 从 1 开始连续递增
+struct Migration {
+    version: i32,
+    description: &'static str,
+    up: fn(&Connection, &BlobStore) -> Result<()>,
+}
+
+/// 按版本顺序排列的全部迁移，只允许在末尾追加
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "初始表结构",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0001_init.sql")),
+    },
+    Migration {
+        version: 2,
+        description: "多供应商设置、图库状态及输出 token 列更名",
+        up: |conn, _| migrate_provider_and_status(conn),
+    },
+    Migration {
+        version: 3,
+        description: "图片移出数据库，改存 blob 仓库",
+        up: migrate_images_to_blob_store,
+    },
+    Migration {
+        version: 4,
+        description: "图库记录风格列及列表查询索引",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0004_gallery_list_indexes.sql")),
+    },
+    Migration {
+        version: 5,
+        description: "图库编辑版本树",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0005_gallery_version.sql")),
+    },
+    Migration {
+        version: 6,
+        description: "后台任务队列",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0006_job.sql")),
+    },
+    Migration {
+        version: 7,
+        description: "批量编辑",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0007_batch.sql")),
+    },
+    Migration {
+        version: 8,
+        description: "供应商图片预处理配置",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0008_preprocess_setting.sql")),
+    },
+    Migration {
+        version: 9,
+        description: "上传前元数据白名单",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0009_metadata_whitelist.sql")),
+    },
+    Migration {
+        version: 10,
+        description: "图库原图元数据列",
+        up: migrate_gallery_metadata,
+    },
+    Migration {
+        version: 11,
+        description: "图库缩略图",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0011_gallery_thumbnail.sql")),
+    },
+    Migration {
+        version: 12,
+        description: "图库编辑待处理状态及失败原因",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0012_gallery_error.sql")),
+    },
+    Migration {
+        version: 13,
+        description: "供应商调用用量记录",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0013_usage_event.sql")),
+    },
+    Migration {
+        version: 14,
+        description: "用量统计时区",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0014_setting_timezone.sql")),
+    },
+    Migration {
+        version: 15,
+        description: "消息记录编辑的输入版本",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0015_message_base_version.sql")),
+    },
+    Migration {
+        version: 16,
+        description: "消息记录编辑的风格",
+        up: |conn, _| conn.execute_batch(include_str!("migrations/0016_message_style.sql")),
+    },
+];
+
+/// 当前程序支持的数据库版本
+pub const SCHEMA_VERSION: i32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// 将数据库升级到 SCHEMA_VERSION，每个迁移在独立事务中执行并同步写入 user_version
+pub fn migrate(conn: &mut Connection, blobs: &BlobStore) -> Result<()> {
+    let current: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    if current > SCHEMA_VERSION {
+        return Err(migration_error(format!(
+            "数据库版本 {} 高于当前程序支持的版本 {}，请升级应用后再打开",
+            current, SCHEMA_VERSION
+        )));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.transaction()?;
+        (migration.up)(&tx, blobs)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+        log::info!("数据库已迁移到版本 {}: {}", migration.version, migration.description);
+    }
+
+    Ok(())
+}
+
+// 引入 user_version 之前的版本会在启动时补列，这里按列是否存在决定是否执行
+fn migrate_provider_and_status(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "setting", "image_model")? {
+        conn.execute(
+            "ALTER TABLE setting ADD COLUMN image_model TEXT NOT NULL DEFAULT 'gpt-image-1'",
+            [],
+        )?;
+    }
+    if !has_column(conn, "setting", "provider")? {
+        conn.execute(
+            "ALTER TABLE setting ADD COLUMN provider TEXT NOT NULL DEFAULT 'openai'",
+            [],
+        )?;
+    }
+    if !has_column(conn, "gallery", "status")? {
+        conn.execute(
+            "ALTER TABLE gallery ADD COLUMN status TEXT NOT NULL DEFAULT 'succeeded'",
+            [],
+        )?;
+    }
+    if has_column(conn, "gallery", "total_ouput_tokens")? {
+        conn.execute(
+            "ALTER TABLE gallery RENAME COLUMN total_ouput_tokens TO total_output_tokens",
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+// 将 data URL 写入 blob 仓库，gallery 表只保留哈希和元信息
+fn migrate_images_to_blob_store(conn: &Connection, blobs: &BlobStore) -> Result<()> {
+    conn.execute_batch(include_str!("migrations/0003_gallery_blob_columns.sql"))?;
+
+    let rows = {
+        let mut stmt = conn.prepare("SELECT id, origin_image, effect_image FROM gallery")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+
+    // 任意一张图片迁移失败都返回错误回滚整个迁移，保留原列以便修复后重试
+    for (id, origin_image, effect_image) in rows {
+        let image = blobs
+            .put_data_url(&origin_image)
+            .map_err(|e| migration_error(format!("迁移图库 {} 原图失败: {}", id, e)))?;
+        conn.execute(
+            "UPDATE gallery SET origin_hash = ?2, origin_mime = ?3, origin_width = ?4,
+             origin_height = ?5, origin_size = ?6 WHERE id = ?1",
+            params![id, image.hash, image.mime_type, image.width, image.height, image.byte_size],
+        )?;
+
+        if effect_image.is_empty() {
+            continue;
+        }
+        let image = blobs
+            .put_data_url(&effect_image)
+            .map_err(|e| migration_error(format!("迁移图库 {} 效果图失败: {}", id, e)))?;
+        conn.execute(
+            "UPDATE gallery SET effect_hash = ?2, effect_mime = ?3, effect_width = ?4,
+             effect_height = ?5, effect_size = ?6 WHERE id = ?1",
+            params![id, image.hash, image.mime_type, image.width, image.height, image.byte_size],
+        )?;
+    }
+
+    conn.execute_batch(
+        "ALTER TABLE gallery DROP COLUMN origin_image;
+         ALTER TABLE gallery DROP COLUMN effect_image;",
+    )
+}
+
+// 为已有记录补充原图元数据，读取失败的图片保持为空
+fn migrate_gallery_metadata(conn: &Connection, blobs: &BlobStore) -> Result<()> {
+    conn.execute_batch(include_str!("migrations/0010_gallery_metadata.sql"))?;
+
+    let rows = {
+        let mut stmt = conn.prepare("SELECT id, origin_hash FROM gallery WHERE origin_hash != ''")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+
+    for (id, origin_hash) in rows {
+        let bytes = match blobs.get(&origin_hash) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::warn!("读取图库 {} 原图失败: {}", id, e);
+                continue;
+            }
+        };
+        let metadata = extract_metadata(&bytes);
+        conn.execute(
+            "UPDATE gallery SET origin_color_type = ?2, taken_at = ?3, camera_model = ?4, orientation = ?5
+             WHERE id = ?1",
+            params![id, metadata.color_type, metadata.taken_at, metadata.camera_model, metadata.orientation],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn migration_error(message: String) -> Error {
+    Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_ERROR), Some(message))
+}
+
+pub(crate) fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in columns {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_DATA_URL: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8/5+hHgAHggJ/PchI7wAAAABJRU5ErkJggg==";
+
+    fn user_version(conn: &Connection) -> i32 {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap()
+    }
+
+    fn temp_blobs() -> BlobStore {
+        BlobStore::new(std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()))).unwrap()
+    }
+
+    #[test]
+    fn test_migrate_fresh_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let blobs = temp_blobs();
+        migrate(&mut conn, &blobs).unwrap();
+
+        assert_eq!(user_version(&conn), SCHEMA_VERSION);
+        assert!(has_column(&conn, "gallery", "total_output_tokens").unwrap());
+        assert!(has_column(&conn, "setting", "provider").unwrap());
+        assert!(!has_column(&conn, "gallery", "origin_image").unwrap());
+
+        // 重复执行不应有副作用
+        migrate(&mut conn, &blobs).unwrap();
+        assert_eq!(user_version(&conn), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_migrate_legacy_database() {
+        // 模拟启动时补过部分列、但没有 user_version 的旧库
+        let mut conn = Connection::open_in_memory().unwrap();
+        let blobs = temp_blobs();
+        conn.execute_batch(include_str!("migrations/0001_init.sql")).unwrap();
+        conn.execute("ALTER TABLE gallery ADD COLUMN status TEXT NOT NULL DEFAULT 'succeeded'", [])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO gallery (id, origin_image, effect_image, total_input_tokens, total_ouput_tokens, create_at)
+             VALUES ('g1', ?1, '', 10, 20, 0)",
+            [PNG_DATA_URL],
+        )
+        .unwrap();
+
+        migrate(&mut conn, &blobs).unwrap();
+
+        let (output_tokens, origin_hash, origin_width, effect_hash): (i64, String, u32, Option<String>) = conn
+            .query_row(
+                "SELECT total_output_tokens, origin_hash, origin_width, effect_hash FROM gallery WHERE id = 'g1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(output_tokens, 20);
+        assert_eq!(origin_width, 1);
+        assert_eq!(effect_hash, None);
+        let color_type: Option<String> = conn
+            .query_row("SELECT origin_color_type FROM gallery WHERE id = 'g1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(color_type.as_deref(), Some("Rgba8"));
+        // 旧记录的累计用量补入用量记录
+        let usage: (String, i64, i64) = conn
+            .query_row("SELECT operation, input_tokens, output_tokens FROM usage_event WHERE gallery_id = 'g1'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(usage, ("legacy".to_string(), 10, 20));
+        assert!(blobs.path(&origin_hash).unwrap().exists());
+        assert!(has_column(&conn, "setting", "image_model").unwrap());
+    }
+
+    #[test]
+    fn test_rollback_broken_image() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(include_str!("migrations/0001_init.sql")).unwrap();
+        conn.execute(
+            "INSERT INTO gallery (id, origin_image, effect_image, total_input_tokens, total_ouput_tokens, create_at)
+             VALUES ('g1', 'data:image/png;base64,!!!', '', 0, 0, 0)",
+            [],
+        )
+        .unwrap();
+
+        assert!(migrate(&mut conn, &temp_blobs()).is_err());
+        // 失败的迁移整体回滚，原图列仍然保留
+        assert!(has_column(&conn, "gallery", "origin_image").unwrap());
+        assert!(user_version(&conn) < 3);
+    }
+
+    #[test]
+    fn test_refuse_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+        assert!(migrate(&mut conn, &temp_blobs()).is_err());
+    }
+}
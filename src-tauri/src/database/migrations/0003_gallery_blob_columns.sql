@@ -0,0 +1,14 @@
+-- 图片内容存放在 blob 仓库，这里只记录哈希和元信息
+ALTER TABLE gallery ADD COLUMN origin_hash TEXT NOT NULL DEFAULT '';
+ALTER TABLE gallery ADD COLUMN origin_mime TEXT NOT NULL DEFAULT '';
+ALTER TABLE gallery ADD COLUMN origin_width INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE gallery ADD COLUMN origin_height INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE gallery ADD COLUMN origin_size INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE gallery ADD COLUMN effect_hash TEXT;
+ALTER TABLE gallery ADD COLUMN effect_mime TEXT;
+ALTER TABLE gallery ADD COLUMN effect_width INTEGER;
+ALTER TABLE gallery ADD COLUMN effect_height INTEGER;
+ALTER TABLE gallery ADD COLUMN effect_size INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_gallery_origin_hash ON gallery(origin_hash);
+CREATE INDEX IF NOT EXISTS idx_gallery_effect_hash ON gallery(effect_hash);
@@ -0,0 +1,11 @@
+-- 记录编辑时使用的风格，便于按风格筛选
+ALTER TABLE gallery ADD COLUMN style_name TEXT;
+
+-- 列表分页按 (排序键, id) 定位，筛选列与创建时间组合建索引
+CREATE INDEX IF NOT EXISTS idx_gallery_create_at_id ON gallery(create_at, id);
+CREATE INDEX IF NOT EXISTS idx_gallery_total_tokens ON gallery((total_input_tokens + total_output_tokens), id);
+CREATE INDEX IF NOT EXISTS idx_gallery_status_create_at ON gallery(status, create_at, id);
+CREATE INDEX IF NOT EXISTS idx_gallery_style_create_at ON gallery(style_name, create_at, id);
+
+-- 已被 idx_gallery_create_at_id 覆盖
+DROP INDEX IF EXISTS idx_gallery_create_at;
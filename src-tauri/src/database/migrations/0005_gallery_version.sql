@@ -0,0 +1,48 @@
+-- 图库编辑版本树，每次编辑生成一个节点，parent_id 指向编辑前的版本
+CREATE TABLE IF NOT EXISTS gallery_version (
+    id TEXT PRIMARY KEY,
+    gallery_id TEXT NOT NULL,
+    parent_id TEXT,
+    source TEXT NOT NULL,
+    prompt TEXT,
+    style_name TEXT,
+    model TEXT,
+    params TEXT,
+    image_hash TEXT NOT NULL,
+    image_mime TEXT NOT NULL,
+    image_width INTEGER NOT NULL DEFAULT 0,
+    image_height INTEGER NOT NULL DEFAULT 0,
+    image_size INTEGER NOT NULL DEFAULT 0,
+    input_tokens INTEGER NOT NULL DEFAULT 0,
+    output_tokens INTEGER NOT NULL DEFAULT 0,
+    create_at INTEGER NOT NULL,
+    FOREIGN KEY (gallery_id) REFERENCES gallery(id) ON DELETE CASCADE,
+    FOREIGN KEY (parent_id) REFERENCES gallery_version(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_gallery_version_gallery_id ON gallery_version(gallery_id, create_at);
+CREATE INDEX IF NOT EXISTS idx_gallery_version_parent_id ON gallery_version(parent_id);
+CREATE INDEX IF NOT EXISTS idx_gallery_version_image_hash ON gallery_version(image_hash);
+
+ALTER TABLE gallery ADD COLUMN current_version_id TEXT;
+
+-- 为已有记录补建版本：原图作为根节点，已有效果图作为其子节点
+INSERT INTO gallery_version (id, gallery_id, parent_id, source, style_name, image_hash, image_mime,
+    image_width, image_height, image_size, create_at)
+SELECT id || '-v0', id, NULL, 'origin', NULL, origin_hash, origin_mime,
+    origin_width, origin_height, origin_size, create_at
+FROM gallery;
+
+INSERT INTO gallery_version (id, gallery_id, parent_id, source, prompt, style_name, image_hash, image_mime,
+    image_width, image_height, image_size, input_tokens, output_tokens, create_at)
+SELECT g.id || '-v1', g.id, g.id || '-v0', 'ai',
+    (SELECT content FROM message m WHERE m.gallery_id = g.id AND m.role = 'user' ORDER BY m.create_at LIMIT 1),
+    g.style_name, g.effect_hash, g.effect_mime, g.effect_width, g.effect_height, g.effect_size,
+    g.total_input_tokens, g.total_output_tokens, g.create_at
+FROM gallery g
+WHERE g.effect_hash IS NOT NULL;
+
+UPDATE gallery SET current_version_id = CASE
+    WHEN effect_hash IS NOT NULL THEN id || '-v1'
+    ELSE id || '-v0'
+END;
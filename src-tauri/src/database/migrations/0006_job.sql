@@ -0,0 +1,25 @@
+-- 后台任务队列，AI 编辑请求入队后由工作线程执行，应用重启后继续未完成的任务
+CREATE TABLE IF NOT EXISTS job (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    gallery_id TEXT NOT NULL,
+    request_id TEXT,
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 1,
+    error TEXT,
+    result TEXT,
+    run_after INTEGER NOT NULL DEFAULT 0,
+    create_at INTEGER NOT NULL,
+    update_at INTEGER NOT NULL,
+    FOREIGN KEY (gallery_id) REFERENCES gallery(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_job_status_run_after ON job(status, run_after, create_at);
+CREATE INDEX IF NOT EXISTS idx_job_gallery_id ON job(gallery_id, create_at);
+CREATE INDEX IF NOT EXISTS idx_job_request_id ON job(request_id);
+
+-- 任务队列的工作线程数和失败重试次数
+ALTER TABLE setting ADD COLUMN job_workers INTEGER NOT NULL DEFAULT 2;
+ALTER TABLE setting ADD COLUMN job_max_retries INTEGER NOT NULL DEFAULT 3;
@@ -0,0 +1,31 @@
+-- 批量编辑，每个输入对应一个 batch_item，成功入队的输入关联一个编辑任务
+CREATE TABLE IF NOT EXISTS batch (
+    id TEXT PRIMARY KEY,
+    prompt TEXT NOT NULL,
+    style_name TEXT,
+    concurrency INTEGER NOT NULL,
+    total INTEGER NOT NULL,
+    create_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS batch_item (
+    id TEXT PRIMARY KEY,
+    batch_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    input TEXT NOT NULL,
+    gallery_id TEXT,
+    job_id TEXT,
+    -- 入队前失败（如文件无法读取）的原因，入队后的失败记录在 job.error
+    error TEXT,
+    FOREIGN KEY (batch_id) REFERENCES batch(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_batch_item_batch_id ON batch_item(batch_id, position);
+CREATE INDEX IF NOT EXISTS idx_batch_create_at ON batch(create_at);
+
+-- 任务所属批次及用量，用于批次内限流和汇总
+ALTER TABLE job ADD COLUMN batch_id TEXT;
+ALTER TABLE job ADD COLUMN input_tokens INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE job ADD COLUMN output_tokens INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_job_batch_id ON job(batch_id, status);
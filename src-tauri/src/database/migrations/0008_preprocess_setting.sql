@@ -0,0 +1,11 @@
+-- 上传前图片预处理配置，每个供应商一行，没有记录时使用供应商默认配置
+CREATE TABLE IF NOT EXISTS preprocess_setting (
+    provider TEXT PRIMARY KEY,
+    sniff_mime INTEGER NOT NULL DEFAULT 1,
+    apply_orientation INTEGER NOT NULL DEFAULT 1,
+    -- 长边上限，为空时不缩放
+    max_edge INTEGER,
+    output_format TEXT NOT NULL DEFAULT 'auto',
+    quality INTEGER NOT NULL DEFAULT 85,
+    update_at INTEGER NOT NULL
+);
@@ -0,0 +1,2 @@
+-- 上传前保留的元数据类别，逗号分隔，默认只保留色彩配置文件
+ALTER TABLE setting ADD COLUMN metadata_whitelist TEXT NOT NULL DEFAULT 'icc_profile';
@@ -0,0 +1,9 @@
+-- 导入时从原图解析的元数据，尺寸、格式和文件大小已记录在 origin_* 列中
+ALTER TABLE gallery ADD COLUMN origin_color_type TEXT;
+-- EXIF 拍摄时间，YYYY-MM-DDTHH:MM:SS 格式的相机本地时间，可直接按字符串比较
+ALTER TABLE gallery ADD COLUMN taken_at TEXT;
+ALTER TABLE gallery ADD COLUMN camera_model TEXT;
+ALTER TABLE gallery ADD COLUMN orientation INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_gallery_taken_at ON gallery(taken_at, id);
+CREATE INDEX IF NOT EXISTS idx_gallery_long_edge ON gallery(MAX(origin_width, origin_height), id);
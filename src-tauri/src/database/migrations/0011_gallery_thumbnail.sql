@@ -0,0 +1,19 @@
+-- 图库缩略图，每个规格一行，图片存放在 blob 仓库
+-- source_hash 为生成时所用的图片（效果图或原图），与当前图片不一致时需要重新生成
+CREATE TABLE IF NOT EXISTS gallery_thumbnail (
+    gallery_id TEXT NOT NULL,
+    size TEXT NOT NULL,
+    source_hash TEXT NOT NULL,
+    -- 生成失败时为空并记录 error，避免反复重试同一张图片
+    image_hash TEXT,
+    image_mime TEXT,
+    image_width INTEGER,
+    image_height INTEGER,
+    image_size INTEGER,
+    error TEXT,
+    create_at INTEGER NOT NULL,
+    PRIMARY KEY (gallery_id, size),
+    FOREIGN KEY (gallery_id) REFERENCES gallery(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_gallery_thumbnail_image_hash ON gallery_thumbnail(image_hash);
@@ -0,0 +1,10 @@
+-- 最近一次编辑失败的原因，重新编辑或成功后清空
+ALTER TABLE gallery ADD COLUMN error TEXT;
+
+-- 排队中的记录标记为 pending，任务已结束却仍停留在 processing 的记录标记为失败
+UPDATE gallery SET status = 'pending'
+WHERE status = 'processing'
+  AND EXISTS (SELECT 1 FROM job WHERE job.gallery_id = gallery.id AND job.status = 'pending');
+UPDATE gallery SET status = 'failed', error = '编辑任务已中断'
+WHERE status = 'processing'
+  AND NOT EXISTS (SELECT 1 FROM job WHERE job.gallery_id = gallery.id AND job.status IN ('pending', 'running'));
@@ -0,0 +1,23 @@
+-- 每次供应商调用的用量记录，图库记录删除后保留用量，仅清空关联
+CREATE TABLE IF NOT EXISTS usage_event (
+    id TEXT PRIMARY KEY,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    gallery_id TEXT,
+    input_tokens INTEGER NOT NULL DEFAULT 0,
+    output_tokens INTEGER NOT NULL DEFAULT 0,
+    latency_ms INTEGER NOT NULL DEFAULT 0,
+    create_at INTEGER NOT NULL,
+    FOREIGN KEY (gallery_id) REFERENCES gallery(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_usage_event_create_at ON usage_event(create_at);
+CREATE INDEX IF NOT EXISTS idx_usage_event_gallery_id ON usage_event(gallery_id);
+
+-- 此前的用量只累计在图库记录上，按记录创建时间补一条汇总事件
+INSERT INTO usage_event (id, provider, model, operation, gallery_id, input_tokens, output_tokens, latency_ms, create_at)
+SELECT lower(hex(randomblob(16))), COALESCE((SELECT provider FROM setting LIMIT 1), ''), '', 'legacy', id,
+       total_input_tokens, total_output_tokens, 0, create_at
+FROM gallery
+WHERE total_input_tokens + total_output_tokens > 0;
@@ -0,0 +1,2 @@
+-- 用量统计使用的 IANA 时区名，为空时使用系统时区
+ALTER TABLE setting ADD COLUMN timezone TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,2 @@
+-- 用户消息对应编辑的输入版本，从该消息重新生成时以它为分支起点；旧消息为空
+ALTER TABLE message ADD COLUMN base_version_id TEXT REFERENCES gallery_version(id) ON DELETE SET NULL;
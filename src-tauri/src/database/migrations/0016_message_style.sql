@@ -0,0 +1,2 @@
+-- 用户消息编辑时应用的风格，从该消息重新生成时沿用；旧消息为空
+ALTER TABLE message ADD COLUMN style_name TEXT;
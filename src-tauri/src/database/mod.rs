@@ -1,32 +1,78 @@
-use rusqlite::{Connection, Result};
+use rusqlite::Connection;
 use std::path::Path;
 
+use crate::error::AppError;
+
+mod migration;
+pub mod blob_store;
 pub mod gallery_repository;
 pub mod style_repository;
 pub mod setting_repository;
 pub mod message_repository;
+pub mod gallery_version_repository;
+pub mod job_repository;
+pub mod batch_repository;
+pub mod preprocess_setting_repository;
+pub mod thumbnail_repository;
+pub mod usage_repository;
 
-pub use gallery_repository::{GalleryRepository, Gallery};
+pub use gallery_repository::{
+    GalleryRepository, Gallery, GalleryCursor, GalleryFilter, GallerySort,
+    GALLERY_STATUS_CANCELLED, GALLERY_STATUS_FAILED, GALLERY_STATUS_PENDING, GALLERY_STATUS_PROCESSING,
+    GALLERY_STATUS_SUCCEEDED,
+};
 pub use style_repository::{StyleRepository, Style};
 pub use setting_repository::{SettingRepository, Setting};
 pub use message_repository::{MessageRepository, Message};
+pub use blob_store::{BlobStore, StoredImage};
+pub use gallery_version_repository::{
+    GalleryVersionRepository, GalleryVersion, VERSION_SOURCE_AI, VERSION_SOURCE_LOCAL,
+    VERSION_SOURCE_ORIGIN,
+};
+pub use job_repository::{
+    JobRepository, Job, JOB_KIND_IMAGE_EDIT, JOB_STATUS_CANCELLED, JOB_STATUS_FAILED,
+    JOB_STATUS_PENDING, JOB_STATUS_RUNNING, JOB_STATUS_SUCCEEDED,
+};
+pub use batch_repository::{BatchRepository, Batch, BatchFailure, BatchItem};
+pub use preprocess_setting_repository::{PreprocessSettingRepository, PreprocessSetting};
+pub use thumbnail_repository::{ThumbnailRepository, GalleryThumbnail, StaleThumbnail};
+pub use usage_repository::{
+    UsageRepository, UsageEvent, UsageBucket, DailyUsage, UsageGroupBy, USAGE_OPERATION_EDIT_IMAGE,
+    USAGE_OPERATION_GENERATE_STYLE, USAGE_OPERATION_PROCESS_IMAGE,
+};
 
 pub struct Database {
     conn: Connection,
+    blobs: BlobStore,
 }
 
 impl Database {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let conn = Connection::open(path)?;
-        let db = Self { conn };
-        db.init_tables()?;
-        Ok(db)
+    pub fn new<P: AsRef<Path>, B: AsRef<Path>>(path: P, blob_dir: B) -> Result<Self, String> {
+        let blobs = BlobStore::new(blob_dir)
+            .map_err(|e| format!("Failed to create blob store: {}", e))?;
+        let mut conn = Connection::open(path)
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        // SQLite 默认不校验外键，需要每个连接单独开启，ON DELETE CASCADE 才会生效
+        conn.pragma_update(None, "foreign_keys", true)
+            .map_err(|e| format!("Failed to enable foreign keys: {}", e))?;
+        migration::migrate(&mut conn, &blobs)
+            .map_err(|e| format!("Failed to migrate database: {}", e))?;
+        Ok(Self { conn, blobs })
+    }
+
+    /// 在事务中执行 f，f 返回错误时回滚。图片文件按内容寻址，
+    /// 回滚后残留的文件不影响数据，不在事务范围内
+    pub fn transaction<T, E: From<AppError>>(&self, f: impl FnOnce(&Database) -> Result<T, E>) -> Result<T, E> {
+        let tx = self.conn.unchecked_transaction()
+            .map_err(|e| AppError::database("Failed to begin transaction", e))?;
+        let value = f(self)?;
+        tx.commit().map_err(|e| AppError::database("Failed to commit transaction", e))?;
+        Ok(value)
     }
 
-    fn init_tables(&self) -> Result<()> {
-        let schema = include_str!("schema.sql");
-        self.conn.execute_batch(schema)?;
-        Ok(())
+    /// 图片文件仓库
+    pub fn blobs(&self) -> &BlobStore {
+        &self.blobs
     }
 
     pub fn gallery(&self) -> GalleryRepository {
@@ -44,4 +90,28 @@ impl Database {
     pub fn message(&self) -> MessageRepository {
         MessageRepository::new(&self.conn)
     }
+
+    pub fn gallery_version(&self) -> GalleryVersionRepository {
+        GalleryVersionRepository::new(&self.conn)
+    }
+
+    pub fn job(&self) -> JobRepository {
+        JobRepository::new(&self.conn)
+    }
+
+    pub fn batch(&self) -> BatchRepository {
+        BatchRepository::new(&self.conn)
+    }
+
+    pub fn preprocess_setting(&self) -> PreprocessSettingRepository {
+        PreprocessSettingRepository::new(&self.conn)
+    }
+
+    pub fn thumbnail(&self) -> ThumbnailRepository {
+        ThumbnailRepository::new(&self.conn)
+    }
+
+    pub fn usage(&self) -> UsageRepository {
+        UsageRepository::new(&self.conn)
+    }
 }
\ No newline at end of file
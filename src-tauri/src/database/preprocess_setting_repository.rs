@@ -0,0 +1,87 @@
+use rusqlite::{Connection, OptionalExtension, Result, Row, params};
+use serde::{Deserialize, Serialize};
+
+use crate::ai_service::ProviderKind;
+use crate::image_ops::preprocess::{OutputFormat, PreprocessOptions};
+
+/// 某个供应商保存的图片预处理配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreprocessSetting {
+    pub provider: String,
+    #[serde(flatten)]
+    pub options: PreprocessOptions,
+    pub update_at: i64,
+}
+
+pub struct PreprocessSettingRepository<'conn> {
+    conn: &'conn Connection,
+}
+
+impl<'conn> PreprocessSettingRepository<'conn> {
+    pub fn new(conn: &'conn Connection) -> Self {
+        Self { conn }
+    }
+
+    /// 新增或覆盖供应商的配置
+    pub fn save(&self, setting: &PreprocessSetting) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO preprocess_setting (provider, sniff_mime, apply_orientation, max_edge, output_format,
+             quality, update_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(provider) DO UPDATE SET
+             sniff_mime = excluded.sniff_mime, apply_orientation = excluded.apply_orientation,
+             max_edge = excluded.max_edge, output_format = excluded.output_format,
+             quality = excluded.quality, update_at = excluded.update_at",
+            params![
+                setting.provider,
+                setting.options.sniff_mime,
+                setting.options.apply_orientation,
+                setting.options.max_edge,
+                setting.options.output_format.as_str(),
+                setting.options.quality,
+                setting.update_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, provider: &str) -> Result<Option<PreprocessSetting>> {
+        self.conn
+            .query_row(
+                "SELECT provider, sniff_mime, apply_orientation, max_edge, output_format, quality, update_at
+                 FROM preprocess_setting WHERE provider = ?1",
+                [provider],
+                map_setting,
+            )
+            .optional()
+    }
+
+    /// 供应商当前生效的配置，没有保存过时使用默认配置
+    pub fn get_options(&self, kind: ProviderKind) -> Result<PreprocessOptions> {
+        Ok(self
+            .get(kind.as_str())?
+            .map(|setting| setting.options)
+            .unwrap_or_else(|| PreprocessOptions::for_provider(kind)))
+    }
+
+    /// 删除保存的配置，恢复为默认配置
+    pub fn delete(&self, provider: &str) -> Result<bool> {
+        let affected = self.conn.execute("DELETE FROM preprocess_setting WHERE provider = ?1", [provider])?;
+        Ok(affected > 0)
+    }
+}
+
+fn map_setting(row: &Row) -> Result<PreprocessSetting> {
+    let output_format: String = row.get(4)?;
+    Ok(PreprocessSetting {
+        provider: row.get(0)?,
+        options: PreprocessOptions {
+            sniff_mime: row.get(1)?,
+            apply_orientation: row.get(2)?,
+            max_edge: row.get(3)?,
+            output_format: output_format.parse().unwrap_or(OutputFormat::Auto),
+            quality: row.get(5)?,
+        },
+        update_at: row.get(6)?,
+    })
+}
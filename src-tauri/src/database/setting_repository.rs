@@ -2,12 +2,31 @@ use rusqlite::{Connection, Result, params};
 use serde::{Deserialize, Serialize};
 use chrono::Utc;
 
+use crate::image_ops::privacy::MetadataKind;
+
+/// 任务队列默认配置，与迁移中的列默认值一致
+pub const DEFAULT_JOB_WORKERS: u32 = 2;
+pub const DEFAULT_JOB_MAX_RETRIES: u32 = 3;
+
+/// 默认只保留色彩配置文件，与迁移中的列默认值一致
+pub const DEFAULT_METADATA_WHITELIST: &[MetadataKind] = &[MetadataKind::IccProfile];
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Setting {
     pub id: String,
+    pub provider: String,
     pub api_url: String,
     pub api_key: String,
     pub model: String,
+    pub image_model: String,
+    /// 任务队列工作线程数，重启后生效
+    pub job_workers: u32,
+    /// AI 请求失败后的最大重试次数
+    pub job_max_retries: u32,
+    /// 上传给 AI 供应商前保留的元数据类别，其余全部移除
+    pub metadata_whitelist: Vec<MetadataKind>,
+    /// 用量统计使用的 IANA 时区名，为空时使用系统时区
+    pub timezone: String,
     pub update_at: i64,
 }
 
@@ -22,13 +41,20 @@ impl<'conn> SettingRepository<'conn> {
 
     pub fn create(&self, setting: &Setting) -> Result<()> {
         self.conn.execute(
-            "INSERT INTO setting (id, api_url, api_key, model, update_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO setting (id, provider, api_url, api_key, model, image_model, job_workers,
+             job_max_retries, metadata_whitelist, timezone, update_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
             params![
                 setting.id,
+                setting.provider,
                 setting.api_url,
                 setting.api_key,
                 setting.model,
+                setting.image_model,
+                setting.job_workers,
+                setting.job_max_retries,
+                join_metadata_kinds(&setting.metadata_whitelist),
+                setting.timezone,
                 setting.update_at
             ],
         )?;
@@ -37,17 +63,23 @@ impl<'conn> SettingRepository<'conn> {
 
     pub fn get(&self) -> Result<Option<Setting>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, api_url, api_key, model, update_at
-             FROM setting LIMIT 1"
+            "SELECT id, provider, api_url, api_key, model, image_model, job_workers, job_max_retries,
+             metadata_whitelist, timezone, update_at FROM setting LIMIT 1"
         )?;
 
         let mut settings = stmt.query_map([], |row| {
             Ok(Setting {
                 id: row.get(0)?,
-                api_url: row.get(1)?,
-                api_key: row.get(2)?,
-                model: row.get(3)?,
-                update_at: row.get(4)?,
+                provider: row.get(1)?,
+                api_url: row.get(2)?,
+                api_key: row.get(3)?,
+                model: row.get(4)?,
+                image_model: row.get(5)?,
+                job_workers: row.get(6)?,
+                job_max_retries: row.get(7)?,
+                metadata_whitelist: parse_metadata_kinds(&row.get::<_, String>(8)?),
+                timezone: row.get(9)?,
+                update_at: row.get(10)?,
             })
         })?;
 
@@ -57,13 +89,20 @@ impl<'conn> SettingRepository<'conn> {
     pub fn update(&self, setting: &Setting) -> Result<()> {
         self.conn.execute(
             "UPDATE setting SET
-             api_url = ?2, api_key = ?3, model = ?4, update_at = ?5
+             provider = ?2, api_url = ?3, api_key = ?4, model = ?5, image_model = ?6,
+             job_workers = ?7, job_max_retries = ?8, metadata_whitelist = ?9, timezone = ?10, update_at = ?11
              WHERE id = ?1",
             params![
                 setting.id,
+                setting.provider,
                 setting.api_url,
                 setting.api_key,
                 setting.model,
+                setting.image_model,
+                setting.job_workers,
+                setting.job_max_retries,
+                join_metadata_kinds(&setting.metadata_whitelist),
+                setting.timezone,
                 setting.update_at
             ],
         )?;
@@ -78,13 +117,32 @@ impl<'conn> SettingRepository<'conn> {
         // Create default setting
         let default_setting = Setting {
             id: uuid::Uuid::new_v4().to_string(),
+            provider: "openai".to_string(),
             api_url: "https://api.openai.com/v1".to_string(),
             api_key: String::new(),
             model: "gpt-4o".to_string(),
+            image_model: "gpt-image-1".to_string(),
+            job_workers: DEFAULT_JOB_WORKERS,
+            job_max_retries: DEFAULT_JOB_MAX_RETRIES,
+            metadata_whitelist: DEFAULT_METADATA_WHITELIST.to_vec(),
+            timezone: String::new(),
             update_at: Utc::now().timestamp_millis(),
         };
 
         self.create(&default_setting)?;
         Ok(default_setting)
     }
-}
\ No newline at end of file
+}
+
+fn join_metadata_kinds(kinds: &[MetadataKind]) -> String {
+    kinds.iter().map(|kind| kind.as_str()).collect::<Vec<_>>().join(",")
+}
+
+// 忽略无法识别的类别，按移除处理更安全
+fn parse_metadata_kinds(value: &str) -> Vec<MetadataKind> {
+    value
+        .split(',')
+        .filter(|kind| !kind.trim().is_empty())
+        .filter_map(|kind| kind.parse().ok())
+        .collect()
+}
@@ -0,0 +1,212 @@
+use rusqlite::types::{Type, Value};
+use rusqlite::{Connection, Error, OptionalExtension, Result, Row, params, params_from_iter};
+use serde::{Deserialize, Serialize};
+
+use super::blob_store::StoredImage;
+use crate::image_ops::thumbnail::{ThumbnailSize, THUMBNAIL_SIZES};
+
+/// 图库记录某个规格的缩略图
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GalleryThumbnail {
+    pub gallery_id: String,
+    pub size: ThumbnailSize,
+    /// 生成缩略图所用的图片哈希
+    pub source_hash: String,
+    /// 生成失败时为空
+    pub image: Option<StoredImage>,
+    pub error: Option<String>,
+    pub create_at: i64,
+}
+
+/// 缺少缩略图或缩略图已过期的图库记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleThumbnail {
+    pub gallery_id: String,
+    /// 当前应使用的图片：有效果图时为效果图，否则为原图
+    pub source_hash: String,
+}
+
+const THUMBNAIL_COLUMNS: &str = "t.gallery_id, t.size, t.source_hash, t.image_hash, t.image_mime, t.image_width,
+    t.image_height, t.image_size, t.error, t.create_at";
+
+// 缩略图与当前图片一致且已生成成功
+const CURRENT_CONDITION: &str = "t.source_hash = COALESCE(g.effect_hash, g.origin_hash) AND t.image_hash IS NOT NULL";
+
+pub struct ThumbnailRepository<'conn> {
+    conn: &'conn Connection,
+}
+
+impl<'conn> ThumbnailRepository<'conn> {
+    pub fn new(conn: &'conn Connection) -> Self {
+        Self { conn }
+    }
+
+    /// 新增或覆盖同一规格的缩略图
+    pub fn save(&self, thumbnail: &GalleryThumbnail) -> Result<()> {
+        let image = thumbnail.image.as_ref();
+        self.conn.execute(
+            "INSERT OR REPLACE INTO gallery_thumbnail (gallery_id, size, source_hash, image_hash, image_mime,
+             image_width, image_height, image_size, error, create_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                thumbnail.gallery_id,
+                thumbnail.size.as_str(),
+                thumbnail.source_hash,
+                image.map(|i| &i.hash),
+                image.map(|i| &i.mime_type),
+                image.map(|i| i.width),
+                image.map(|i| i.height),
+                image.map(|i| i.byte_size),
+                thumbnail.error,
+                thumbnail.create_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// 与当前图片一致的缩略图
+    pub fn get_current(&self, gallery_id: &str, size: ThumbnailSize) -> Result<Option<GalleryThumbnail>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM gallery_thumbnail t JOIN gallery g ON g.id = t.gallery_id
+                     WHERE t.gallery_id = ?1 AND t.size = ?2 AND {}",
+                    THUMBNAIL_COLUMNS, CURRENT_CONDITION
+                ),
+                params![gallery_id, size.as_str()],
+                map_thumbnail,
+            )
+            .optional()
+    }
+
+    /// 多条图库记录当前可用的缩略图，用于列表接口一次性查询
+    pub fn get_current_by_gallery_ids(&self, gallery_ids: &[String]) -> Result<Vec<GalleryThumbnail>> {
+        if gallery_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; gallery_ids.len()].join(", ");
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM gallery_thumbnail t JOIN gallery g ON g.id = t.gallery_id
+             WHERE t.gallery_id IN ({}) AND {}",
+            THUMBNAIL_COLUMNS, placeholders, CURRENT_CONDITION
+        ))?;
+
+        let thumbnails = stmt.query_map(
+            params_from_iter(gallery_ids.iter().map(|id| Value::Text(id.clone()))),
+            map_thumbnail,
+        )?;
+
+        thumbnails.collect()
+    }
+
+    /// 缺少任一规格缩略图或缩略图过期的记录，生成失败的记录在图片变化前不再返回
+    pub fn find_stale(&self, limit: u32) -> Result<Vec<StaleThumbnail>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT g.id, COALESCE(g.effect_hash, g.origin_hash) AS source_hash FROM gallery g
+             WHERE g.origin_hash != ''
+               AND (SELECT COUNT(*) FROM gallery_thumbnail t
+                    WHERE t.gallery_id = g.id AND t.source_hash = COALESCE(g.effect_hash, g.origin_hash)) < ?1
+             ORDER BY g.create_at DESC
+             LIMIT ?2"
+        )?;
+
+        let stale = stmt.query_map(params![THUMBNAIL_SIZES.len() as i64, limit], |row| {
+            Ok(StaleThumbnail {
+                gallery_id: row.get(0)?,
+                source_hash: row.get(1)?,
+            })
+        })?;
+
+        stale.collect()
+    }
+
+    /// 图库记录的缩略图文件哈希
+    pub fn get_image_hashes(&self, gallery_id: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT image_hash FROM gallery_thumbnail WHERE gallery_id = ?1 AND image_hash IS NOT NULL"
+        )?;
+
+        let hashes = stmt.query_map([gallery_id], |row| row.get(0))?;
+
+        hashes.collect()
+    }
+
+    pub fn delete_by_gallery_id(&self, gallery_id: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM gallery_thumbnail WHERE gallery_id = ?1", [gallery_id])?;
+        Ok(())
+    }
+}
+
+fn map_thumbnail(row: &Row) -> Result<GalleryThumbnail> {
+    let size: String = row.get(1)?;
+    let size = ThumbnailSize::parse(&size)
+        .ok_or_else(|| Error::FromSqlConversionFailure(1, Type::Text, format!("无效的缩略图规格: {}", size).into()))?;
+    let image = match row.get::<_, Option<String>>(3)? {
+        Some(hash) => Some(StoredImage {
+            hash,
+            mime_type: row.get(4)?,
+            width: row.get(5)?,
+            height: row.get(6)?,
+            byte_size: row.get(7)?,
+        }),
+        None => None,
+    };
+
+    Ok(GalleryThumbnail {
+        gallery_id: row.get(0)?,
+        size,
+        source_hash: row.get(2)?,
+        image,
+        error: row.get(8)?,
+        create_at: row.get(9)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_stale_and_current() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE gallery (id TEXT PRIMARY KEY, origin_hash TEXT NOT NULL, effect_hash TEXT, create_at INTEGER);
+             INSERT INTO gallery VALUES ('g1', 'origin', NULL, 1), ('g2', 'origin', 'effect', 2);",
+        )
+        .unwrap();
+        conn.execute_batch(include_str!("migrations/0011_gallery_thumbnail.sql")).unwrap();
+        let repo = ThumbnailRepository::new(&conn);
+
+        let stale_ids = |repo: &ThumbnailRepository| -> Vec<String> {
+            repo.find_stale(10).unwrap().into_iter().map(|stale| stale.gallery_id).collect()
+        };
+        assert_eq!(stale_ids(&repo), vec!["g2", "g1"]);
+
+        let thumbnail = |gallery_id: &str, size, source_hash: &str, ok: bool| GalleryThumbnail {
+            gallery_id: gallery_id.to_string(),
+            size,
+            source_hash: source_hash.to_string(),
+            image: ok.then(|| StoredImage {
+                hash: format!("{}-{}", source_hash, size.as_str()),
+                mime_type: "image/jpeg".to_string(),
+                width: size.edge(),
+                height: size.edge(),
+                byte_size: 1,
+            }),
+            error: (!ok).then(|| "解码失败".to_string()),
+            create_at: 0,
+        };
+        // g1 全部生成，g2 的缩略图基于原图，效果图更新后视为过期
+        for size in THUMBNAIL_SIZES {
+            repo.save(&thumbnail("g1", size, "origin", size != ThumbnailSize::Large)).unwrap();
+            repo.save(&thumbnail("g2", size, "origin", true)).unwrap();
+        }
+        assert_eq!(stale_ids(&repo), vec!["g2"]);
+
+        let current = repo.get_current_by_gallery_ids(&["g1".to_string(), "g2".to_string()]).unwrap();
+        assert_eq!(current.len(), 2);
+        assert!(current.iter().all(|t| t.gallery_id == "g1" && t.image.is_some()));
+        assert!(repo.get_current("g1", ThumbnailSize::Large).unwrap().is_none());
+        assert!(repo.get_current("g2", ThumbnailSize::Small).unwrap().is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use rusqlite::types::Value;
+use rusqlite::{Connection, Result, params, params_from_iter};
+use serde::{Deserialize, Serialize};
+
+/// 调用类型：对话模型分析图片、图片编辑、生成风格
+pub const USAGE_OPERATION_PROCESS_IMAGE: &str = "process_image";
+pub const USAGE_OPERATION_EDIT_IMAGE: &str = "edit_image";
+pub const USAGE_OPERATION_GENERATE_STYLE: &str = "generate_style";
+
+/// 一次供应商调用的用量，重试只在成功后记录一次，latency_ms 包含重试等待
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageEvent {
+    pub id: String,
+    pub provider: String,
+    pub model: String,
+    pub operation: String,
+    pub gallery_id: Option<String>,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub latency_ms: i64,
+    pub create_at: i64,
+}
+
+/// 用量统计的分组维度
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGroupBy {
+    /// 不分组，只有一条汇总序列
+    #[default]
+    None,
+    Provider,
+    Model,
+    Operation,
+}
+
+impl UsageGroupBy {
+    fn key_expr(&self) -> &'static str {
+        match self {
+            Self::None => "''",
+            Self::Provider => "provider",
+            Self::Model => "model",
+            Self::Operation => "operation",
+        }
+    }
+}
+
+/// 统计区间，通常为本地时区的一天，时间为毫秒时间戳 [start, end)
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageBucket {
+    pub day: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+/// 某个统计区间内某个分组的用量汇总
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DailyUsage {
+    pub day: String,
+    pub key: String,
+    pub calls: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    /// 平均耗时（毫秒）
+    pub latency_ms: i64,
+}
+
+pub struct UsageRepository<'conn> {
+    conn: &'conn Connection,
+}
+
+impl<'conn> UsageRepository<'conn> {
+    pub fn new(conn: &'conn Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn create(&self, event: &UsageEvent) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO usage_event (id, provider, model, operation, gallery_id, input_tokens, output_tokens,
+             latency_ms, create_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                event.id,
+                event.provider,
+                event.model,
+                event.operation,
+                event.gallery_id,
+                event.input_tokens,
+                event.output_tokens,
+                event.latency_ms,
+                event.create_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// [start, end) 时间范围内的输入与输出 token 总量
+    pub fn total_tokens(&self, start: i64, end: i64) -> Result<i64> {
+        self.conn.query_row(
+            "SELECT COALESCE(SUM(input_tokens + output_tokens), 0) FROM usage_event
+             WHERE create_at >= ?1 AND create_at < ?2",
+            params![start, end],
+            |row| row.get(0),
+        )
+    }
+
+    /// 按统计区间和分组汇总的用量，按日期和分组排序。区间由调用方按时区划分，
+    /// 夏令时切换当天的区间长度不是 24 小时
+    pub fn daily_usage(&self, buckets: &[UsageBucket], group_by: UsageGroupBy) -> Result<Vec<DailyUsage>> {
+        if buckets.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["(?, ?, ?)"; buckets.len()].join(", ");
+        let mut stmt = self.conn.prepare(&format!(
+            "WITH bucket(day, start_at, end_at) AS (VALUES {})
+             SELECT bucket.day, {} AS key, COUNT(*),
+                 SUM(input_tokens), SUM(output_tokens), CAST(AVG(latency_ms) AS INTEGER)
+             FROM bucket JOIN usage_event
+                 ON usage_event.create_at >= bucket.start_at AND usage_event.create_at < bucket.end_at
+             GROUP BY bucket.day, key ORDER BY bucket.day, key",
+            placeholders,
+            group_by.key_expr()
+        ))?;
+
+        let values = buckets.iter().flat_map(|bucket| {
+            [Value::Text(bucket.day.clone()), Value::Integer(bucket.start), Value::Integer(bucket.end)]
+        });
+        let usages = stmt.query_map(params_from_iter(values), |row| {
+            Ok(DailyUsage {
+                day: row.get(0)?,
+                key: row.get(1)?,
+                calls: row.get(2)?,
+                input_tokens: row.get(3)?,
+                output_tokens: row.get(4)?,
+                latency_ms: row.get(5)?,
+            })
+        })?;
+
+        usages.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_usage() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE gallery (id TEXT PRIMARY KEY, total_input_tokens INTEGER, total_output_tokens INTEGER, create_at INTEGER);
+             CREATE TABLE setting (provider TEXT);")
+            .unwrap();
+        conn.execute_batch(include_str!("migrations/0013_usage_event.sql")).unwrap();
+        let repo = UsageRepository::new(&conn);
+
+        const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+        let event = |model: &str, operation: &str, tokens: i64, latency_ms: i64, create_at: i64| UsageEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            provider: "openai".to_string(),
+            model: model.to_string(),
+            operation: operation.to_string(),
+            gallery_id: None,
+            input_tokens: tokens,
+            output_tokens: tokens * 2,
+            latency_ms,
+            create_at,
+        };
+        repo.create(&event("gpt-4o", USAGE_OPERATION_PROCESS_IMAGE, 10, 100, 0)).unwrap();
+        repo.create(&event("gpt-image-1", USAGE_OPERATION_EDIT_IMAGE, 20, 300, DAY_MS - 1)).unwrap();
+        repo.create(&event("gpt-4o", USAGE_OPERATION_GENERATE_STYLE, 30, 200, DAY_MS)).unwrap();
+        repo.create(&event("gpt-4o", USAGE_OPERATION_PROCESS_IMAGE, 40, 400, 2 * DAY_MS)).unwrap();
+
+        assert_eq!(repo.total_tokens(0, DAY_MS).unwrap(), 90);
+        assert_eq!(repo.total_tokens(0, 2 * DAY_MS).unwrap(), 180);
+        assert_eq!(repo.total_tokens(3 * DAY_MS, 4 * DAY_MS).unwrap(), 0);
+
+        let usage = |day: &str, key: &str, calls, input_tokens, latency_ms| DailyUsage {
+            day: day.to_string(),
+            key: key.to_string(),
+            calls,
+            input_tokens,
+            output_tokens: input_tokens * 2,
+            latency_ms,
+        };
+        let bucket = |day: &str, start: i64| UsageBucket { day: day.to_string(), start, end: start + DAY_MS };
+        let utc_days = [bucket("1970-01-01", 0), bucket("1970-01-02", DAY_MS)];
+        assert_eq!(repo.daily_usage(&utc_days, UsageGroupBy::None).unwrap(), vec![
+            usage("1970-01-01", "", 2, 30, 200),
+            usage("1970-01-02", "", 1, 30, 200),
+        ]);
+        assert_eq!(repo.daily_usage(&utc_days, UsageGroupBy::Model).unwrap(), vec![
+            usage("1970-01-01", "gpt-4o", 1, 10, 100),
+            usage("1970-01-01", "gpt-image-1", 1, 20, 300),
+            usage("1970-01-02", "gpt-4o", 1, 30, 200),
+        ]);
+
+        // UTC+8 的一天从前一天 16:00 UTC 开始
+        let offset = 8 * 60 * 60 * 1000;
+        let local_days = [bucket("1970-01-01", -offset), bucket("1970-01-02", DAY_MS - offset)];
+        assert_eq!(repo.daily_usage(&local_days, UsageGroupBy::None).unwrap(), vec![
+            usage("1970-01-01", "", 1, 10, 100),
+            usage("1970-01-02", "", 2, 50, 250),
+        ]);
+        assert!(repo.daily_usage(&[], UsageGroupBy::None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+use std::io;
+use std::sync::PoisonError;
+
+use crate::ai_service::AIError;
+
+/// 命令返回给前端的错误，序列化为 {code, message, details, retryable, retry_after_ms}。
+/// 用户可见的提示统一放在 message，原始错误信息放在 details 便于排查
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    /// 数据库读写失败，内容为原始错误
+    Database(String),
+    /// 数据库锁异常，通常是其它线程持锁时崩溃
+    Lock(String),
+    /// 设置缺失或无效，如未配置 API 密钥
+    Config(String),
+    /// 供应商鉴权失败，内容为供应商返回的错误
+    Auth(String),
+    /// 供应商额度或余额不足
+    Quota(String),
+    /// 供应商限流，retry_after_ms 为服务端要求的等待时间
+    RateLimit { details: String, retry_after_ms: Option<u64> },
+    /// 网络请求失败或超时
+    Network(String),
+    /// 请求参数无效
+    Validation(String),
+    /// 请求的记录不存在
+    NotFound(String),
+    /// 供应商返回的其它错误，retryable 表示是否值得重试
+    Provider { details: String, retryable: bool, retry_after_ms: Option<u64> },
+    /// 请求已取消
+    Cancelled,
+    /// 图片编解码、文件读写等其它错误
+    Internal(String),
+}
+
+impl AppError {
+    /// 带上下文的数据库错误，上下文说明失败的操作
+    pub fn database(context: &str, error: impl fmt::Display) -> Self {
+        AppError::Database(format!("{}: {}", context, error))
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        AppError::Validation(message.into())
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound(message.into())
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        AppError::Config(message.into())
+    }
+
+    /// 读取 blob 仓库中的图片失败：文件缺失为 NotFound，哈希无效为 Validation
+    pub fn blob(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => AppError::NotFound(format!("图片文件不存在: {}", error)),
+            io::ErrorKind::InvalidInput => AppError::Validation(error.to_string()),
+            _ => AppError::Internal(format!("读取图片失败: {}", error)),
+        }
+    }
+
+    /// 错误码，前端据此区分错误类型
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Database(_) => "database",
+            AppError::Lock(_) => "lock",
+            AppError::Config(_) => "config",
+            AppError::Auth(_) => "auth",
+            AppError::Quota(_) => "quota",
+            AppError::RateLimit { .. } => "rate_limit",
+            AppError::Network(_) => "network",
+            AppError::Validation(_) => "validation",
+            AppError::NotFound(_) => "not_found",
+            AppError::Provider { .. } => "provider",
+            AppError::Cancelled => "cancelled",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// 面向用户的提示
+    pub fn message(&self) -> String {
+        match self {
+            AppError::Database(_) => "数据库操作失败".to_string(),
+            AppError::Lock(_) => "数据库状态异常，请重启应用".to_string(),
+            AppError::Auth(_) => "API 密钥无效或无权访问，请检查设置".to_string(),
+            AppError::Quota(_) => "API 额度已用完，请检查账户余额".to_string(),
+            AppError::RateLimit { .. } => "请求过于频繁，请稍后重试".to_string(),
+            AppError::Network(_) => "网络请求失败，请检查网络设置".to_string(),
+            AppError::Provider { .. } => "AI 服务返回错误".to_string(),
+            AppError::Cancelled => "请求已取消".to_string(),
+            AppError::Config(message)
+            | AppError::Validation(message)
+            | AppError::NotFound(message)
+            | AppError::Internal(message) => message.clone(),
+        }
+    }
+
+    /// 原始错误信息，提示本身已完整时为空
+    pub fn details(&self) -> Option<&str> {
+        match self {
+            AppError::Database(details)
+            | AppError::Lock(details)
+            | AppError::Auth(details)
+            | AppError::Quota(details)
+            | AppError::RateLimit { details, .. }
+            | AppError::Network(details)
+            | AppError::Provider { details, .. } => Some(details),
+            AppError::Config(_)
+            | AppError::Validation(_)
+            | AppError::NotFound(_)
+            | AppError::Cancelled
+            | AppError::Internal(_) => None,
+        }
+    }
+
+    /// 稍后重试是否可能成功
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AppError::RateLimit { .. } | AppError::Network(_) => true,
+            AppError::Provider { retryable, .. } => *retryable,
+            _ => false,
+        }
+    }
+
+    /// 服务端要求的最短重试等待时间（毫秒）
+    pub fn retry_after_ms(&self) -> Option<u64> {
+        match self {
+            AppError::RateLimit { retry_after_ms, .. } | AppError::Provider { retry_after_ms, .. } => *retry_after_ms,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.details() {
+            Some(details) => write!(f, "{}: {}", self.message(), details),
+            None => f.write_str(&self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 5)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.message())?;
+        state.serialize_field("details", &self.details())?;
+        state.serialize_field("retryable", &self.is_retryable())?;
+        state.serialize_field("retry_after_ms", &self.retry_after_ms())?;
+        state.end()
+    }
+}
+
+impl<T> From<PoisonError<T>> for AppError {
+    fn from(error: PoisonError<T>) -> Self {
+        AppError::Lock(error.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(error: rusqlite::Error) -> Self {
+        AppError::Database(error.to_string())
+    }
+}
+
+impl From<AppError> for String {
+    fn from(error: AppError) -> Self {
+        error.to_string()
+    }
+}
+
+// HTTP 错误已在 ai_service 中归类为统一的错误类型，这里仍按关键字兜底以兼容未归类的错误
+impl From<AIError> for AppError {
+    fn from(error: AIError) -> Self {
+        let retry_after_ms = error.retry_after_ms;
+        let error_type = error.error_type.to_lowercase();
+        let code = error.code.as_deref().unwrap_or_default().to_lowercase();
+        let has = |keywords: &[&str]| keywords.iter().any(|k| error_type.contains(k) || code.contains(k));
+
+        if error_type == "cancelled" {
+            AppError::Cancelled
+        } else if has(&["quota", "billing", "insufficient"]) {
+            AppError::Quota(error.message)
+        } else if has(&["auth", "permission", "api_key", "unauthenticated"]) || code == "401" || code == "403" {
+            AppError::Auth(error.message)
+        } else if has(&["rate_limit", "resource_exhausted"]) || code == "429" {
+            AppError::RateLimit { details: error.message, retry_after_ms }
+        } else if error_type == "network_error" || error_type == "timeout_error" {
+            AppError::Network(error.message)
+        } else if has(&["image_error", "invalid_request", "invalid_argument"]) || code == "400" {
+            AppError::Validation(error.message)
+        } else if error_type == "unsupported_error" {
+            AppError::Config(error.message)
+        } else if error_type.contains("not_found") || code == "404" {
+            AppError::NotFound(error.message)
+        } else {
+            let retryable = error.is_retryable();
+            AppError::Provider { details: error.message, retryable, retry_after_ms }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_ai_error() {
+        let error = |error_type: &str, code: Option<&str>| AIError {
+            error_type: error_type.to_string(),
+            message: "upstream".to_string(),
+            code: code.map(str::to_string),
+            retry_after_ms: None,
+        };
+
+        assert_eq!(AppError::from(error("invalid_request_error", Some("insufficient_quota"))).code(), "quota");
+        assert_eq!(AppError::from(error("authentication_error", Some("401"))).code(), "auth");
+        assert_eq!(AppError::from(error("RESOURCE_EXHAUSTED", Some("429"))).code(), "rate_limit");
+        assert_eq!(AppError::from(error("api_error", Some("429"))).code(), "rate_limit");
+        assert_eq!(AppError::from(error("network_error", None)).code(), "network");
+        assert_eq!(AppError::from(error("timeout_error", None)).code(), "network");
+        assert_eq!(AppError::from(error("invalid_request_error", Some("context_length_exceeded"))).code(), "validation");
+        assert_eq!(AppError::from(error("image_error", None)).code(), "validation");
+        assert_eq!(AppError::from(AIError::cancelled()), AppError::Cancelled);
+
+        let overloaded = AppError::from(error("overloaded_error", Some("529")));
+        assert_eq!(overloaded, AppError::Provider { details: "upstream".to_string(), retryable: true, retry_after_ms: None });
+
+        let rate_limited = AIError { retry_after_ms: Some(20_000), ..error("rate_limit_error", Some("429")) };
+        let value = serde_json::to_value(AppError::from(rate_limited)).unwrap();
+        assert_eq!(value, serde_json::json!({
+            "code": "rate_limit",
+            "message": "请求过于频繁，请稍后重试",
+            "details": "upstream",
+            "retryable": true,
+            "retry_after_ms": 20000,
+        }));
+    }
+}
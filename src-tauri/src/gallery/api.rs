@@ -1,15 +1,39 @@
-use tauri::State;
+use tauri::{AppHandle, State};
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 
-use crate::database::Database;
+use crate::ai_service::TokenUsage;
+use crate::database::{Database, Gallery, GallerySort, GalleryVersion, Message, StoredImage};
+use crate::error::AppError;
+use crate::image_ops::LocalOp;
+use crate::image_ops::thumbnail::ThumbnailSize;
+use crate::job::queue::JobQueue;
 use super::service::GalleryService;
+use super::thumbnail::ThumbnailQueue;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImageEditRequest {
-    pub origin_image: String,
+    /// 新建编辑时必填，继续编辑已有图库时忽略
+    #[serde(default)]
+    pub origin_image: Option<String>,
+    /// 指定时在该图库记录上继续多轮编辑
+    #[serde(default)]
+    pub gallery_id: Option<String>,
+    /// 继续编辑时以该版本为输入，新版本作为它的子节点（分支）；为空时使用当前版本
+    #[serde(default)]
+    pub base_version_id: Option<String>,
     pub prompt: String,
     pub style_name: Option<String>,
+    /// 前端生成的请求 ID，用于在拿到 gallery_id 之前取消请求
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// 候选结果数量，所有候选图作为同一父版本下的兄弟版本保存
+    #[serde(default = "default_candidate_count", alias = "variations")]
+    pub n: u32,
+}
+
+pub fn default_candidate_count() -> u32 {
+    1
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,6 +42,144 @@ pub struct ImageEditResponse {
     pub effect_image: Option<String>,
     pub gallery_id: String,
     pub message: String,
+    /// 本次生成的候选版本 ID，第一个为当前版本
+    #[serde(default)]
+    pub candidate_version_ids: Vec<String>,
+    /// 编辑任务 ID，结果通过任务进度事件通知
+    #[serde(default)]
+    pub job_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListImagesRequest {
+    /// 上一页返回的 next_cursor，为空时从第一页开始
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// 创建时间范围（毫秒时间戳，闭区间）
+    #[serde(default)]
+    pub start_time: Option<i64>,
+    #[serde(default)]
+    pub end_time: Option<i64>,
+    #[serde(default)]
+    pub style_name: Option<String>,
+    /// 总 token 数范围（闭区间）
+    #[serde(default)]
+    pub min_tokens: Option<i64>,
+    #[serde(default)]
+    pub max_tokens: Option<i64>,
+    #[serde(default)]
+    pub status: Option<String>,
+    /// 原图长边像素范围（闭区间）
+    #[serde(default)]
+    pub min_edge: Option<u32>,
+    #[serde(default)]
+    pub max_edge: Option<u32>,
+    /// 原图格式，如 image/jpeg
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    /// 拍摄年份
+    #[serde(default)]
+    pub taken_year: Option<i32>,
+    /// 拍摄日期范围，YYYY-MM-DD（闭区间）
+    #[serde(default)]
+    pub taken_from: Option<String>,
+    #[serde(default)]
+    pub taken_to: Option<String>,
+    /// 相机型号，包含匹配
+    #[serde(default)]
+    pub camera_model: Option<String>,
+    #[serde(default)]
+    pub sort: GallerySort,
+}
+
+/// 缩略图，可通过 gallery://localhost/<id>/thumb/<size> 加载
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Thumbnail {
+    pub size: ThumbnailSize,
+    pub image: StoredImage,
+}
+
+/// 列表项：图库记录及已生成的缩略图，缩略图尚未生成时为空
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GalleryListItem {
+    #[serde(flatten)]
+    pub gallery: Gallery,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListImagesResponse {
+    pub items: Vec<GalleryListItem>,
+    pub next_cursor: Option<String>,
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditMessageRequest {
+    pub id: String,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegenerateRequest {
+    pub message_id: String,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// 重新生成的候选结果数量
+    #[serde(default = "default_candidate_count", alias = "variations")]
+    pub n: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionListResponse {
+    pub current_version_id: Option<String>,
+    pub versions: Vec<GalleryVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BranchVersionRequest {
+    pub gallery_id: String,
+    pub version_id: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub style_name: Option<String>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default = "default_candidate_count", alias = "variations")]
+    pub n: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApplyLocalOpsRequest {
+    pub gallery_id: String,
+    /// 在该版本上应用操作，为空时使用当前版本
+    #[serde(default)]
+    pub base_version_id: Option<String>,
+    pub ops: Vec<LocalOp>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApplyLocalOpsResponse {
+    pub gallery: Gallery,
+    pub version: GalleryVersion,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionChange {
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionDiffResponse {
+    pub from: GalleryVersion,
+    pub to: GalleryVersion,
+    /// 两个版本最近的共同祖先
+    pub common_ancestor_id: Option<String>,
+    pub changes: Vec<VersionChange>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,23 +200,57 @@ pub struct StyleGenerateResponse {
     pub message: String,
 }
 
+/// 流式输出的增量文本事件
+pub const AI_STREAM_DELTA_EVENT: &str = "ai-stream-delta";
+/// 流式输出结束事件，携带完整消息
+pub const AI_STREAM_DONE_EVENT: &str = "ai-stream-done";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIStreamDelta {
+    pub gallery_id: String,
+    pub delta: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIStreamDone {
+    pub gallery_id: String,
+    pub content: String,
+    pub tokens_used: u32,
+    pub usage: TokenUsage,
+}
+
 type DatabaseState = Mutex<Database>;
 
 /// 图片编辑接口
 #[tauri::command]
-pub async fn edit_image(
+pub fn edit_image(
     db: State<'_, DatabaseState>,
+    job_queue: State<'_, JobQueue>,
+    thumbnails: State<'_, ThumbnailQueue>,
     request: ImageEditRequest,
-) -> Result<ImageEditResponse, String> {
+) -> Result<ImageEditResponse, AppError> {
     let service = GalleryService::new();
-    service.edit_image(db, request).await
+    let response = service.edit_image(db, job_queue, request)?;
+    // 新建的记录先生成原图缩略图
+    thumbnails.notify();
+    Ok(response)
 }
 
 /// 获取全部图片接口
 #[tauri::command]
-pub fn get_all_images(db: State<'_, DatabaseState>) -> Result<Vec<crate::database::Gallery>, String> {
-    let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
-    db.gallery().get_all().map_err(|e| format!("Failed to get images: {}", e))
+pub fn get_all_images(db: State<'_, DatabaseState>) -> Result<Vec<Gallery>, AppError> {
+    let db = db.lock()?;
+    db.gallery().get_all().map_err(|e| AppError::database("Failed to get images", e))
+}
+
+/// 分页查询图片接口
+#[tauri::command]
+pub fn list_images(
+    db: State<'_, DatabaseState>,
+    request: ListImagesRequest,
+) -> Result<ListImagesResponse, AppError> {
+    let service = GalleryService::new();
+    service.list_images(db, request)
 }
 
 /// 批量删除图片接口
@@ -62,17 +258,195 @@ pub fn get_all_images(db: State<'_, DatabaseState>) -> Result<Vec<crate::databas
 pub fn batch_delete_images(
     db: State<'_, DatabaseState>,
     request: BatchDeleteRequest,
-) -> Result<(), String> {
-    let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
-    db.gallery().batch_delete(&request.ids).map_err(|e| format!("Failed to delete images: {}", e))
+) -> Result<(), AppError> {
+    let service = GalleryService::new();
+    service.batch_delete_images(db, &request.ids)
+}
+
+/// 按哈希读取图片，返回 data URL
+#[tauri::command]
+pub fn get_image_data(db: State<'_, DatabaseState>, hash: String) -> Result<String, AppError> {
+    let db = db.lock()?;
+    db.blobs().get_data_url(&hash).map_err(AppError::blob)
 }
 
 /// 根据消息内容生成风格接口
 #[tauri::command]
 pub async fn generate_style_from_message(
+    app: AppHandle,
     db: State<'_, DatabaseState>,
     request: StyleGenerateRequest,
-) -> Result<StyleGenerateResponse, String> {
+) -> Result<StyleGenerateResponse, AppError> {
+    let service = GalleryService::new();
+    service.generate_style_from_message(&app, db, request).await
+}
+
+/// 获取图库对话消息接口
+#[tauri::command]
+pub fn get_gallery_messages(
+    db: State<'_, DatabaseState>,
+    gallery_id: String,
+) -> Result<Vec<Message>, AppError> {
     let service = GalleryService::new();
-    service.generate_style_from_message(db, request).await
-}
\ No newline at end of file
+    service.get_gallery_messages(db, &gallery_id)
+}
+
+/// 删除消息接口
+#[tauri::command]
+pub fn delete_message(db: State<'_, DatabaseState>, id: String) -> Result<(), AppError> {
+    let service = GalleryService::new();
+    service.delete_message(db, &id)
+}
+
+/// 修改消息接口
+#[tauri::command]
+pub fn edit_message(
+    db: State<'_, DatabaseState>,
+    request: EditMessageRequest,
+) -> Result<Message, AppError> {
+    let service = GalleryService::new();
+    service.edit_message(db, request)
+}
+
+/// 从指定消息重新生成接口
+#[tauri::command]
+pub fn regenerate_from_message(
+    db: State<'_, DatabaseState>,
+    job_queue: State<'_, JobQueue>,
+    request: RegenerateRequest,
+) -> Result<ImageEditResponse, AppError> {
+    let service = GalleryService::new();
+    service.regenerate_from_message(db, job_queue, request)
+}
+
+/// 获取图库版本列表接口
+#[tauri::command]
+pub fn list_versions(
+    db: State<'_, DatabaseState>,
+    gallery_id: String,
+) -> Result<VersionListResponse, AppError> {
+    let service = GalleryService::new();
+    service.list_versions(db, &gallery_id)
+}
+
+/// 检出版本接口
+#[tauri::command]
+pub fn checkout_version(
+    db: State<'_, DatabaseState>,
+    thumbnails: State<'_, ThumbnailQueue>,
+    gallery_id: String,
+    version_id: String,
+) -> Result<Gallery, AppError> {
+    let service = GalleryService::new();
+    let gallery = service.checkout_version(db, &gallery_id, &version_id)?;
+    thumbnails.notify();
+    Ok(gallery)
+}
+
+/// 撤销到上一个版本接口
+#[tauri::command]
+pub fn undo_version(
+    db: State<'_, DatabaseState>,
+    thumbnails: State<'_, ThumbnailQueue>,
+    gallery_id: String,
+) -> Result<Gallery, AppError> {
+    let service = GalleryService::new();
+    let gallery = service.undo_version(db, &gallery_id)?;
+    thumbnails.notify();
+    Ok(gallery)
+}
+
+/// 重做到下一个版本接口
+#[tauri::command]
+pub fn redo_version(
+    db: State<'_, DatabaseState>,
+    thumbnails: State<'_, ThumbnailQueue>,
+    gallery_id: String,
+) -> Result<Gallery, AppError> {
+    let service = GalleryService::new();
+    let gallery = service.redo_version(db, &gallery_id)?;
+    thumbnails.notify();
+    Ok(gallery)
+}
+
+/// 从指定版本分支编辑接口
+#[tauri::command]
+pub fn branch_from_version(
+    db: State<'_, DatabaseState>,
+    job_queue: State<'_, JobQueue>,
+    request: BranchVersionRequest,
+) -> Result<ImageEditResponse, AppError> {
+    let service = GalleryService::new();
+    let edit_request = ImageEditRequest {
+        origin_image: None,
+        gallery_id: Some(request.gallery_id),
+        base_version_id: Some(request.version_id),
+        prompt: request.prompt,
+        style_name: request.style_name,
+        request_id: request.request_id,
+        n: request.n,
+    };
+    service.edit_image(db, job_queue, edit_request)
+}
+
+/// 将候选结果设为当前版本接口
+#[tauri::command]
+pub fn promote_candidate(
+    db: State<'_, DatabaseState>,
+    thumbnails: State<'_, ThumbnailQueue>,
+    gallery_id: String,
+    version_id: String,
+) -> Result<Gallery, AppError> {
+    let service = GalleryService::new();
+    let gallery = service.promote_candidate(db, &gallery_id, &version_id)?;
+    thumbnails.notify();
+    Ok(gallery)
+}
+
+/// 比较两个版本接口
+#[tauri::command]
+pub fn diff_versions(
+    db: State<'_, DatabaseState>,
+    from_version_id: String,
+    to_version_id: String,
+) -> Result<VersionDiffResponse, AppError> {
+    let service = GalleryService::new();
+    service.diff_versions(db, &from_version_id, &to_version_id)
+}
+
+/// 本地图片操作接口，结果作为新版本保存，不消耗 token
+#[tauri::command]
+pub async fn apply_local_ops(
+    db: State<'_, DatabaseState>,
+    thumbnails: State<'_, ThumbnailQueue>,
+    request: ApplyLocalOpsRequest,
+) -> Result<ApplyLocalOpsResponse, AppError> {
+    let service = GalleryService::new();
+    let response = service.apply_local_ops(db, request).await?;
+    thumbnails.notify();
+    Ok(response)
+}
+
+/// 重试编辑失败的图片接口，按原参数重新加入队列
+#[tauri::command]
+pub fn retry_edit(
+    db: State<'_, DatabaseState>,
+    job_queue: State<'_, JobQueue>,
+    gallery_id: String,
+) -> Result<ImageEditResponse, AppError> {
+    let service = GalleryService::new();
+    service.retry_edit(db, job_queue, &gallery_id)
+}
+
+/// 重新生成缩略图接口，gallery_ids 为空时重新生成全部记录
+#[tauri::command]
+pub fn regenerate_thumbnails(
+    db: State<'_, DatabaseState>,
+    thumbnails: State<'_, ThumbnailQueue>,
+    gallery_ids: Vec<String>,
+) -> Result<(), AppError> {
+    let service = GalleryService::new();
+    service.regenerate_thumbnails(db, &gallery_ids)?;
+    thumbnails.notify();
+    Ok(())
+}
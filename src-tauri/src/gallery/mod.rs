@@ -1,2 +1,4 @@
 pub mod api;
-pub mod service;
\ No newline at end of file
+pub mod protocol;
+pub mod service;
+pub mod thumbnail;
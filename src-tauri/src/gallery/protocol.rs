@@ -0,0 +1,235 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager};
+
+use crate::database::{Database, StoredImage};
+use crate::image_ops::thumbnail::ThumbnailSize;
+
+/// 图库图片协议名，前端通过 gallery://localhost/<id>/<kind> 访问，
+/// 缩略图可指定规格：gallery://localhost/<id>/thumb/<size>
+pub const GALLERY_PROTOCOL: &str = "gallery";
+
+type DatabaseState = Mutex<Database>;
+
+/// 图片种类：原图、效果图、缩略图
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImageKind {
+    Origin,
+    Effect,
+    Thumb(ThumbnailSize),
+}
+
+impl ImageKind {
+    fn parse(kind: &str, size: Option<&str>) -> Option<Self> {
+        match (kind, size) {
+            ("origin", None) => Some(Self::Origin),
+            ("effect", None) => Some(Self::Effect),
+            ("thumb", None) => Some(Self::Thumb(ThumbnailSize::Medium)),
+            ("thumb", Some(size)) => Some(Self::Thumb(ThumbnailSize::parse(size)?)),
+            _ => None,
+        }
+    }
+}
+
+/// 处理 gallery:// 请求，支持 ETag 协商缓存和单段 Range 请求
+pub fn handle(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    match serve(app, request) {
+        Ok(response) => response,
+        Err((status, message)) => error_response(status, message),
+    }
+}
+
+fn serve(app: &AppHandle, request: &Request<Vec<u8>>) -> Result<Response<Vec<u8>>, (StatusCode, String)> {
+    let uri = request.uri();
+    let (gallery_id, kind) = parse_target(uri.host().unwrap_or_default(), uri.path())
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("无效的图片地址: {}", uri)))?;
+
+    // 锁内只查询图片记录和文件路径，读取文件在释放数据库锁之后进行
+    let (image, path) = {
+        let db = app.state::<DatabaseState>();
+        let db = db.lock()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database lock error: {}", e)))?;
+        let gallery = db.gallery().get_by_id(&gallery_id)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get gallery: {}", e)))?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("图库记录不存在: {}", gallery_id)))?;
+
+        // 缩略图尚未生成时回退到完整图片
+        let thumbnail = match kind {
+            ImageKind::Thumb(size) => db.thumbnail().get_current(&gallery_id, size)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get thumbnail: {}", e)))?
+                .and_then(|thumbnail| thumbnail.image),
+            _ => None,
+        };
+        let image = thumbnail
+            .or_else(|| select_image(gallery.origin_image, gallery.effect_image, kind))
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "图片尚未生成".to_string()))?;
+        let path = db.blobs().path(&image.hash)
+            .map_err(|e| (StatusCode::NOT_FOUND, format!("读取图片失败: {}", e)))?;
+        (image, path)
+    };
+
+    // 同一地址的图片会随编辑而变化，因此不做强缓存，依赖 ETag 协商
+    let etag = format!("\"{}\"", image.hash);
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, &image.mime_type)
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+
+    let not_modified = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    if not_modified {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Vec::new())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+
+    let read_error = |e: io::Error| (StatusCode::NOT_FOUND, format!("读取图片失败: {}", e));
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let response = match range {
+        Some(range) => {
+            let len = std::fs::metadata(&path).map_err(read_error)?.len();
+            match parse_range(range, len) {
+                Some((start, end)) => builder
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len))
+                    .body(read_range(&path, start, end).map_err(read_error)?),
+                None => builder
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                    .body(Vec::new()),
+            }
+        }
+        None => builder.status(StatusCode::OK).body(std::fs::read(&path).map_err(read_error)?),
+    };
+
+    response.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// 只读取闭区间 [start, end] 内的字节，避免为一个分段读入整张图片
+fn read_range(path: &Path, start: u64, end: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = vec![0; (end - start + 1) as usize];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// 缩略图优先使用效果图，处理中的记录回退到原图
+fn select_image(origin: StoredImage, effect: Option<StoredImage>, kind: ImageKind) -> Option<StoredImage> {
+    match kind {
+        ImageKind::Origin => Some(origin),
+        ImageKind::Effect => effect,
+        ImageKind::Thumb(_) => Some(effect.unwrap_or(origin)),
+    }
+}
+
+/// 解析图片地址。macOS/Linux 为 gallery://localhost/<id>/<kind>[/<size>]，
+/// Windows 为 http://gallery.localhost/<id>/<kind>，也兼容 gallery://<id>/<kind>
+fn parse_target(host: &str, path: &str) -> Option<(String, ImageKind)> {
+    let path = percent_decode(path);
+    let mut segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if host != "localhost" && host != format!("{}.localhost", GALLERY_PROTOCOL) && !host.is_empty() {
+        segments.insert(0, host);
+    }
+
+    match segments.as_slice() {
+        [id, kind] => Some((id.to_string(), ImageKind::parse(kind, None)?)),
+        [id, kind, size] => Some((id.to_string(), ImageKind::parse(kind, Some(size))?)),
+        _ => None,
+    }
+}
+
+// 前端 convertFileSrc 会对路径整体编码，斜杠会变成 %2F
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// 解析单段 Range 头，返回闭区间 [start, end]；多段或越界时返回 None
+fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || len == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return None;
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (start, "") => (start.parse().ok()?, len - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(len - 1)),
+    };
+    if start > end || start >= len {
+        return None;
+    }
+    Some((start, end))
+}
+
+fn error_response(status: StatusCode, message: String) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(message.into_bytes())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(parse_target("localhost", "/abc/effect"), Some(("abc".to_string(), ImageKind::Effect)));
+        assert_eq!(parse_target("gallery.localhost", "/abc%2Fthumb"), Some(("abc".to_string(), ImageKind::Thumb(ThumbnailSize::Medium))));
+        assert_eq!(
+            parse_target("localhost", "/abc/thumb/small"),
+            Some(("abc".to_string(), ImageKind::Thumb(ThumbnailSize::Small)))
+        );
+        assert_eq!(parse_target("localhost", "/abc/thumb/huge"), None);
+        assert_eq!(parse_target("localhost", "/abc/effect/small"), None);
+        assert_eq!(parse_target("abc", "/origin"), Some(("abc".to_string(), ImageKind::Origin)));
+        assert_eq!(parse_target("localhost", "/abc/unknown"), None);
+        assert_eq!(parse_target("localhost", "/abc"), None);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=900-", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=500-5000", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+}
@@ -1,18 +1,46 @@
-use tauri::State;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
 use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use chrono::Utc;
 
-use crate::database::{Database, Gallery, Message};
-use crate::ai::service::AIService;
+use crate::database::{
+    BlobStore, Database, Gallery, GalleryCursor, GalleryFilter, GalleryThumbnail, GalleryVersion, Job, Message,
+    Setting, StoredImage, GALLERY_STATUS_CANCELLED, GALLERY_STATUS_FAILED, GALLERY_STATUS_PENDING, GALLERY_STATUS_PROCESSING,
+    GALLERY_STATUS_SUCCEEDED, JOB_KIND_IMAGE_EDIT, JOB_STATUS_FAILED, JOB_STATUS_PENDING, JOB_STATUS_RUNNING, VERSION_SOURCE_AI, VERSION_SOURCE_LOCAL, VERSION_SOURCE_ORIGIN,
+};
+use crate::error::AppError;
+use crate::ai::service::{AIService, UsageRecorder};
+use crate::ai_service::{create_provider, decode_image_data, ChatMessage, ProviderConfig, RetryConfig};
+use crate::job::queue::{
+    emit_progress, JobError, JobOutput, JobQueue, JOB_STAGE_GENERATING, JOB_STAGE_SAVING,
+};
+use crate::image_ops;
+use crate::image_ops::metadata::ImageMetadata;
 use crate::style::service::StyleService;
 
-use super::api::{ImageEditRequest, ImageEditResponse, StyleGenerateRequest, StyleGenerateResponse};
+use super::api::{
+    ApplyLocalOpsRequest, ApplyLocalOpsResponse, AIStreamDelta, AIStreamDone, EditMessageRequest, GalleryListItem,
+    ImageEditRequest, ImageEditResponse, ListImagesRequest, ListImagesResponse, RegenerateRequest, StyleGenerateRequest, VersionChange,
+    VersionDiffResponse, VersionListResponse, StyleGenerateResponse, Thumbnail, AI_STREAM_DELTA_EVENT,
+    AI_STREAM_DONE_EVENT,
+};
+use super::thumbnail::ThumbnailQueue;
+
+/// 继续编辑时携带的历史消息条数上限
+const MAX_HISTORY_MESSAGES: usize = 20;
+
+/// 单次编辑最多生成的候选结果数量
+const MAX_CANDIDATES: u32 = 8;
+
+/// 列表默认每页条数和上限
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
 
 type DatabaseState = Mutex<Database>;
 
 pub struct GalleryService {
-    ai_service: AIService,
     #[allow(dead_code)]
     style_service: StyleService,
 }
@@ -20,145 +48,336 @@ pub struct GalleryService {
 impl GalleryService {
     pub fn new() -> Self {
         Self {
-            ai_service: AIService::new(),
             style_service: StyleService::new(),
         }
     }
 
-    /// 图片编辑服务
-    pub async fn edit_image(
+    /// 图片编辑服务：创建或载入图库记录、保存用户消息后将编辑任务加入队列，立即返回任务 ID
+    pub fn edit_image(
         &self,
         db: State<'_, DatabaseState>,
+        job_queue: State<'_, JobQueue>,
         request: ImageEditRequest,
-    ) -> Result<ImageEditResponse, String> {
-        // 1. 创建图库记录和保存用户消息（不持有MutexGuard跨越await）
-        let (gallery_id, setting, style_prompt) = {
-            let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
-
-            let gallery_id = Uuid::new_v4().to_string();
-            let gallery = Gallery {
-                id: gallery_id.clone(),
-                origin_image: request.origin_image.clone(),
-                effect_image: request.origin_image.clone(), // 初始时原图和效果图相同
-                total_input_tokens: 0,
-                total_ouput_tokens: 0,
-                create_at: Utc::now().timestamp_millis(),
+    ) -> Result<ImageEditResponse, AppError> {
+        let (gallery_id, job) = {
+            let db = db.lock()?;
+            // 继续编辑时忽略上传的图片
+            let origin = match (&request.gallery_id, &request.origin_image) {
+                (None, Some(origin_image)) => Some(OriginImage::from_data_url(db.blobs(), origin_image)?),
+                _ => None,
             };
+            enqueue_edit(&db, request, origin, None)?
+        };
 
-            db.gallery().create(&gallery)
-                .map_err(|e| format!("Failed to create gallery: {}", e))?;
+        job_queue.notify();
 
-            // 保存用户消息
-            let user_message = Message {
-                id: Uuid::new_v4().to_string(),
-                gallery_id: gallery_id.clone(),
-                role: "user".to_string(),
-                content: request.prompt.clone(),
-                create_at: Utc::now().timestamp_millis(),
-            };
+        Ok(ImageEditResponse {
+            success: true,
+            effect_image: None,
+            gallery_id,
+            message: "图片编辑已加入队列".to_string(),
+            candidate_version_ids: Vec::new(),
+            job_id: Some(job.id),
+        })
+    }
 
-            db.message().create(&user_message)
-                .map_err(|e| format!("Failed to create message: {}", e))?;
+    /// 重试失败的编辑：最近一次编辑任务按原参数重新排队，立即返回任务 ID
+    pub fn retry_edit(
+        &self,
+        db: State<'_, DatabaseState>,
+        job_queue: State<'_, JobQueue>,
+        gallery_id: &str,
+    ) -> Result<ImageEditResponse, AppError> {
+        let job = {
+            let db = db.lock()?;
+            check_provider_setting(&db)?;
 
-            // 获取AI服务配置
-            let setting = db.setting().get_or_create_default()
-                .map_err(|e| format!("Failed to get settings: {}", e))?;
+            db.transaction(|db| {
+                let gallery = db.gallery().get_by_id(gallery_id)
+                    .map_err(|e| AppError::database("Failed to get gallery", e))?
+                    .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+                if gallery.status != GALLERY_STATUS_FAILED {
+                    return Err(AppError::validation("只能重试编辑失败的图片"));
+                }
 
-            if setting.api_key.is_empty() {
-                return Err("请先配置API密钥".to_string());
-            }
+                let job = db.job().get_by_gallery_id(gallery_id)
+                    .map_err(|e| AppError::database("Failed to get jobs", e))?
+                    .into_iter()
+                    .rfind(|job| job.kind == JOB_KIND_IMAGE_EDIT)
+                    .filter(|job| job.status == JOB_STATUS_FAILED)
+                    .ok_or_else(|| AppError::not_found("没有可重试的编辑任务"))?;
 
-            // 获取风格配置
-            let style_prompt = if let Some(style_name) = &request.style_name {
-                match db.style().get_by_name(style_name) {
-                    Ok(Some(style)) => Some(style.prompt),
-                    Ok(None) => None,
-                    Err(e) => return Err(format!("Failed to get style: {}", e)),
+                let requeued = db.job().requeue(&job.id, Utc::now().timestamp_millis())
+                    .map_err(|e| AppError::database("Failed to update job", e))?;
+                if !requeued {
+                    return Err(AppError::not_found("没有可重试的编辑任务"));
                 }
-            } else {
-                None
+                db.gallery().update_status(gallery_id, GALLERY_STATUS_PENDING, None)
+                    .map_err(|e| AppError::database("Failed to update gallery", e))?;
+
+                Ok(job)
+            })?
+        };
+
+        job_queue.notify();
+
+        Ok(ImageEditResponse {
+            success: true,
+            effect_image: None,
+            gallery_id: gallery_id.to_string(),
+            message: "图片编辑已重新加入队列".to_string(),
+            candidate_version_ids: Vec::new(),
+            job_id: Some(job.id),
+        })
+    }
+
+    /// 执行图片编辑任务，由任务队列的工作线程调用
+    pub async fn run_edit_job(
+        &self,
+        app: &AppHandle,
+        job: &Job,
+        cancel_token: CancellationToken,
+        retry_config: RetryConfig,
+    ) -> Result<JobOutput, JobError> {
+        let payload: EditJobPayload = serde_json::from_str(&job.payload)
+            .map_err(|e| JobError::Fatal(format!("任务参数无效: {}", e)))?;
+        let gallery_id = job.gallery_id.clone();
+
+        // 1. 载入配置、输入图片和对话历史（不持有MutexGuard跨越await）
+        let (config, preprocess, setting, style_prompt, input_image, history) = {
+            let db = app.state::<DatabaseState>();
+            let db = db.lock().map_err(|e| JobError::Fatal(format!("Database lock error: {}", e)))?;
+
+            let setting = db.setting().get_or_create_default()
+                .map_err(|e| JobError::Fatal(format!("Failed to get settings: {}", e)))?;
+            let config = ProviderConfig::new(&setting.provider, setting.api_url.clone(), setting.api_key.clone())
+                .map_err(JobError::Fatal)?;
+            if config.kind.requires_api_key() && config.api_key.is_empty() {
+                return Err(JobError::Fatal("请先配置API密钥".to_string()));
+            }
+            // 入队后切换了供应商时，同样不发起任何调用
+            if !config.kind.supports_image_edit() {
+                return Err(JobError::Fatal(format!("{} 不支持图片编辑，请切换供应商", config.kind)));
+            }
+            let preprocess = db.preprocess_setting().get_options(config.kind)
+                .map_err(|e| JobError::Fatal(format!("Failed to get preprocess settings: {}", e)))?;
+
+            let style_prompt = match &payload.style_name {
+                Some(style_name) => db.style().get_by_name(style_name)
+                    .map_err(|e| JobError::Fatal(format!("Failed to get style: {}", e)))?
+                    .map(|style| style.prompt),
+                None => None,
+            };
+
+            let gallery = db.gallery().get_by_id(&gallery_id)
+                .map_err(|e| JobError::Fatal(format!("Failed to get gallery: {}", e)))?
+                .ok_or_else(|| JobError::Fatal("图库记录不存在".to_string()))?;
+            // 重试的任务开始前已是 processing，不需要切换
+            db.gallery().transition_status(&gallery_id, GALLERY_STATUS_PENDING, GALLERY_STATUS_PROCESSING)
+                .map_err(|e| JobError::Fatal(format!("Failed to update gallery: {}", e)))?;
+            let input_hash = match &payload.base_version_id {
+                Some(version_id) => db.gallery_version().get_by_id(version_id)
+                    .map_err(|e| JobError::Fatal(format!("Failed to get version: {}", e)))?
+                    .ok_or_else(|| JobError::Fatal("版本不存在".to_string()))?
+                    .image
+                    .hash,
+                None => gallery.effect_image.unwrap_or(gallery.origin_image).hash,
             };
+            let input_image = db.blobs().get_data_url(&input_hash)
+                .map_err(|e| JobError::Fatal(format!("读取图片失败: {}", e)))?;
 
-            (gallery_id, setting, style_prompt)
+            // 历史中包含本次的用户消息，normalize_history 会去掉结尾的用户消息
+            let history = db.message().get_latest_by_gallery_id(&gallery_id, MAX_HISTORY_MESSAGES)
+                .map_err(|e| JobError::Fatal(format!("Failed to get messages: {}", e)))?
+                .into_iter()
+                .map(|message| ChatMessage {
+                    role: message.role,
+                    content: message.content,
+                })
+                .collect();
+
+            (config, preprocess, setting, style_prompt, input_image, history)
         };
 
-        // 2. 调用AI服务处理图片
-        let ai_response = self.ai_service.process_image(
-            request.prompt.clone(),
-            request.origin_image.clone(),
-            setting.api_url,
-            setting.api_key,
-            setting.model,
-            style_prompt,
-        ).await.map_err(|e| format!("AI processing failed: {}", e))?;
+        // 2. 调用AI服务：对话模型流式给出文字说明，图片编辑模型生成结果图
+        emit_progress(app, job, JOB_STATUS_RUNNING, JOB_STAGE_GENERATING, None);
+        let ai_service = AIService::new(create_provider(config))
+            .with_cancel_token(cancel_token)
+            .with_retry_config(retry_config)
+            .with_preprocess(preprocess)
+            .with_metadata_whitelist(setting.metadata_whitelist)
+            .with_usage_recorder(UsageRecorder::new(app.clone(), Some(gallery_id.clone())));
+        // 对话和图片编辑共用同一张预处理后的图片
+        let input_image = ai_service.preprocess_image(input_image).await?;
+        let on_delta = {
+            let app = app.clone();
+            let gallery_id = gallery_id.clone();
+            move |delta: &str| {
+                let _ = app.emit(AI_STREAM_DELTA_EVENT, AIStreamDelta {
+                    gallery_id: gallery_id.clone(),
+                    delta: delta.to_string(),
+                });
+            }
+        };
+        // 两个调用各自计费，分别处理结果：对话失败时不丢弃已经生成的图片
+        let (chat_result, image_result) = tokio::join!(
+            ai_service.process_image_stream(
+                payload.prompt.clone(),
+                input_image.clone(),
+                setting.model,
+                style_prompt.clone(),
+                history,
+                &on_delta,
+            ),
+            ai_service.edit_image(
+                payload.prompt.clone(),
+                input_image,
+                setting.image_model,
+                style_prompt,
+                payload.n,
+            ),
+        );
+        let image_responses = image_result?;
+        let ai_response = match chat_result {
+            Ok(response) => Some(response),
+            Err(e) if e.error_type == "cancelled" => return Err(JobError::Cancelled),
+            Err(e) => {
+                log::warn!("编辑说明生成失败，仅保存图片结果: {}", e.message);
+                None
+            }
+        };
+        let ai_content = ai_response.as_ref().map(|r| r.content.clone()).unwrap_or_default();
 
-        // 3. 保存处理后的图片（这里使用模拟数据，实际应该从AI响应中获取）
-        let effect_image = self.generate_mock_processed_image(&ai_response.content)?;
+        // 3. 第一张候选图作为当前结果，其余候选图可随后提升为当前版本
+        emit_progress(app, job, JOB_STATUS_RUNNING, JOB_STAGE_SAVING, None);
+        if image_responses.is_empty() {
+            return Err(JobError::Retryable { error: "AI 没有返回图片".to_string(), retry_after_ms: None });
+        }
+        let usage = image_responses
+            .iter()
+            .fold(ai_response.map(|r| r.usage).unwrap_or_default(), |usage, response| usage + response.usage);
+        let mut candidate_version_ids = Vec::with_capacity(image_responses.len());
 
-        // 4. 保存AI消息和更新图库记录
+        // 4. 保存AI消息和更新图库记录，全部写入成功后记录才标记为成功
         {
-            let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+            let db = app.state::<DatabaseState>();
+            let db = db.lock().map_err(|e| JobError::Fatal(format!("Database lock error: {}", e)))?;
+            db.transaction(|db| {
+                // 保存AI消息，对话调用失败时没有说明可保存
+                if !ai_content.is_empty() {
+                    let ai_message = Message {
+                        id: Uuid::new_v4().to_string(),
+                        gallery_id: gallery_id.clone(),
+                        role: "assistant".to_string(),
+                        content: ai_content.clone(),
+                        create_at: Utc::now().timestamp_millis(),
+                        base_version_id: None,
+                        style_name: None,
+                    };
 
-            // 保存AI消息
-            let ai_message = Message {
-                id: Uuid::new_v4().to_string(),
-                gallery_id: gallery_id.clone(),
-                role: "assistant".to_string(),
-                content: ai_response.content.clone(),
-                create_at: Utc::now().timestamp_millis(),
-            };
+                    db.message().create(&ai_message)
+                        .map_err(|e| JobError::Fatal(format!("Failed to create AI message: {}", e)))?;
+                }
 
-            db.message().create(&ai_message)
-                .map_err(|e| format!("Failed to create AI message: {}", e))?;
+                // 保存结果图并更新图库记录
+                let mut gallery = db.gallery().get_by_id(&gallery_id)
+                    .map_err(|e| JobError::Fatal(format!("Failed to get gallery: {}", e)))?
+                    .ok_or_else(|| JobError::Fatal("Gallery not found".to_string()))?;
 
-            // 更新图库记录
-            let updated_gallery = Gallery {
-                id: gallery_id.clone(),
-                origin_image: request.origin_image,
-                effect_image: effect_image.clone(),
-                total_input_tokens: 0, // 应该从AI响应中获取
-                total_ouput_tokens: ai_response.tokens_used as i64,
-                create_at: Utc::now().timestamp_millis(), // This will be fixed below
-            };
+                // 每个候选图在版本树上新增一个节点，父节点均为本次编辑的输入版本，
+                // 版本上记录该候选图自身的图片编辑用量
+                let create_at = Utc::now().timestamp_millis();
+                let mut effect = None;
+                for image_response in &image_responses {
+                    let stored_image = db.blobs().put_data_url(&image_response.image_data)
+                        .map_err(JobError::Fatal)?;
+                    let version = GalleryVersion {
+                        id: Uuid::new_v4().to_string(),
+                        gallery_id: gallery_id.clone(),
+                        parent_id: payload.base_version_id.clone(),
+                        source: VERSION_SOURCE_AI.to_string(),
+                        prompt: Some(payload.prompt.clone()),
+                        style_name: payload.style_name.clone(),
+                        model: Some(image_response.model.clone()),
+                        params: None,
+                        image: stored_image.clone(),
+                        input_tokens: image_response.usage.input_tokens as i64,
+                        output_tokens: image_response.usage.output_tokens as i64,
+                        create_at,
+                    };
+                    db.gallery_version().create(&version)
+                        .map_err(|e| JobError::Fatal(format!("Failed to create version: {}", e)))?;
+
+                    effect.get_or_insert((stored_image, version.id.clone()));
+                    candidate_version_ids.push(version.id);
+                }
 
-            db.gallery().update(&updated_gallery)
-                .map_err(|e| format!("Failed to update gallery: {}", e))?;
+                if let Some((stored_image, version_id)) = effect {
+                    gallery.effect_image = Some(stored_image);
+                    gallery.current_version_id = Some(version_id);
+                }
+                // 多轮编辑在同一条记录上累计用量
+                gallery.total_input_tokens += usage.input_tokens as i64;
+                gallery.total_output_tokens += usage.output_tokens as i64;
+                gallery.status = GALLERY_STATUS_SUCCEEDED.to_string();
+                gallery.error = None;
+                if payload.style_name.is_some() {
+                    gallery.style_name = payload.style_name.clone();
+                }
+
+                db.gallery().update(&gallery)
+                    .map_err(|e| JobError::Fatal(format!("Failed to update gallery: {}", e)))
+            })?;
         }
 
-        Ok(ImageEditResponse {
-            success: true,
-            effect_image: Some(effect_image),
+        // 效果图已更新，重新生成缩略图
+        app.state::<ThumbnailQueue>().notify();
+
+        let _ = app.emit(AI_STREAM_DONE_EVENT, AIStreamDone {
             gallery_id,
-            message: "图片编辑完成".to_string(),
+            content: ai_content,
+            tokens_used: usage.total(),
+            usage,
+        });
+
+        let result = serde_json::to_string(&EditJobResult { candidate_version_ids })
+            .map_err(|e| JobError::Fatal(format!("Failed to encode job result: {}", e)))?;
+
+        Ok(JobOutput {
+            result: Some(result),
+            usage,
         })
     }
 
     /// 根据消息内容生成风格
     pub async fn generate_style_from_message(
         &self,
+        app: &AppHandle,
         db: State<'_, DatabaseState>,
         request: StyleGenerateRequest,
-    ) -> Result<StyleGenerateResponse, String> {
+    ) -> Result<StyleGenerateResponse, AppError> {
         // 获取设置信息（不持有MutexGuard跨越await）
-        let (api_url, api_key, model) = {
-            let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+        let (config, model) = {
+            let db = db.lock()?;
             let setting = db.setting().get_or_create_default()
-                .map_err(|e| format!("Failed to get settings: {}", e))?;
+                .map_err(|e| AppError::database("Failed to get settings", e))?;
 
-            if setting.api_key.is_empty() {
-                return Err("请先配置API密钥".to_string());
+            let config = ProviderConfig::new(&setting.provider, setting.api_url.clone(), setting.api_key.clone())
+                .map_err(AppError::Config)?;
+            if config.kind.requires_api_key() && config.api_key.is_empty() {
+                return Err(AppError::config("请先配置API密钥"));
             }
 
-            (setting.api_url, setting.api_key, setting.model)
+            (config, setting.model)
         };
 
         // 使用AI服务分析消息内容并生成风格
-        let style_generation = self.ai_service.generate_style_from_content(
+        let ai_service = AIService::new(create_provider(config))
+            .with_usage_recorder(UsageRecorder::new(app.clone(), None));
+        let style_generation = ai_service.generate_style_from_content(
             request.message_content.clone(),
-            api_url,
-            api_key,
             model,
-        ).await.map_err(|e| format!("Style generation failed: {}", e))?;
+        ).await?;
 
         Ok(StyleGenerateResponse {
             success: true,
@@ -168,21 +387,768 @@ impl GalleryService {
         })
     }
 
-    /// 生成模拟的处理后图片
-    fn generate_mock_processed_image(&self,
-        _ai_content: &str
-    ) -> Result<String, String> {
-        // 这里应该根据AI返回的内容生成实际的图片数据
-        // 现在返回一个模拟的SVG图片
-        let svg_content = "<svg width='400' height='400' xmlns='http://www.w3.org/2000/svg'>\
-                <rect width='400' height='400' fill='#3373dc'/>\
-                <text x='50%' y='50%' dominant-baseline='middle' text-anchor='middle'\
-                      font-family='monospace' font-size='16px' fill='white'>\
-                    AI Processed\
-                </text>\
-            </svg>";
-
-        Ok(format!("data:image/svg+xml;base64,{}",
-            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, svg_content)))
-    }
-}
\ No newline at end of file
+    /// 分页查询图库，返回当前页、下一页游标和满足条件的总数
+    pub fn list_images(
+        &self,
+        db: State<'_, DatabaseState>,
+        request: ListImagesRequest,
+    ) -> Result<ListImagesResponse, AppError> {
+        let limit = request.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let cursor = match &request.cursor {
+            Some(cursor) => Some(GalleryCursor::decode(cursor).ok_or_else(|| AppError::validation("无效的分页游标"))?),
+            None => None,
+        };
+        let (taken_from, taken_until) = taken_range(
+            request.taken_year,
+            request.taken_from.as_deref(),
+            request.taken_to.as_deref(),
+        )?;
+        let filter = GalleryFilter {
+            start_time: request.start_time,
+            end_time: request.end_time,
+            style_name: request.style_name,
+            min_tokens: request.min_tokens,
+            max_tokens: request.max_tokens,
+            status: request.status,
+            min_edge: request.min_edge,
+            max_edge: request.max_edge,
+            mime_type: request.mime_type,
+            taken_from,
+            taken_until,
+            camera_model: request.camera_model.filter(|model| !model.trim().is_empty()),
+        };
+
+        let db = db.lock()?;
+
+        // 多取一条判断是否还有下一页
+        let mut items = db.gallery().list(&filter, request.sort, cursor.as_ref(), limit + 1)
+            .map_err(|e| AppError::database("Failed to list images", e))?;
+        let next_cursor = if items.len() > limit as usize {
+            items.truncate(limit as usize);
+            items.last().map(|last| GalleryCursor::after(last, request.sort).encode())
+        } else {
+            None
+        };
+
+        let total = db.gallery().count(&filter)
+            .map_err(|e| AppError::database("Failed to count images", e))?;
+
+        let ids: Vec<String> = items.iter().map(|gallery| gallery.id.clone()).collect();
+        let mut thumbnails = db.thumbnail().get_current_by_gallery_ids(&ids)
+            .map_err(|e| AppError::database("Failed to get thumbnails", e))?;
+        let items = items
+            .into_iter()
+            .map(|gallery| {
+                let (own, rest) = thumbnails.drain(..).partition(|t| t.gallery_id == gallery.id);
+                thumbnails = rest;
+                let mut own: Vec<Thumbnail> = own
+                    .into_iter()
+                    .filter_map(|t: GalleryThumbnail| Some(Thumbnail { size: t.size, image: t.image? }))
+                    .collect();
+                own.sort_by_key(|t| t.size.edge());
+                GalleryListItem { gallery, thumbnails: own }
+            })
+            .collect();
+
+        Ok(ListImagesResponse { items, next_cursor, total })
+    }
+
+    /// 删除缩略图，由后台线程重新生成
+    pub fn regenerate_thumbnails(
+        &self,
+        db: State<'_, DatabaseState>,
+        gallery_ids: &[String],
+    ) -> Result<(), AppError> {
+        let db = db.lock()?;
+
+        let gallery_ids = if gallery_ids.is_empty() {
+            db.gallery().get_all()
+                .map_err(|e| AppError::database("Failed to get galleries", e))?
+                .into_iter()
+                .map(|gallery| gallery.id)
+                .collect()
+        } else {
+            gallery_ids.to_vec()
+        };
+
+        let mut hashes = Vec::new();
+        for gallery_id in &gallery_ids {
+            hashes.extend(db.thumbnail().get_image_hashes(gallery_id)
+                .map_err(|e| AppError::database("Failed to get thumbnails", e))?);
+            db.thumbnail().delete_by_gallery_id(gallery_id)
+                .map_err(|e| AppError::database("Failed to delete thumbnails", e))?;
+        }
+
+        remove_unreferenced_blobs(&db, hashes)
+    }
+
+    /// 获取图库的全部版本及当前版本
+    pub fn list_versions(
+        &self,
+        db: State<'_, DatabaseState>,
+        gallery_id: &str,
+    ) -> Result<VersionListResponse, AppError> {
+        let db = db.lock()?;
+        let gallery = db.gallery().get_by_id(gallery_id)
+            .map_err(|e| AppError::database("Failed to get gallery", e))?
+            .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+        let versions = db.gallery_version().get_by_gallery_id(gallery_id)
+            .map_err(|e| AppError::database("Failed to get versions", e))?;
+
+        Ok(VersionListResponse {
+            current_version_id: gallery.current_version_id,
+            versions,
+        })
+    }
+
+    /// 检出指定版本作为当前版本，效果图随之切换
+    pub fn checkout_version(
+        &self,
+        db: State<'_, DatabaseState>,
+        gallery_id: &str,
+        version_id: &str,
+    ) -> Result<Gallery, AppError> {
+        let db = db.lock()?;
+        checkout(&db, gallery_id, |_| Ok(Some(version_id.to_string())))?
+            .ok_or_else(|| AppError::not_found("版本不存在"))
+    }
+
+    /// 在指定版本上应用本地图片操作，生成新的本地版本并设为当前版本
+    pub async fn apply_local_ops(
+        &self,
+        db: State<'_, DatabaseState>,
+        request: ApplyLocalOpsRequest,
+    ) -> Result<ApplyLocalOpsResponse, AppError> {
+        if request.ops.is_empty() {
+            return Err(AppError::validation("请至少指定一个操作"));
+        }
+        for op in &request.ops {
+            op.validate().map_err(AppError::Validation)?;
+        }
+
+        let (base_version_id, input) = {
+            let db = db.lock()?;
+            let gallery = db.gallery().get_by_id(&request.gallery_id)
+                .map_err(|e| AppError::database("Failed to get gallery", e))?
+                .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+            if gallery.is_editing() {
+                return Err(AppError::validation("该图片正在处理中，请稍后再试"));
+            }
+
+            let base_version_id = request.base_version_id.clone()
+                .or(gallery.current_version_id)
+                .ok_or_else(|| AppError::not_found("版本不存在"))?;
+            let base_version = db.gallery_version().get_by_id(&base_version_id)
+                .map_err(|e| AppError::database("Failed to get version", e))?
+                .filter(|version| version.gallery_id == gallery.id)
+                .ok_or_else(|| AppError::not_found("版本不存在"))?;
+            let input = db.blobs().get(&base_version.image.hash).map_err(AppError::blob)?;
+
+            (base_version_id, input)
+        };
+
+        // 解码和处理大图较慢，放到阻塞线程池中执行，期间不持有数据库锁。
+        // 参数已校验，此时的错误多为裁剪越界等与图片尺寸有关的参数问题
+        let ops = request.ops.clone();
+        let output = tauri::async_runtime::spawn_blocking(move || image_ops::process_image_bytes(&input, &ops))
+            .await
+            .map_err(|e| AppError::Internal(format!("图片处理失败: {}", e)))?
+            .map_err(AppError::Validation)?;
+
+        let db = db.lock()?;
+        // 新版本和图库记录的当前版本一起写入，避免留下未被引用的版本
+        db.transaction(|db| {
+            let mut gallery = db.gallery().get_by_id(&request.gallery_id)
+                .map_err(|e| AppError::database("Failed to get gallery", e))?
+                .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+            if gallery.is_editing() {
+                return Err(AppError::validation("该图片正在处理中，请稍后再试"));
+            }
+
+            let stored_image = db.blobs().put_image(&output, None).map_err(AppError::Internal)?;
+            let version = GalleryVersion {
+                id: Uuid::new_v4().to_string(),
+                gallery_id: gallery.id.clone(),
+                parent_id: Some(base_version_id),
+                source: VERSION_SOURCE_LOCAL.to_string(),
+                prompt: None,
+                style_name: None,
+                model: None,
+                params: Some(
+                    serde_json::to_string(&request.ops)
+                        .map_err(|e| AppError::Internal(format!("Failed to encode ops: {}", e)))?,
+                ),
+                image: stored_image.clone(),
+                input_tokens: 0,
+                output_tokens: 0,
+                create_at: Utc::now().timestamp_millis(),
+            };
+            db.gallery_version().create(&version)
+                .map_err(|e| AppError::database("Failed to create version", e))?;
+
+            gallery.effect_image = Some(stored_image);
+            gallery.current_version_id = Some(version.id.clone());
+            db.gallery().update(&gallery)
+                .map_err(|e| AppError::database("Failed to update gallery", e))?;
+
+            Ok(ApplyLocalOpsResponse { gallery, version })
+        })
+    }
+
+    /// 将同一次编辑生成的另一张候选图设为当前版本
+    pub fn promote_candidate(
+        &self,
+        db: State<'_, DatabaseState>,
+        gallery_id: &str,
+        version_id: &str,
+    ) -> Result<Gallery, AppError> {
+        let db = db.lock()?;
+        let candidate = db.gallery_version().get_by_id(version_id)
+            .map_err(|e| AppError::database("Failed to get version", e))?
+            .ok_or_else(|| AppError::not_found("版本不存在"))?;
+        if candidate.source != VERSION_SOURCE_AI {
+            return Err(AppError::validation("只能提升 AI 生成的候选结果"));
+        }
+
+        checkout(&db, gallery_id, |current| {
+            // 候选图与当前版本是兄弟节点，即同一输入版本下的结果
+            if current.is_some_and(|current| current.parent_id != candidate.parent_id) {
+                return Err(AppError::validation("该版本不是当前编辑的候选结果"));
+            }
+            Ok(Some(candidate.id.clone()))
+        })?
+        .ok_or_else(|| AppError::not_found("版本不存在"))
+    }
+
+    /// 撤销：检出当前版本的父版本
+    pub fn undo_version(&self, db: State<'_, DatabaseState>, gallery_id: &str) -> Result<Gallery, AppError> {
+        let db = db.lock()?;
+        checkout(&db, gallery_id, |current| Ok(current.and_then(|v| v.parent_id.clone())))?
+            .ok_or_else(|| AppError::validation("没有可撤销的版本"))
+    }
+
+    /// 重做：检出当前版本最近创建的子版本
+    pub fn redo_version(&self, db: State<'_, DatabaseState>, gallery_id: &str) -> Result<Gallery, AppError> {
+        let db = db.lock()?;
+        let version_repository = db.gallery_version();
+        checkout(&db, gallery_id, |current| match current {
+            Some(current) => version_repository.get_latest_child(&current.id)
+                .map(|child| child.map(|c| c.id))
+                .map_err(|e| AppError::database("Failed to get version", e)),
+            None => Ok(None),
+        })?
+        .ok_or_else(|| AppError::validation("没有可重做的版本"))
+    }
+
+    /// 比较两个版本的编辑参数
+    pub fn diff_versions(
+        &self,
+        db: State<'_, DatabaseState>,
+        from_version_id: &str,
+        to_version_id: &str,
+    ) -> Result<VersionDiffResponse, AppError> {
+        let db = db.lock()?;
+        let get_version = |id: &str| {
+            db.gallery_version().get_by_id(id)
+                .map_err(|e| AppError::database("Failed to get version", e))?
+                .ok_or_else(|| AppError::not_found(format!("版本不存在: {}", id)))
+        };
+        let from = get_version(from_version_id)?;
+        let to = get_version(to_version_id)?;
+        if from.gallery_id != to.gallery_id {
+            return Err(AppError::validation("只能比较同一图库记录的版本"));
+        }
+
+        let versions = db.gallery_version().get_by_gallery_id(&from.gallery_id)
+            .map_err(|e| AppError::database("Failed to get versions", e))?;
+        let from_lineage = version_lineage(&versions, Some(&from.id));
+        let to_lineage = version_lineage(&versions, Some(&to.id));
+        let common_ancestor_id = from_lineage
+            .iter()
+            .zip(to_lineage.iter())
+            .take_while(|(a, b)| a.id == b.id)
+            .last()
+            .map(|(version, _)| version.id.clone());
+
+        Ok(VersionDiffResponse {
+            changes: diff_version_params(&from, &to),
+            common_ancestor_id,
+            from,
+            to,
+        })
+    }
+
+    /// 批量删除图库记录，并清理不再被引用的图片文件
+    pub fn batch_delete_images(
+        &self,
+        db: State<'_, DatabaseState>,
+        ids: &[String],
+    ) -> Result<(), AppError> {
+        let db = db.lock()?;
+
+        let mut hashes = Vec::new();
+        for id in ids {
+            if let Some(gallery) = db.gallery().get_by_id(id)
+                .map_err(|e| AppError::database("Failed to get gallery", e))?
+            {
+                hashes.push(gallery.origin_image.hash);
+                hashes.extend(gallery.effect_image.map(|image| image.hash));
+                hashes.extend(db.gallery_version().get_image_hashes(id)
+                    .map_err(|e| AppError::database("Failed to get versions", e))?);
+                hashes.extend(db.thumbnail().get_image_hashes(id)
+                    .map_err(|e| AppError::database("Failed to get thumbnails", e))?);
+            }
+        }
+
+        db.gallery().batch_delete(ids)
+            .map_err(|e| AppError::database("Failed to delete images", e))?;
+
+        remove_unreferenced_blobs(&db, hashes)
+    }
+
+    /// 获取图库记录的对话消息
+    pub fn get_gallery_messages(
+        &self,
+        db: State<'_, DatabaseState>,
+        gallery_id: &str,
+    ) -> Result<Vec<Message>, AppError> {
+        let db = db.lock()?;
+        db.message().get_by_gallery_id(gallery_id)
+            .map_err(|e| AppError::database("Failed to get messages", e))
+    }
+
+    /// 删除单条消息
+    pub fn delete_message(&self, db: State<'_, DatabaseState>, id: &str) -> Result<(), AppError> {
+        let db = db.lock()?;
+        db.message().delete(id)
+            .map_err(|e| AppError::database("Failed to delete message", e))
+    }
+
+    /// 修改消息内容，返回修改后的消息
+    pub fn edit_message(
+        &self,
+        db: State<'_, DatabaseState>,
+        request: EditMessageRequest,
+    ) -> Result<Message, AppError> {
+        if request.content.trim().is_empty() {
+            return Err(AppError::validation("消息内容不能为空"));
+        }
+
+        let db = db.lock()?;
+        let mut message = db.message().get_by_id(&request.id)
+            .map_err(|e| AppError::database("Failed to get message", e))?
+            .ok_or_else(|| AppError::not_found("消息不存在"))?;
+        // 编辑任务运行时会读取对话历史，修改需等任务结束
+        let gallery = db.gallery().get_by_id(&message.gallery_id)
+            .map_err(|e| AppError::database("Failed to get gallery", e))?
+            .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+        if gallery.is_editing() {
+            return Err(AppError::validation("该图片正在处理中，请稍后再试"));
+        }
+
+        db.message().update_content(&message.id, &request.content)
+            .map_err(|e| AppError::database("Failed to update message", e))?;
+        message.content = request.content;
+
+        Ok(message)
+    }
+
+    /// 从指定消息重新生成：删除该轮及之后的消息，再以该轮的用户提示词重新编辑。
+    /// 指定的是助手消息时，从它之前最近的一条用户消息开始
+    pub fn regenerate_from_message(
+        &self,
+        db: State<'_, DatabaseState>,
+        job_queue: State<'_, JobQueue>,
+        request: RegenerateRequest,
+    ) -> Result<ImageEditResponse, AppError> {
+        let (gallery_id, job) = {
+            let db = db.lock()?;
+            let setting = check_provider_setting(&db)?;
+
+            // 校验通过后才删除消息，删除和入队在同一事务中，入队失败时消息保留
+            db.transaction(|db| {
+                let message = db.message().get_by_id(&request.message_id)
+                    .map_err(|e| AppError::database("Failed to get message", e))?
+                    .ok_or_else(|| AppError::not_found("消息不存在"))?;
+                let messages = db.message().get_by_gallery_id(&message.gallery_id)
+                    .map_err(|e| AppError::database("Failed to get messages", e))?;
+
+                // 消息已按 (create_at, rowid) 排序，从指定消息的位置向前查找
+                let position = messages.iter().position(|m| m.id == message.id)
+                    .ok_or_else(|| AppError::not_found("消息不存在"))?;
+                let user_message = messages[..=position]
+                    .iter()
+                    .rev()
+                    .find(|m| m.role == "user")
+                    .ok_or_else(|| AppError::not_found("找不到可以重新生成的用户消息"))?;
+
+                let gallery = db.gallery().get_by_id(&message.gallery_id)
+                    .map_err(|e| AppError::database("Failed to get gallery", e))?
+                    .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+                if gallery.is_editing() {
+                    return Err(AppError::validation("该图片正在处理中，请稍后再试"));
+                }
+
+                db.message().delete_from(&gallery.id, &user_message.id)
+                    .map_err(|e| AppError::database("Failed to delete messages", e))?;
+
+                // 以该轮的输入版本和风格为起点分出新分支，原结果保留在版本树中；
+                // 没有记录输入版本的旧消息从当前版本开始
+                let edit_request = ImageEditRequest {
+                    origin_image: None,
+                    gallery_id: Some(gallery.id),
+                    base_version_id: user_message.base_version_id.clone(),
+                    prompt: user_message.content.clone(),
+                    style_name: user_message.style_name.clone(),
+                    request_id: request.request_id,
+                    n: request.n,
+                };
+                insert_edit(db, &setting, edit_request, None, None)
+            })?
+        };
+
+        job_queue.notify();
+
+        Ok(ImageEditResponse {
+            success: true,
+            effect_image: None,
+            gallery_id,
+            message: "图片编辑已加入队列".to_string(),
+            candidate_version_ids: Vec::new(),
+            job_id: Some(job.id),
+        })
+    }
+}
+
+// 删除不再被任何图库记录引用的图片文件
+pub(crate) fn remove_unreferenced_blobs(db: &Database, hashes: Vec<String>) -> Result<(), AppError> {
+    for hash in hashes {
+        let referenced = db.gallery().is_blob_referenced(&hash)
+            .map_err(|e| AppError::database("Failed to check image reference", e))?;
+        if !referenced {
+            // 文件清理失败不影响删除结果，残留文件只占用磁盘空间
+            if let Err(e) = db.blobs().remove(&hash) {
+                log::warn!("删除图片文件 {} 失败: {}", hash, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+// 从根节点到指定版本的路径
+fn version_lineage<'a>(versions: &'a [GalleryVersion], version_id: Option<&str>) -> Vec<&'a GalleryVersion> {
+    let mut lineage = Vec::new();
+    let mut next = version_id;
+    while let Some(id) = next {
+        // 版本树无环，但仍以节点数为上限防止脏数据导致死循环
+        if lineage.len() > versions.len() {
+            break;
+        }
+        let Some(version) = versions.iter().find(|v| v.id == id) else {
+            break;
+        };
+        lineage.push(version);
+        next = version.parent_id.as_deref();
+    }
+    lineage.reverse();
+    lineage
+}
+
+// 检出 target 选出的版本，更新图库的当前版本和效果图；根版本对应原图，检出后没有效果图。
+// target 没有选出版本时不做修改并返回 None，由调用方给出具体提示
+fn checkout<F>(db: &Database, gallery_id: &str, target: F) -> Result<Option<Gallery>, AppError>
+where
+    F: FnOnce(Option<&GalleryVersion>) -> Result<Option<String>, AppError>,
+{
+    let mut gallery = db.gallery().get_by_id(gallery_id)
+        .map_err(|e| AppError::database("Failed to get gallery", e))?
+        .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+    if gallery.is_editing() {
+        return Err(AppError::validation("该图片正在处理中，请稍后再试"));
+    }
+
+    let current = match &gallery.current_version_id {
+        Some(id) => db.gallery_version().get_by_id(id)
+            .map_err(|e| AppError::database("Failed to get version", e))?,
+        None => None,
+    };
+    let Some(target_id) = target(current.as_ref())? else {
+        return Ok(None);
+    };
+    let version = db.gallery_version().get_by_id(&target_id)
+        .map_err(|e| AppError::database("Failed to get version", e))?
+        .filter(|version| version.gallery_id == gallery.id)
+        .ok_or_else(|| AppError::not_found("版本不存在"))?;
+
+    gallery.effect_image = if version.is_root() { None } else { Some(version.image.clone()) };
+    gallery.current_version_id = Some(version.id);
+    db.gallery().update(&gallery)
+        .map_err(|e| AppError::database("Failed to update gallery", e))?;
+
+    Ok(Some(gallery))
+}
+
+// 逐项比较两个版本的编辑参数，只返回有差异的字段
+fn diff_version_params(from: &GalleryVersion, to: &GalleryVersion) -> Vec<VersionChange> {
+    let fields: [(&str, Option<String>, Option<String>); 9] = [
+        ("source", Some(from.source.clone()), Some(to.source.clone())),
+        ("prompt", from.prompt.clone(), to.prompt.clone()),
+        ("style_name", from.style_name.clone(), to.style_name.clone()),
+        ("model", from.model.clone(), to.model.clone()),
+        ("params", from.params.clone(), to.params.clone()),
+        ("image", Some(from.image.hash.clone()), Some(to.image.hash.clone())),
+        (
+            "dimensions",
+            Some(format!("{}x{}", from.image.width, from.image.height)),
+            Some(format!("{}x{}", to.image.width, to.image.height)),
+        ),
+        ("input_tokens", Some(from.input_tokens.to_string()), Some(to.input_tokens.to_string())),
+        ("output_tokens", Some(from.output_tokens.to_string()), Some(to.output_tokens.to_string())),
+    ];
+
+    fields
+        .into_iter()
+        .filter(|(_, from, to)| from != to)
+        .map(|(field, from, to)| VersionChange {
+            field: field.to_string(),
+            from,
+            to,
+        })
+        .collect()
+}
+
+/// 编辑任务的参数，入队时写入 job.payload
+#[derive(Debug, Serialize, Deserialize)]
+struct EditJobPayload {
+    prompt: String,
+    style_name: Option<String>,
+    /// 本次编辑的输入版本，新版本作为它的子节点
+    base_version_id: Option<String>,
+    n: u32,
+    /// 继续编辑前的图库状态，任务取消时恢复
+    previous_status: Option<String>,
+}
+
+/// 编辑任务的结果，写入 job.result
+#[derive(Debug, Serialize, Deserialize)]
+struct EditJobResult {
+    candidate_version_ids: Vec<String>,
+}
+
+/// 新建图库记录使用的原图，已写入 blob 仓库
+pub(crate) struct OriginImage {
+    pub image: StoredImage,
+    pub metadata: ImageMetadata,
+}
+
+impl OriginImage {
+    /// 保存原图并解析元数据；只读写文件，可以在数据库锁之外调用
+    pub(crate) fn store(blobs: &BlobStore, bytes: &[u8], mime_type: Option<&str>) -> Result<Self, AppError> {
+        Ok(Self {
+            image: blobs.put_image(bytes, mime_type).map_err(AppError::Internal)?,
+            metadata: image_ops::metadata::extract_metadata(bytes),
+        })
+    }
+
+    pub(crate) fn from_data_url(blobs: &BlobStore, data_url: &str) -> Result<Self, AppError> {
+        let (mime_type, bytes) = decode_image_data(data_url).map_err(AppError::Validation)?;
+        Self::store(blobs, &bytes, Some(&mime_type))
+    }
+}
+
+/// 创建或载入图库记录，保存用户消息并写入编辑任务。request 没有 gallery_id 时以 origin 新建记录，
+/// batch_id 为所属批次
+pub(crate) fn enqueue_edit(
+    db: &Database,
+    request: ImageEditRequest,
+    origin: Option<OriginImage>,
+    batch_id: Option<&str>,
+) -> Result<(String, Job), AppError> {
+    let setting = check_provider_setting(db)?;
+
+    // 记录、消息和任务要么全部写入，要么全部回滚，避免留下没有任务的记录
+    db.transaction(|db| insert_edit(db, &setting, request, origin, batch_id))
+}
+
+// enqueue_edit 的写入部分，需在事务中调用
+fn insert_edit(
+    db: &Database,
+    setting: &Setting,
+    request: ImageEditRequest,
+    origin: Option<OriginImage>,
+    batch_id: Option<&str>,
+) -> Result<(String, Job), AppError> {
+    if request.n == 0 || request.n > MAX_CANDIDATES {
+        return Err(AppError::Validation(format!("候选结果数量需在 1 到 {} 之间", MAX_CANDIDATES)));
+    }
+
+    let (gallery_id, base_version_id, previous_status) = match &request.gallery_id {
+        // 继续编辑：以当前版本（或指定的分支起点）为输入
+        Some(gallery_id) => {
+            let gallery = db.gallery().get_by_id(gallery_id)
+                .map_err(|e| AppError::database("Failed to get gallery", e))?
+                .ok_or_else(|| AppError::not_found("图库记录不存在"))?;
+            if gallery.is_editing() {
+                return Err(AppError::validation("该图片正在处理中，请稍后再试"));
+            }
+
+            let base_version_id = request.base_version_id.clone().or(gallery.current_version_id.clone());
+            if let Some(version_id) = &base_version_id {
+                db.gallery_version().get_by_id(version_id)
+                    .map_err(|e| AppError::database("Failed to get version", e))?
+                    .filter(|version| version.gallery_id == gallery.id)
+                    .ok_or_else(|| AppError::not_found("版本不存在"))?;
+            }
+
+            db.gallery().update_status(gallery_id, GALLERY_STATUS_PENDING, None)
+                .map_err(|e| AppError::database("Failed to update gallery", e))?;
+
+            (gallery_id.clone(), base_version_id, Some(gallery.status))
+        }
+        None => {
+            let OriginImage { image: stored_image, metadata } = origin
+                .ok_or_else(|| AppError::validation("请先上传图片"))?;
+
+            let gallery_id = Uuid::new_v4().to_string();
+            let root_version_id = Uuid::new_v4().to_string();
+            let create_at = Utc::now().timestamp_millis();
+            let gallery = Gallery {
+                id: gallery_id.clone(),
+                origin_image: stored_image.clone(),
+                effect_image: None,
+                total_input_tokens: 0,
+                total_output_tokens: 0,
+                status: GALLERY_STATUS_PENDING.to_string(),
+                style_name: request.style_name.clone(),
+                create_at,
+                current_version_id: Some(root_version_id.clone()),
+                metadata,
+                error: None,
+            };
+
+            db.gallery().create(&gallery)
+                .map_err(|e| AppError::database("Failed to create gallery", e))?;
+
+            // 原图作为版本树的根节点
+            db.gallery_version().create(&GalleryVersion {
+                id: root_version_id.clone(),
+                gallery_id: gallery_id.clone(),
+                parent_id: None,
+                source: VERSION_SOURCE_ORIGIN.to_string(),
+                prompt: None,
+                style_name: None,
+                model: None,
+                params: None,
+                image: stored_image,
+                input_tokens: 0,
+                output_tokens: 0,
+                create_at,
+            })
+            .map_err(|e| AppError::database("Failed to create version", e))?;
+
+            (gallery_id, Some(root_version_id), None)
+        }
+    };
+
+    // 保存用户消息
+    let user_message = Message {
+        id: Uuid::new_v4().to_string(),
+        gallery_id: gallery_id.clone(),
+        role: "user".to_string(),
+        content: request.prompt.clone(),
+        create_at: Utc::now().timestamp_millis(),
+        base_version_id: base_version_id.clone(),
+        style_name: request.style_name.clone(),
+    };
+
+    db.message().create(&user_message)
+        .map_err(|e| AppError::database("Failed to create message", e))?;
+
+    let payload = EditJobPayload {
+        prompt: request.prompt,
+        style_name: request.style_name,
+        base_version_id,
+        n: request.n,
+        previous_status,
+    };
+    let now = Utc::now().timestamp_millis();
+    let job = Job {
+        id: Uuid::new_v4().to_string(),
+        kind: JOB_KIND_IMAGE_EDIT.to_string(),
+        gallery_id: gallery_id.clone(),
+        request_id: request.request_id,
+        batch_id: batch_id.map(str::to_string),
+        payload: serde_json::to_string(&payload)
+            .map_err(|e| AppError::Internal(format!("Failed to encode job payload: {}", e)))?,
+        status: JOB_STATUS_PENDING.to_string(),
+        attempts: 0,
+        max_attempts: setting.job_max_retries + 1,
+        error: None,
+        result: None,
+        run_after: now,
+        input_tokens: 0,
+        output_tokens: 0,
+        create_at: now,
+        update_at: now,
+    };
+    db.job().create(&job)
+        .map_err(|e| AppError::database("Failed to create job", e))?;
+
+    Ok((gallery_id, job))
+}
+
+/// 将拍摄年份和日期范围（闭区间）转换为 taken_at 的半开区间，同时指定时取交集
+fn taken_range(
+    year: Option<i32>,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<(Option<String>, Option<String>), AppError> {
+    let parse_date = |value: &str| {
+        chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| AppError::Validation(format!("无效的日期: {}，格式应为 YYYY-MM-DD", value)))
+    };
+
+    let mut start = from.map(parse_date).transpose()?;
+    let mut end = to.map(parse_date).transpose()?.and_then(|date| date.succ_opt());
+    if let Some(year) = year {
+        let year_start = chrono::NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(|| AppError::Validation(format!("无效的年份: {}", year)))?;
+        let year_end = chrono::NaiveDate::from_ymd_opt(year + 1, 1, 1).ok_or_else(|| AppError::Validation(format!("无效的年份: {}", year)))?;
+        start = Some(start.map_or(year_start, |date| date.max(year_start)));
+        end = Some(end.map_or(year_end, |date| date.min(year_end)));
+    }
+
+    let format = |date: chrono::NaiveDate| date.format("%Y-%m-%d").to_string();
+    Ok((start.map(format), end.map(format)))
+}
+
+/// 读取设置并校验供应商配置，入队前调用以便尽早提示
+pub(crate) fn check_provider_setting(db: &Database) -> Result<Setting, AppError> {
+    let setting = db.setting().get_or_create_default()
+        .map_err(|e| AppError::database("Failed to get settings", e))?;
+    let config = ProviderConfig::new(&setting.provider, setting.api_url.clone(), setting.api_key.clone())
+        .map_err(AppError::Config)?;
+    if config.kind.requires_api_key() && config.api_key.is_empty() {
+        return Err(AppError::config("请先配置API密钥"));
+    }
+    // 不支持图片编辑的供应商入队后只会白白消耗一次对话调用
+    if !config.kind.supports_image_edit() {
+        return Err(AppError::Config(format!("{} 不支持图片编辑，请切换供应商", config.kind)));
+    }
+    Ok(setting)
+}
+
+/// 编辑任务最终失败或被取消时更新图库状态：失败时标记为失败并记录原因，可通过 retry_edit 重试；
+/// 取消时继续编辑的记录恢复原状态，新建的记录标记为已取消
+pub(crate) fn abort_edit_job(db: &Database, job: &Job, error: Option<&str>) -> Result<(), AppError> {
+    let result = match error {
+        Some(error) => db.gallery().update_status(&job.gallery_id, GALLERY_STATUS_FAILED, Some(error)),
+        None => {
+            let previous_status = serde_json::from_str::<EditJobPayload>(&job.payload)
+                .ok()
+                .and_then(|payload| payload.previous_status);
+            let status = previous_status.as_deref().unwrap_or(GALLERY_STATUS_CANCELLED);
+            db.gallery().update_status(&job.gallery_id, status, None)
+        }
+    };
+    result.map_err(|e| AppError::database("Failed to update gallery", e))
+}
@@ -0,0 +1,134 @@
+use std::sync::{Arc, Mutex};
+use chrono::Utc;
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+
+use crate::database::{Database, GalleryThumbnail, StaleThumbnail};
+use crate::image_ops::thumbnail::{generate_thumbnails, THUMBNAIL_SIZES};
+use super::service::remove_unreferenced_blobs;
+
+/// 每轮处理的记录数
+const BATCH_SIZE: u32 = 20;
+
+/// 数据库出错后的等待时间（毫秒），避免空转
+const ERROR_DELAY_MS: u64 = 5000;
+
+type DatabaseState = Mutex<Database>;
+
+/// 缩略图后台生成队列，待处理的记录直接由 gallery 与 gallery_thumbnail 表比对得出
+pub struct ThumbnailQueue {
+    notify: Arc<Notify>,
+}
+
+impl ThumbnailQueue {
+    pub fn new() -> Self {
+        Self {
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    /// 图库记录新建或效果图变化后调用，唤醒后台线程生成缩略图
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    /// 启动后台线程，先补齐已有记录的缩略图，之后按通知处理
+    pub fn start(&self, app: &AppHandle) {
+        tauri::async_runtime::spawn(worker_loop(app.clone(), self.notify.clone()));
+    }
+}
+
+async fn worker_loop(app: AppHandle, notify: Arc<Notify>) {
+    loop {
+        let stale = {
+            let db = app.state::<DatabaseState>();
+            db.lock()
+                .map_err(|e| format!("Database lock error: {}", e))
+                .and_then(|db| db.thumbnail().find_stale(BATCH_SIZE)
+                    .map_err(|e| format!("Failed to get thumbnails: {}", e)))
+        };
+
+        match stale {
+            Ok(stale) if !stale.is_empty() => {
+                for item in stale {
+                    if let Err(e) = generate(&app, &item).await {
+                        log::warn!("生成图库 {} 的缩略图失败: {}", item.gallery_id, e);
+                        tokio::time::sleep(tokio::time::Duration::from_millis(ERROR_DELAY_MS)).await;
+                    }
+                }
+            }
+            Ok(_) => notify.notified().await,
+            Err(e) => {
+                log::error!("查询待生成的缩略图失败: {}", e);
+                tokio::time::sleep(tokio::time::Duration::from_millis(ERROR_DELAY_MS)).await;
+            }
+        }
+    }
+}
+
+// 生成一条记录的全部规格；图片无法解码时记录失败原因，图片变化前不再重试
+async fn generate(app: &AppHandle, item: &StaleThumbnail) -> Result<(), String> {
+    let source = {
+        let db = app.state::<DatabaseState>();
+        let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+        db.blobs().get(&item.source_hash).map_err(|e| format!("读取图片失败: {}", e))
+    };
+    let result = match source {
+        Ok(bytes) => tauri::async_runtime::spawn_blocking(move || generate_thumbnails(&bytes))
+            .await
+            .map_err(|e| format!("缩略图任务异常: {}", e))?,
+        Err(e) => Err(e),
+    };
+
+    let db = app.state::<DatabaseState>();
+    let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+
+    // 生成期间记录可能已被删除
+    if db.gallery().get_by_id(&item.gallery_id)
+        .map_err(|e| format!("Failed to get gallery: {}", e))?
+        .is_none()
+    {
+        return Ok(());
+    }
+    let previous_hashes = db.thumbnail().get_image_hashes(&item.gallery_id)
+        .map_err(|e| format!("Failed to get thumbnails: {}", e))?;
+
+    let create_at = Utc::now().timestamp_millis();
+    let thumbnails = match result {
+        Ok(thumbnails) => thumbnails
+            .into_iter()
+            .map(|(size, bytes)| {
+                Ok(GalleryThumbnail {
+                    gallery_id: item.gallery_id.clone(),
+                    size,
+                    source_hash: item.source_hash.clone(),
+                    image: Some(db.blobs().put_image(&bytes, None)?),
+                    error: None,
+                    create_at,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?,
+        Err(e) => {
+            log::warn!("图库 {} 无法生成缩略图: {}", item.gallery_id, e);
+            THUMBNAIL_SIZES
+                .into_iter()
+                .map(|size| GalleryThumbnail {
+                    gallery_id: item.gallery_id.clone(),
+                    size,
+                    source_hash: item.source_hash.clone(),
+                    image: None,
+                    error: Some(e.clone()),
+                    create_at,
+                })
+                .collect()
+        }
+    };
+
+    for thumbnail in &thumbnails {
+        db.thumbnail().save(thumbnail)
+            .map_err(|e| format!("Failed to save thumbnail: {}", e))?;
+    }
+
+    // 清理被替换的旧缩略图文件
+    remove_unreferenced_blobs(&db, previous_hashes).map_err(String::from)
+}
@@ -0,0 +1,105 @@
+/// 常用 EXIF 标签
+pub const TAG_MAKE: u16 = 0x010F;
+pub const TAG_MODEL: u16 = 0x0110;
+pub const TAG_ORIENTATION: u16 = 0x0112;
+pub const TAG_DATE_TIME: u16 = 0x0132;
+pub const TAG_ARTIST: u16 = 0x013B;
+pub const TAG_COPYRIGHT: u16 = 0x8298;
+pub const TAG_EXIF_IFD: u16 = 0x8769;
+pub const TAG_GPS_IFD: u16 = 0x8825;
+pub const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+pub const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+pub const TAG_CAMERA_OWNER: u16 = 0xA430;
+pub const TAG_BODY_SERIAL: u16 = 0xA431;
+pub const TAG_LENS_MODEL: u16 = 0xA434;
+pub const TAG_LENS_SERIAL: u16 = 0xA435;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+// TIFF 字段类型
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    /// 值字段（4 字节）在 TIFF 数据中的位置
+    value_pos: usize,
+}
+
+/// EXIF 块（TIFF 结构）的只读解析，只读取 IFD0 和 EXIF 子目录
+pub struct ExifReader<'a> {
+    tiff: &'a [u8],
+    little_endian: bool,
+    entries: Vec<Entry>,
+}
+
+impl<'a> ExifReader<'a> {
+    /// 解析 EXIF 块，兼容带或不带 "Exif\0\0" 前缀的数据
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let tiff = data.strip_prefix(EXIF_HEADER).unwrap_or(data);
+        let little_endian = match tiff.get(0..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let mut reader = Self { tiff, little_endian, entries: Vec::new() };
+
+        let ifd0 = reader.read_u32(4)? as usize;
+        reader.entries = reader.read_ifd(ifd0);
+        if let Some(exif_ifd) = reader.entry(TAG_EXIF_IFD).and_then(|entry| reader.read_u32(entry.value_pos)) {
+            let sub_entries = reader.read_ifd(exif_ifd as usize);
+            reader.entries.extend(sub_entries);
+        }
+        Some(reader)
+    }
+
+    pub fn has_tag(&self, tag: u16) -> bool {
+        self.entry(tag).is_some()
+    }
+
+    /// 读取 ASCII 标签，去掉结尾的空字符和空白
+    pub fn ascii(&self, tag: u16) -> Option<String> {
+        let entry = self.entry(tag).filter(|entry| entry.field_type == TYPE_ASCII)?;
+        let count = entry.count as usize;
+        let start = if count <= 4 { entry.value_pos } else { self.read_u32(entry.value_pos)? as usize };
+        let bytes = self.tiff.get(start..start.checked_add(count)?)?;
+        let value = String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string();
+        (!value.is_empty()).then_some(value)
+    }
+
+    pub fn short(&self, tag: u16) -> Option<u16> {
+        let entry = self.entry(tag).filter(|entry| entry.field_type == TYPE_SHORT)?;
+        self.read_u16(entry.value_pos)
+    }
+
+    fn entry(&self, tag: u16) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+
+    fn read_ifd(&self, offset: usize) -> Vec<Entry> {
+        let count = self.read_u16(offset).unwrap_or(0) as usize;
+        (0..count)
+            .map_while(|i| {
+                let pos = offset + 2 + i * 12;
+                Some(Entry {
+                    tag: self.read_u16(pos)?,
+                    field_type: self.read_u16(pos + 2)?,
+                    count: self.read_u32(pos + 4)?,
+                    value_pos: pos + 8,
+                })
+            })
+            .collect()
+    }
+
+    fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.tiff.get(offset..offset.checked_add(2)?)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn read_u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.tiff.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+}
@@ -0,0 +1,131 @@
+use chrono::NaiveDateTime;
+use image::{ImageDecoder, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+use super::exif::{
+    ExifReader, TAG_DATE_TIME, TAG_DATE_TIME_DIGITIZED, TAG_DATE_TIME_ORIGINAL, TAG_MAKE, TAG_MODEL,
+    TAG_ORIENTATION,
+};
+
+/// 导入时从原图解析的元数据，尺寸、格式和文件大小记录在 StoredImage 中
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ImageMetadata {
+    /// 像素颜色类型，如 Rgb8、Rgba8
+    pub color_type: Option<String>,
+    /// EXIF 拍摄时间，格式为 YYYY-MM-DDTHH:MM:SS，为相机本地时间
+    pub taken_at: Option<String>,
+    /// 相机型号，包含厂商名
+    pub camera_model: Option<String>,
+    /// EXIF 方向，1-8
+    pub orientation: Option<u16>,
+}
+
+/// 解析图片元数据，无法识别的字段留空，不会因元数据损坏而失败
+pub fn extract_metadata(bytes: &[u8]) -> ImageMetadata {
+    let Ok(mut decoder) = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(image::ImageError::from)
+        .and_then(|reader| reader.into_decoder())
+    else {
+        return ImageMetadata::default();
+    };
+
+    let color_type = Some(format!("{:?}", decoder.color_type()));
+    let exif_data = decoder.exif_metadata().ok().flatten();
+    let Some(exif) = exif_data.as_deref().and_then(ExifReader::parse) else {
+        return ImageMetadata { color_type, ..Default::default() };
+    };
+
+    let taken_at = [TAG_DATE_TIME_ORIGINAL, TAG_DATE_TIME_DIGITIZED, TAG_DATE_TIME]
+        .into_iter()
+        .find_map(|tag| exif.ascii(tag).as_deref().and_then(parse_exif_datetime));
+    let camera_model = match (exif.ascii(TAG_MAKE), exif.ascii(TAG_MODEL)) {
+        // 多数厂商的型号已包含厂商名
+        (Some(make), Some(model)) if !model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            Some(format!("{} {}", make, model))
+        }
+        (make, model) => model.or(make),
+    };
+
+    ImageMetadata {
+        color_type,
+        taken_at,
+        camera_model,
+        orientation: exif.short(TAG_ORIENTATION).filter(|value| (1..=8).contains(value)),
+    }
+}
+
+// EXIF 时间格式为 "YYYY:MM:DD HH:MM:SS"，未设置时常写为全零
+fn parse_exif_datetime(value: &str) -> Option<String> {
+    NaiveDateTime::parse_from_str(value, "%Y:%m:%d %H:%M:%S")
+        .ok()
+        .map(|datetime| datetime.format("%Y-%m-%dT%H:%M:%S").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+
+    // 构造大端 TIFF：IFD0 含 Make、Model、Orientation 和 EXIF 子目录，子目录含拍摄时间
+    fn exif_block() -> Vec<u8> {
+        let make = b"Canon\0";
+        let model = b"Canon EOS R5\0";
+        let taken_at = b"2025:03:14 09:26:53\0";
+        let ifd0_len = 2 + 4 * 12 + 4;
+        let exif_ifd_len = 2 + 12 + 4;
+        let make_pos = 8 + ifd0_len + exif_ifd_len;
+        let model_pos = make_pos + make.len();
+        let taken_at_pos = model_pos + model.len();
+
+        let entry = |tag: u16, field_type: u16, count: u32, value: [u8; 4]| {
+            let mut entry = tag.to_be_bytes().to_vec();
+            entry.extend_from_slice(&field_type.to_be_bytes());
+            entry.extend_from_slice(&count.to_be_bytes());
+            entry.extend_from_slice(&value);
+            entry
+        };
+
+        let mut data = b"Exif\0\0MM\0\x2a".to_vec();
+        data.extend_from_slice(&8u32.to_be_bytes());
+        data.extend_from_slice(&4u16.to_be_bytes());
+        data.extend(entry(TAG_MAKE, 2, make.len() as u32, (make_pos as u32).to_be_bytes()));
+        data.extend(entry(TAG_MODEL, 2, model.len() as u32, (model_pos as u32).to_be_bytes()));
+        data.extend(entry(TAG_ORIENTATION, 3, 1, [0, 6, 0, 0]));
+        data.extend(entry(0x8769, 4, 1, ((8 + ifd0_len) as u32).to_be_bytes()));
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend(entry(TAG_DATE_TIME_ORIGINAL, 2, taken_at.len() as u32, (taken_at_pos as u32).to_be_bytes()));
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(make);
+        data.extend_from_slice(model);
+        data.extend_from_slice(taken_at);
+        data
+    }
+
+    #[test]
+    fn test_extract_metadata() {
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(4, 4))
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+        let exif = exif_block();
+        let mut input = jpeg[..2].to_vec();
+        input.extend_from_slice(&[0xFF, 0xE1]);
+        input.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        input.extend_from_slice(&exif);
+        input.extend_from_slice(&jpeg[2..]);
+
+        let metadata = extract_metadata(&input);
+        assert_eq!(metadata.color_type.as_deref(), Some("Rgb8"));
+        assert_eq!(metadata.taken_at.as_deref(), Some("2025-03-14T09:26:53"));
+        assert_eq!(metadata.camera_model.as_deref(), Some("Canon EOS R5"));
+        assert_eq!(metadata.orientation, Some(6));
+
+        // 没有 EXIF 时只有颜色类型
+        let metadata = extract_metadata(&jpeg);
+        assert_eq!(metadata, ImageMetadata { color_type: Some("Rgb8".to_string()), ..Default::default() });
+        assert_eq!(extract_metadata(b"not an image"), ImageMetadata::default());
+    }
+}
@@ -0,0 +1,317 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+pub mod exif;
+pub mod metadata;
+pub mod preprocess;
+pub mod privacy;
+pub mod thumbnail;
+
+/// 编码 JPEG 时使用的质量
+const JPEG_QUALITY: u8 = 90;
+
+/// 单边尺寸上限，避免误操作生成超大图片
+pub(crate) const MAX_DIMENSION: u32 = 16384;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlipDirection {
+    Horizontal,
+    Vertical,
+}
+
+/// 本地图片操作，按顺序依次应用，不调用 AI 接口
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum LocalOp {
+    /// 按像素区域裁剪
+    Crop { x: u32, y: u32, width: u32, height: u32 },
+    /// 按宽高比居中裁剪，如 1:1、16:9
+    CropAspect { ratio_width: u32, ratio_height: u32 },
+    /// 缩放；只给出一边时按原比例计算另一边
+    Resize { width: Option<u32>, height: Option<u32> },
+    /// 顺时针旋转，只支持 90 的倍数
+    Rotate { degrees: i32 },
+    Flip { direction: FlipDirection },
+    /// 亮度，-255 到 255
+    Brightness { value: i32 },
+    /// 对比度，-100 到 100
+    Contrast { value: f32 },
+    /// 饱和度，-100（灰度）到 100（加倍）
+    Saturation { value: f32 },
+    /// 色相旋转角度
+    Hue { degrees: i32 },
+    Blur { sigma: f32 },
+    Sharpen { sigma: f32, threshold: i32 },
+    Grayscale,
+    Sepia,
+    /// 色阶：将 [black, white] 映射到 [0, 255]，gamma 大于 1 提亮中间调
+    Levels { black: u8, white: u8, gamma: f32 },
+}
+
+impl LocalOp {
+    /// 校验参数取值
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            LocalOp::Crop { width, height, .. } if width == 0 || height == 0 => {
+                Err("裁剪区域不能为空".to_string())
+            }
+            LocalOp::CropAspect { ratio_width, ratio_height } if ratio_width == 0 || ratio_height == 0 => {
+                Err("宽高比必须大于 0".to_string())
+            }
+            LocalOp::Resize { width: None, height: None } => Err("缩放需要指定宽度或高度".to_string()),
+            LocalOp::Resize { width, height }
+                if width.is_some_and(|w| w == 0 || w > MAX_DIMENSION)
+                    || height.is_some_and(|h| h == 0 || h > MAX_DIMENSION) =>
+            {
+                Err(format!("缩放尺寸需在 1 到 {} 之间", MAX_DIMENSION))
+            }
+            LocalOp::Rotate { degrees } if degrees % 90 != 0 => Err("旋转角度必须是 90 的倍数".to_string()),
+            LocalOp::Brightness { value } if !(-255..=255).contains(&value) => {
+                Err("亮度需在 -255 到 255 之间".to_string())
+            }
+            LocalOp::Contrast { value } | LocalOp::Saturation { value } if !(-100.0..=100.0).contains(&value) => {
+                Err("对比度和饱和度需在 -100 到 100 之间".to_string())
+            }
+            LocalOp::Blur { sigma } | LocalOp::Sharpen { sigma, .. } if !(sigma > 0.0 && sigma <= 100.0) => {
+                Err("模糊半径需在 0 到 100 之间".to_string())
+            }
+            LocalOp::Levels { black, white, gamma } if black >= white || !(gamma > 0.0 && gamma <= 10.0) => {
+                Err("色阶参数无效".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&self, image: DynamicImage) -> Result<DynamicImage, String> {
+        let (width, height) = (image.width(), image.height());
+        let image = match *self {
+            LocalOp::Crop { x, y, width: crop_width, height: crop_height } => {
+                if x >= width || y >= height {
+                    return Err("裁剪区域超出图片范围".to_string());
+                }
+                image.crop_imm(x, y, crop_width.min(width - x), crop_height.min(height - y))
+            }
+            LocalOp::CropAspect { ratio_width, ratio_height } => {
+                let (crop_width, crop_height) = aspect_crop_size(width, height, ratio_width, ratio_height);
+                image.crop_imm((width - crop_width) / 2, (height - crop_height) / 2, crop_width, crop_height)
+            }
+            LocalOp::Resize { width: target_width, height: target_height } => {
+                let scale = |value: u32, from: u32, to: u32| {
+                    ((value as u64 * to as u64 + from as u64 / 2) / from.max(1) as u64).clamp(1, MAX_DIMENSION as u64) as u32
+                };
+                let (new_width, new_height) = match (target_width, target_height) {
+                    (Some(w), Some(h)) => (w, h),
+                    (Some(w), None) => (w, scale(height, width, w)),
+                    (None, Some(h)) => (scale(width, height, h), h),
+                    (None, None) => (width, height),
+                };
+                image.resize_exact(new_width, new_height, FilterType::Lanczos3)
+            }
+            LocalOp::Rotate { degrees } => match degrees.rem_euclid(360) {
+                90 => image.rotate90(),
+                180 => image.rotate180(),
+                270 => image.rotate270(),
+                _ => image,
+            },
+            LocalOp::Flip { direction: FlipDirection::Horizontal } => image.fliph(),
+            LocalOp::Flip { direction: FlipDirection::Vertical } => image.flipv(),
+            LocalOp::Brightness { value } => image.brighten(value),
+            LocalOp::Contrast { value } => image.adjust_contrast(value),
+            LocalOp::Saturation { value } => map_pixels(image, |pixel| saturate(pixel, 1.0 + value / 100.0)),
+            LocalOp::Hue { degrees } => image.huerotate(degrees),
+            LocalOp::Blur { sigma } => image.blur(sigma),
+            LocalOp::Sharpen { sigma, threshold } => image.unsharpen(sigma, threshold),
+            LocalOp::Grayscale => image.grayscale(),
+            LocalOp::Sepia => map_pixels(image, sepia),
+            LocalOp::Levels { black, white, gamma } => {
+                let table = levels_table(black, white, gamma);
+                map_pixels(image, |Rgba([r, g, b, a])| {
+                    Rgba([table[r as usize], table[g as usize], table[b as usize], a])
+                })
+            }
+        };
+        Ok(image)
+    }
+}
+
+/// 按顺序应用全部操作
+pub fn apply_ops(image: DynamicImage, ops: &[LocalOp]) -> Result<DynamicImage, String> {
+    ops.iter().try_fold(image, |image, op| op.apply(image))
+}
+
+/// 解码图片、按 EXIF 方向摆正后应用操作并重新编码；JPEG 输入保持 JPEG，其余格式输出 PNG 以保留透明度。
+/// 重新编码的图片不带 EXIF，因此必须先摆正，否则结果会按未旋转的方向显示
+pub fn process_image_bytes(bytes: &[u8], ops: &[LocalOp]) -> Result<Vec<u8>, String> {
+    if ops.is_empty() {
+        return Err("请至少指定一个操作".to_string());
+    }
+    for op in ops {
+        op.validate()?;
+    }
+
+    let (image, format) = decode_oriented(bytes)?;
+    let image = apply_ops(image, ops)?;
+
+    encode_image(&image, format)
+}
+
+/// 解码图片并按 EXIF 方向摆正，同时返回原格式
+pub fn decode_oriented(bytes: &[u8]) -> Result<(DynamicImage, ImageFormat), String> {
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("读取图片失败: {}", e))?;
+    let format = reader.format().ok_or_else(|| "无法识别图片格式".to_string())?;
+    let mut decoder = reader.into_decoder().map_err(|e| format!("解码图片失败: {}", e))?;
+    let orientation = decoder.orientation().ok();
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| format!("解码图片失败: {}", e))?;
+    if let Some(orientation) = orientation {
+        image.apply_orientation(orientation);
+    }
+    Ok((image, format))
+}
+
+/// 编码图片，JPEG 以外的格式统一编码为 PNG
+pub fn encode_image(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let result = if format == ImageFormat::Jpeg {
+        image.to_rgb8().write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY))
+    } else {
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+    };
+    result.map_err(|e| format!("编码图片失败: {}", e))?;
+    Ok(bytes)
+}
+
+// 在原图内取最大的指定宽高比区域
+fn aspect_crop_size(width: u32, height: u32, ratio_width: u32, ratio_height: u32) -> (u32, u32) {
+    let (width, height) = (width as u64, height as u64);
+    let (ratio_width, ratio_height) = (ratio_width as u64, ratio_height as u64);
+    if width * ratio_height > height * ratio_width {
+        ((height * ratio_width / ratio_height).max(1) as u32, height as u32)
+    } else {
+        (width as u32, (width * ratio_height / ratio_width).max(1) as u32)
+    }
+}
+
+fn map_pixels<F>(image: DynamicImage, f: F) -> DynamicImage
+where
+    F: Fn(Rgba<u8>) -> Rgba<u8>,
+{
+    let mut buffer: RgbaImage = image.into_rgba8();
+    for pixel in buffer.pixels_mut() {
+        *pixel = f(*pixel);
+    }
+    DynamicImage::ImageRgba8(buffer)
+}
+
+// 以 Rec.601 亮度为中心按比例拉伸或收拢各通道
+fn saturate(Rgba([r, g, b, a]): Rgba<u8>, factor: f32) -> Rgba<u8> {
+    let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    let adjust = |channel: u8| (luma + (channel as f32 - luma) * factor).round().clamp(0.0, 255.0) as u8;
+    Rgba([adjust(r), adjust(g), adjust(b), a])
+}
+
+fn sepia(Rgba([r, g, b, a]): Rgba<u8>) -> Rgba<u8> {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let channel = |value: f32| value.round().min(255.0) as u8;
+    Rgba([
+        channel(0.393 * r + 0.769 * g + 0.189 * b),
+        channel(0.349 * r + 0.686 * g + 0.168 * b),
+        channel(0.272 * r + 0.534 * g + 0.131 * b),
+        a,
+    ])
+}
+
+fn levels_table(black: u8, white: u8, gamma: f32) -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let range = (white - black) as f32;
+    for (value, entry) in table.iter_mut().enumerate() {
+        let normalized = ((value as f32 - black as f32) / range).clamp(0.0, 1.0);
+        *entry = (normalized.powf(1.0 / gamma) * 255.0).round() as u8;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, pixel: [u8; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(pixel)))
+    }
+
+    #[test]
+    fn test_geometry_ops() {
+        let image = solid(400, 300, [10, 20, 30, 255]);
+        let ops = [
+            LocalOp::CropAspect { ratio_width: 1, ratio_height: 1 },
+            LocalOp::Resize { width: Some(100), height: None },
+        ];
+        let result = apply_ops(image.clone(), &ops).unwrap();
+        assert_eq!((result.width(), result.height()), (100, 100));
+
+        let rotated = apply_ops(image.clone(), &[LocalOp::Rotate { degrees: -90 }]).unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (300, 400));
+
+        let cropped = apply_ops(image, &[LocalOp::Crop { x: 350, y: 0, width: 100, height: 10 }]).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (50, 10));
+    }
+
+    #[test]
+    fn test_color_ops() {
+        let image = solid(2, 2, [100, 150, 200, 128]);
+
+        let gray = apply_ops(image.clone(), &[LocalOp::Saturation { value: -100.0 }]).unwrap().to_rgba8();
+        let Rgba([r, g, b, a]) = *gray.get_pixel(0, 0);
+        assert!(r == g && g == b);
+        assert_eq!(a, 128);
+
+        let levels = apply_ops(image, &[LocalOp::Levels { black: 100, white: 200, gamma: 1.0 }])
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(*levels.get_pixel(0, 0), Rgba([0, 128, 255, 128]));
+    }
+
+    #[test]
+    fn test_process_applies_orientation() {
+        // 只含 Orientation = 6（顺时针旋转 90 度）的大端 EXIF
+        let mut exif = b"Exif\0\0MM\0\x2a".to_vec();
+        exif.extend_from_slice(&8u32.to_be_bytes());
+        exif.extend_from_slice(&1u16.to_be_bytes());
+        exif.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0]);
+        exif.extend_from_slice(&0u32.to_be_bytes());
+
+        let mut jpeg = Vec::new();
+        solid(40, 20, [10, 20, 30, 255])
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new(&mut jpeg))
+            .unwrap();
+        let mut input = jpeg[..2].to_vec();
+        input.extend_from_slice(&[0xFF, 0xE1]);
+        input.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        input.extend_from_slice(&exif);
+        input.extend_from_slice(&jpeg[2..]);
+
+        let output = process_image_bytes(&input, &[LocalOp::Grayscale]).unwrap();
+        let result = image::load_from_memory(&output).unwrap();
+        assert_eq!((result.width(), result.height()), (20, 40));
+    }
+
+    #[test]
+    fn test_validate_and_deserialize() {
+        assert!(LocalOp::Rotate { degrees: 45 }.validate().is_err());
+        assert!(LocalOp::Levels { black: 200, white: 100, gamma: 1.0 }.validate().is_err());
+        assert!(LocalOp::Resize { width: None, height: None }.validate().is_err());
+
+        let ops: Vec<LocalOp> = serde_json::from_str(
+            r#"[{"op":"crop_aspect","ratio_width":16,"ratio_height":9},{"op":"flip","direction":"horizontal"},{"op":"sepia"}]"#,
+        )
+        .unwrap();
+        assert_eq!(ops[1], LocalOp::Flip { direction: FlipDirection::Horizontal });
+        assert_eq!(ops[2], LocalOp::Sepia);
+    }
+}
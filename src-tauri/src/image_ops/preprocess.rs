@@ -0,0 +1,269 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Cursor;
+use std::str::FromStr;
+
+use crate::ai_service::{decode_image_data, ProviderKind};
+
+/// 重新编码时的默认质量
+pub const DEFAULT_QUALITY: u8 = 85;
+
+/// 上传前的重新编码格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// 供应商接受原格式时保留，否则转为 JPEG（带透明度时转为 PNG）
+    Auto,
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Auto => "auto",
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+        }
+    }
+
+    fn image_format(&self) -> Option<ImageFormat> {
+        match self {
+            OutputFormat::Auto => None,
+            OutputFormat::Jpeg => Some(ImageFormat::Jpeg),
+            OutputFormat::Png => Some(ImageFormat::Png),
+            OutputFormat::Webp => Some(ImageFormat::WebP),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(OutputFormat::Auto),
+            "jpeg" | "jpg" => Ok(OutputFormat::Jpeg),
+            "png" => Ok(OutputFormat::Png),
+            "webp" => Ok(OutputFormat::Webp),
+            other => Err(format!("不支持的输出格式: {}", other)),
+        }
+    }
+}
+
+/// 上传给 AI 供应商前的图片预处理配置，每个供应商单独保存
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreprocessOptions {
+    /// 根据文件头识别真实格式，而不是信任 data URL 中声明的 MIME 类型
+    pub sniff_mime: bool,
+    /// 按 EXIF 方向信息旋转图片
+    pub apply_orientation: bool,
+    /// 长边上限，超出时等比缩小；为空时不缩放
+    pub max_edge: Option<u32>,
+    pub output_format: OutputFormat,
+    /// JPEG 编码质量 1-100，PNG 和 WebP 为无损编码，不受影响
+    pub quality: u8,
+}
+
+impl PreprocessOptions {
+    /// 供应商的默认配置，长边上限参考各家对输入图片的建议尺寸
+    pub fn for_provider(kind: ProviderKind) -> Self {
+        let max_edge = match kind {
+            ProviderKind::OpenAI => 2048,
+            ProviderKind::Anthropic => 1568,
+            ProviderKind::Gemini => 3072,
+            ProviderKind::Ollama => 1024,
+        };
+        Self {
+            sniff_mime: true,
+            apply_orientation: true,
+            max_edge: Some(max_edge),
+            output_format: OutputFormat::Auto,
+            quality: DEFAULT_QUALITY,
+        }
+    }
+
+    /// 校验取值，指定的输出格式必须是供应商接受的格式
+    pub fn validate(&self, kind: ProviderKind) -> Result<(), String> {
+        if !(1..=100).contains(&self.quality) {
+            return Err("编码质量需在 1 到 100 之间".to_string());
+        }
+        if self.max_edge.is_some_and(|edge| !(64..=super::MAX_DIMENSION).contains(&edge)) {
+            return Err(format!("长边上限需在 64 到 {} 之间", super::MAX_DIMENSION));
+        }
+        if let Some(format) = self.output_format.image_format() {
+            if !kind.accepted_image_types().contains(&format.to_mime_type()) {
+                return Err(format!("{} 不接受 {} 格式的图片", kind, self.output_format));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 根据文件头识别图片的 MIME 类型
+pub fn sniff_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    image::guess_format(bytes).ok().map(|format| format.to_mime_type())
+}
+
+/// 按配置预处理图片，返回 data URL
+///
+/// 只有需要旋转、缩放或转换格式时才解码并重新编码，否则原样返回数据并修正 MIME 类型
+pub fn preprocess_image(
+    image_data: &str,
+    options: &PreprocessOptions,
+    accepted_types: &[&str],
+) -> Result<String, String> {
+    let (declared_mime, bytes) = decode_image_data(image_data)?;
+
+    let source_format = if options.sniff_mime {
+        Some(image::guess_format(&bytes).map_err(|e| format!("无法识别图片格式: {}", e))?)
+    } else {
+        ImageFormat::from_mime_type(&declared_mime)
+    };
+    // 未开启识别且声明的格式无法解码时不做处理
+    let Some(source_format) = source_format else {
+        return Ok(to_data_url(&declared_mime, &bytes));
+    };
+
+    let mut decoder = ImageReader::with_format(Cursor::new(&bytes), source_format)
+        .into_decoder()
+        .map_err(|e| format!("解码图片失败: {}", e))?;
+    let (width, height) = decoder.dimensions();
+    let has_alpha = decoder.color_type().has_alpha();
+    let orientation = if options.apply_orientation {
+        decoder.orientation().unwrap_or(Orientation::NoTransforms)
+    } else {
+        Orientation::NoTransforms
+    };
+
+    let target_format = target_format(options.output_format, source_format, has_alpha, accepted_types);
+    let needs_resize = options.max_edge.is_some_and(|edge| width.max(height) > edge);
+    if target_format == source_format && orientation == Orientation::NoTransforms && !needs_resize {
+        return Ok(to_data_url(source_format.to_mime_type(), &bytes));
+    }
+
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| format!("解码图片失败: {}", e))?;
+    image.apply_orientation(orientation);
+    if let Some(edge) = options.max_edge.filter(|_| needs_resize) {
+        image = image.resize(edge, edge, FilterType::Lanczos3);
+    }
+
+    // GIF 等格式重新编码时改用 PNG
+    let target_format = match target_format {
+        ImageFormat::Jpeg | ImageFormat::WebP => target_format,
+        _ => ImageFormat::Png,
+    };
+    let output = encode(&image, target_format, options.quality)?;
+    log::info!(
+        "图片预处理: {} {}x{} ({} 字节) -> {} {}x{} ({} 字节)",
+        source_format.to_mime_type(), width, height, bytes.len(),
+        target_format.to_mime_type(), image.width(), image.height(), output.len()
+    );
+    Ok(to_data_url(target_format.to_mime_type(), &output))
+}
+
+// 确定上传格式，供应商接受原格式时优先保留
+fn target_format(
+    output_format: OutputFormat,
+    source_format: ImageFormat,
+    has_alpha: bool,
+    accepted_types: &[&str],
+) -> ImageFormat {
+    if let Some(format) = output_format.image_format() {
+        return format;
+    }
+    if accepted_types.contains(&source_format.to_mime_type()) {
+        return source_format;
+    }
+    let png_accepted = accepted_types.contains(&ImageFormat::Png.to_mime_type());
+    if (has_alpha && png_accepted) || !accepted_types.contains(&ImageFormat::Jpeg.to_mime_type()) {
+        ImageFormat::Png
+    } else {
+        ImageFormat::Jpeg
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat, quality: u8) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let result = match format {
+        ImageFormat::Jpeg => image.to_rgb8().write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality)),
+        ImageFormat::WebP if image.color().has_alpha() => {
+            DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut Cursor::new(&mut bytes), format)
+        }
+        ImageFormat::WebP => DynamicImage::ImageRgb8(image.to_rgb8()).write_to(&mut Cursor::new(&mut bytes), format),
+        _ => image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png),
+    };
+    result.map_err(|e| format!("编码图片失败: {}", e))?;
+    Ok(bytes)
+}
+
+fn to_data_url(mime_type: &str, bytes: &[u8]) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn png_data_url(width: u32, height: u32, alpha: u8) -> String {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([200, 100, 50, alpha])));
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        // 故意声明错误的类型，验证按文件头识别
+        to_data_url("image/jpeg", &bytes)
+    }
+
+    #[test]
+    fn test_preprocess_image() {
+        let mut options = PreprocessOptions::for_provider(ProviderKind::OpenAI);
+        options.max_edge = Some(64);
+        let accepted = ProviderKind::OpenAI.accepted_image_types();
+
+        // 无需处理时只修正 MIME 类型
+        let small = png_data_url(10, 20, 255);
+        let output = preprocess_image(&small, &options, accepted).unwrap();
+        assert!(output.starts_with("data:image/png;base64,"));
+        assert_eq!(output.split(',').nth(1), small.split(',').nth(1));
+
+        // 超出长边上限时等比缩小，并按指定格式重新编码
+        options.output_format = OutputFormat::Jpeg;
+        let output = preprocess_image(&png_data_url(256, 128, 255), &options, accepted).unwrap();
+        let (mime_type, bytes) = decode_image_data(&output).unwrap();
+        assert_eq!(mime_type, "image/jpeg");
+        let image = image::load_from_memory(&bytes).unwrap();
+        assert_eq!((image.width(), image.height()), (64, 32));
+    }
+
+    #[test]
+    fn test_target_format_and_validate() {
+        let ollama = ProviderKind::Ollama.accepted_image_types();
+        assert_eq!(target_format(OutputFormat::Auto, ImageFormat::WebP, false, ollama), ImageFormat::Jpeg);
+        assert_eq!(target_format(OutputFormat::Auto, ImageFormat::WebP, true, ollama), ImageFormat::Png);
+        assert_eq!(target_format(OutputFormat::Auto, ImageFormat::Png, false, ollama), ImageFormat::Png);
+
+        let mut options = PreprocessOptions::for_provider(ProviderKind::Ollama);
+        assert!(options.validate(ProviderKind::Ollama).is_ok());
+        options.output_format = OutputFormat::Webp;
+        assert!(options.validate(ProviderKind::Ollama).is_err());
+        assert!(options.validate(ProviderKind::OpenAI).is_ok());
+        options.quality = 0;
+        assert!(options.validate(ProviderKind::OpenAI).is_err());
+    }
+}
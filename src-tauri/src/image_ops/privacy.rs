@@ -0,0 +1,461 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use image::ImageFormat;
+
+use crate::ai_service::decode_image_data;
+use super::{decode_oriented, encode_image};
+use super::exif::{
+    ExifReader, TAG_ARTIST, TAG_BODY_SERIAL, TAG_CAMERA_OWNER, TAG_COPYRIGHT, TAG_DATE_TIME,
+    TAG_DATE_TIME_DIGITIZED, TAG_DATE_TIME_ORIGINAL, TAG_GPS_IFD, TAG_LENS_MODEL, TAG_LENS_SERIAL, TAG_MAKE,
+    TAG_MODEL,
+};
+
+/// 可单独保留的元数据类别，未加入白名单的类别在上传前全部移除
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataKind {
+    /// EXIF，包含 GPS 位置、设备型号和序列号、拍摄时间等
+    Exif,
+    /// XMP，可能包含作者、地点和编辑历史
+    Xmp,
+    /// IPTC（JPEG 中的 Photoshop 资源块），包含作者、版权和地点
+    Iptc,
+    /// 色彩配置文件
+    IccProfile,
+    /// JPEG 注释、PNG 文本块和修改时间
+    Comment,
+}
+
+impl MetadataKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetadataKind::Exif => "exif",
+            MetadataKind::Xmp => "xmp",
+            MetadataKind::Iptc => "iptc",
+            MetadataKind::IccProfile => "icc_profile",
+            MetadataKind::Comment => "comment",
+        }
+    }
+}
+
+impl fmt::Display for MetadataKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MetadataKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "exif" => Ok(MetadataKind::Exif),
+            "xmp" => Ok(MetadataKind::Xmp),
+            "iptc" => Ok(MetadataKind::Iptc),
+            "icc_profile" => Ok(MetadataKind::IccProfile),
+            "comment" => Ok(MetadataKind::Comment),
+            other => Err(format!("不支持的元数据类别: {}", other)),
+        }
+    }
+}
+
+/// 被移除的一段元数据
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemovedMetadata {
+    pub kind: MetadataKind,
+    pub byte_size: usize,
+    /// EXIF 中识别到的敏感内容，如 GPS 位置、设备序列号
+    pub details: Vec<&'static str>,
+}
+
+/// 移除 data URL 中不在白名单内的元数据并记录日志。无法按文件结构处理的格式或损坏的文件
+/// 重新编码，白名单不再生效，全部元数据都会丢弃；无法解码时返回错误，不会原样上传
+pub fn strip_image_metadata(image_data: &str, whitelist: &[MetadataKind]) -> Result<String, String> {
+    let (mime_type, bytes) = decode_image_data(image_data)?;
+
+    let Some((output, removed)) = strip_metadata(&bytes, whitelist) else {
+        return reencode_without_metadata(&bytes);
+    };
+    if removed.is_empty() {
+        return Ok(image_data.to_string());
+    }
+
+    let summary = removed
+        .iter()
+        .map(|item| {
+            if item.details.is_empty() {
+                format!("{} ({} 字节)", item.kind, item.byte_size)
+            } else {
+                format!("{} ({} 字节: {})", item.kind, item.byte_size, item.details.join(", "))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    log::info!("隐私过滤: 已移除 {}", summary);
+
+    Ok(format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &output)
+    ))
+}
+
+// 解码后重新编码，输出中不含任何元数据；EXIF 方向在解码时已应用
+fn reencode_without_metadata(bytes: &[u8]) -> Result<String, String> {
+    let (image, format) = decode_oriented(bytes).map_err(|e| format!("无法移除图片元数据: {}", e))?;
+    let output = encode_image(&image, format)?;
+    let mime_type = if format == ImageFormat::Jpeg { "image/jpeg" } else { "image/png" };
+    log::info!("隐私过滤: {} 无法按结构移除元数据，已重新编码为 {}", format.to_mime_type(), mime_type);
+
+    Ok(format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &output)
+    ))
+}
+
+/// 按格式移除元数据，返回处理后的字节和移除记录；不支持的格式或文件结构异常时返回 None
+pub fn strip_metadata(bytes: &[u8], whitelist: &[MetadataKind]) -> Option<(Vec<u8>, Vec<RemovedMetadata>)> {
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        strip_jpeg(bytes, whitelist)
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        strip_png(bytes, whitelist)
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        strip_webp(bytes, whitelist)
+    } else {
+        None
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+fn removed(kind: MetadataKind, data: &[u8]) -> RemovedMetadata {
+    RemovedMetadata {
+        kind,
+        byte_size: data.len(),
+        details: if kind == MetadataKind::Exif { describe_exif(data) } else { Vec::new() },
+    }
+}
+
+// JPEG 按段扫描，图像数据（SOS 之后的熵编码数据）原样复制，遇到 EOI 即结束；
+// EOI 之后的内容（如 MPF 附带的缩略图及其 EXIF）全部丢弃。没有 EOI 的文件视为结构异常
+fn strip_jpeg(bytes: &[u8], whitelist: &[MetadataKind]) -> Option<(Vec<u8>, Vec<RemovedMetadata>)> {
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut removed_items = Vec::new();
+    output.extend_from_slice(&bytes[..2]);
+
+    let mut pos = 2;
+    while pos + 2 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        // 填充字节及无长度的标记
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            output.extend_from_slice(&bytes[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+        if marker == 0xD9 {
+            output.extend_from_slice(&bytes[pos..pos + 2]);
+            return Some((output, removed_items));
+        }
+
+        if pos + 4 > bytes.len() {
+            return None;
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > bytes.len() {
+            return None;
+        }
+        // 渐进式 JPEG 有多个扫描段，扫描数据之后继续按段处理
+        if marker == 0xDA {
+            let scan_end = entropy_data_end(bytes, end)?;
+            output.extend_from_slice(&bytes[pos..scan_end]);
+            pos = scan_end;
+            continue;
+        }
+
+        let data = &bytes[pos + 4..end];
+        let kind = match marker {
+            0xE1 if data.starts_with(EXIF_HEADER) => Some(MetadataKind::Exif),
+            0xE1 if data.starts_with(b"http://ns.adobe.com/") => Some(MetadataKind::Xmp),
+            0xE2 if data.starts_with(b"ICC_PROFILE\0") => Some(MetadataKind::IccProfile),
+            0xED if data.starts_with(b"Photoshop 3.0\0") => Some(MetadataKind::Iptc),
+            0xFE => Some(MetadataKind::Comment),
+            _ => None,
+        };
+
+        match kind {
+            Some(kind) if !whitelist.contains(&kind) => removed_items.push(removed(kind, data)),
+            _ => output.extend_from_slice(&bytes[pos..end]),
+        }
+        pos = end;
+    }
+
+    None
+}
+
+// 熵编码数据结束的位置，即下一个标记的起点；0xFF00 为转义的数据字节，RST 标记属于扫描数据
+fn entropy_data_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start;
+    while pos + 1 < bytes.len() {
+        if bytes[pos] == 0xFF {
+            match bytes[pos + 1] {
+                0x00 | 0xD0..=0xD7 => {
+                    pos += 2;
+                    continue;
+                }
+                0xFF => {
+                    pos += 1;
+                    continue;
+                }
+                _ => return Some(pos),
+            }
+        }
+        pos += 1;
+    }
+    None
+}
+
+fn strip_png(bytes: &[u8], whitelist: &[MetadataKind]) -> Option<(Vec<u8>, Vec<RemovedMetadata>)> {
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut removed_items = Vec::new();
+    output.extend_from_slice(PNG_SIGNATURE);
+
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 12 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().ok()?) as usize;
+        let end = pos.checked_add(12)?.checked_add(length)?;
+        if end > bytes.len() {
+            return None;
+        }
+        let chunk_type = &bytes[pos + 4..pos + 8];
+        let data = &bytes[pos + 8..pos + 8 + length];
+        let kind = match chunk_type {
+            b"eXIf" => Some(MetadataKind::Exif),
+            b"iCCP" => Some(MetadataKind::IccProfile),
+            b"iTXt" if data.starts_with(b"XML:com.adobe.xmp\0") => Some(MetadataKind::Xmp),
+            b"tEXt" | b"zTXt" | b"iTXt" | b"tIME" => Some(MetadataKind::Comment),
+            _ => None,
+        };
+
+        match kind {
+            Some(kind) if !whitelist.contains(&kind) => removed_items.push(removed(kind, data)),
+            _ => output.extend_from_slice(&bytes[pos..end]),
+        }
+        // IEND 之后附加的数据不属于图片，一并丢弃
+        if chunk_type == b"IEND" {
+            return Some((output, removed_items));
+        }
+        pos = end;
+    }
+
+    None
+}
+
+// WebP 移除块后需要同步更新 VP8X 中的标志位和 RIFF 长度
+fn strip_webp(bytes: &[u8], whitelist: &[MetadataKind]) -> Option<(Vec<u8>, Vec<RemovedMetadata>)> {
+    const ICC_FLAG: u8 = 0x20;
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut removed_items = Vec::new();
+    output.extend_from_slice(&bytes[..12]);
+
+    let mut vp8x_flags_pos = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let fourcc = &bytes[pos..pos + 4];
+        let length = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().ok()?) as usize;
+        let end = pos.checked_add(8)?.checked_add(length + length % 2)?.min(bytes.len());
+        if pos + 8 + length > bytes.len() {
+            return None;
+        }
+        let data = &bytes[pos + 8..pos + 8 + length];
+        let kind = match fourcc {
+            b"EXIF" => Some(MetadataKind::Exif),
+            b"XMP " => Some(MetadataKind::Xmp),
+            b"ICCP" => Some(MetadataKind::IccProfile),
+            _ => None,
+        };
+
+        match kind {
+            Some(kind) if !whitelist.contains(&kind) => removed_items.push(removed(kind, data)),
+            _ => {
+                if fourcc == b"VP8X" && length > 0 {
+                    vp8x_flags_pos = Some(output.len() + 8);
+                }
+                output.extend_from_slice(&bytes[pos..end]);
+            }
+        }
+        pos = end;
+    }
+
+    if let Some(flags_pos) = vp8x_flags_pos {
+        for item in &removed_items {
+            output[flags_pos] &= !match item.kind {
+                MetadataKind::IccProfile => ICC_FLAG,
+                MetadataKind::Exif => EXIF_FLAG,
+                MetadataKind::Xmp => XMP_FLAG,
+                _ => 0,
+            };
+        }
+    }
+    let riff_size = (output.len() - 8) as u32;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some((output, removed_items))
+}
+
+// 根据 EXIF 中出现的标签说明移除了哪些敏感信息，只用于日志
+fn describe_exif(data: &[u8]) -> Vec<&'static str> {
+    let Some(exif) = ExifReader::parse(data) else {
+        return Vec::new();
+    };
+    let has = |tags: &[u16]| tags.iter().any(|tag| exif.has_tag(*tag));
+
+    let mut details = Vec::new();
+    if has(&[TAG_GPS_IFD]) {
+        details.push("GPS 位置");
+    }
+    if has(&[TAG_MAKE, TAG_MODEL, TAG_LENS_MODEL]) {
+        details.push("设备型号");
+    }
+    if has(&[TAG_BODY_SERIAL, TAG_LENS_SERIAL]) {
+        details.push("设备序列号");
+    }
+    if has(&[TAG_ARTIST, TAG_COPYRIGHT, TAG_CAMERA_OWNER]) {
+        details.push("作者和版权");
+    }
+    if has(&[TAG_DATE_TIME, TAG_DATE_TIME_ORIGINAL, TAG_DATE_TIME_DIGITIZED]) {
+        details.push("拍摄时间");
+    }
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&((data.len() + 2) as u16).to_be_bytes());
+        segment.extend_from_slice(data);
+        segment
+    }
+
+    // 小端 TIFF，IFD0 中只有 Make 和 GPSInfo 两个标签
+    fn exif_block() -> Vec<u8> {
+        let mut data = EXIF_HEADER.to_vec();
+        data.extend_from_slice(b"II*\0");
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        for tag in [0x010Fu16, 0x8825] {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&[0; 10]);
+        }
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_strip_jpeg_metadata() {
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(4, 4))
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+
+        let mut input = jpeg[..2].to_vec();
+        input.extend(segment(0xE1, &exif_block()));
+        input.extend(segment(0xE2, b"ICC_PROFILE\0\x01\x01data"));
+        input.extend(segment(0xFE, b"shot by someone"));
+        input.extend_from_slice(&jpeg[2..]);
+
+        let (output, removed) = strip_metadata(&input, &[MetadataKind::IccProfile]).unwrap();
+        let kinds: Vec<_> = removed.iter().map(|item| item.kind).collect();
+        assert_eq!(kinds, vec![MetadataKind::Exif, MetadataKind::Comment]);
+        assert_eq!(removed[0].details, vec!["GPS 位置", "设备型号"]);
+        assert_eq!(output.len(), jpeg.len() + segment(0xE2, b"ICC_PROFILE\0\x01\x01data").len());
+        assert!(image::load_from_memory(&output).is_ok());
+
+        // EOI 之后附带的 MPF 缩略图及其 EXIF 一并丢弃
+        let mut with_trailer = input.clone();
+        with_trailer.extend_from_slice(&jpeg[..2]);
+        with_trailer.extend(segment(0xE1, &exif_block()));
+        with_trailer.extend_from_slice(&jpeg[2..]);
+        let (trimmed, _) = strip_metadata(&with_trailer, &[MetadataKind::IccProfile]).unwrap();
+        assert_eq!(trimmed, output);
+
+        // 没有可移除的内容时保持不变
+        let (output, removed) = strip_metadata(&jpeg, &[]).unwrap();
+        assert!(removed.is_empty());
+        assert_eq!(output, jpeg);
+    }
+
+    #[test]
+    fn test_strip_png_metadata() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(2, 2))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        // 在 IHDR（签名 8 字节 + 块 25 字节）之后插入文本块，CRC 不参与过滤
+        let mut text_chunk = 8u32.to_be_bytes().to_vec();
+        text_chunk.extend_from_slice(b"tEXtAuthor\0A");
+        text_chunk.extend_from_slice(&[0; 4]);
+        let mut input = png[..33].to_vec();
+        input.extend_from_slice(&text_chunk);
+        input.extend_from_slice(&png[33..]);
+
+        let (output, removed) = strip_metadata(&input, &[]).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].kind, MetadataKind::Comment);
+        assert_eq!(output, png);
+        assert_eq!("icc_profile".parse::<MetadataKind>().unwrap(), MetadataKind::IccProfile);
+    }
+
+    #[test]
+    fn test_unsupported_format_fails_closed() {
+        let data_url = |mime_type: &str, bytes: &[u8]| {
+            format!("data:{};base64,{}", mime_type, base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes))
+        };
+
+        // GIF 无法按结构过滤，重新编码为不含元数据的 PNG
+        let mut gif = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(3, 2))
+            .write_to(&mut Cursor::new(&mut gif), ImageFormat::Gif)
+            .unwrap();
+        let output = strip_image_metadata(&data_url("image/gif", &gif), &[]).unwrap();
+        let (mime_type, bytes) = decode_image_data(&output).unwrap();
+        assert_eq!(mime_type, "image/png");
+        assert_eq!(image::guess_format(&bytes).unwrap(), ImageFormat::Png);
+
+        // 无法解码的 TIFF（含 EXIF）不能原样上传
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend_from_slice(&exif_block()[EXIF_HEADER.len() + 4..]);
+        assert!(strip_image_metadata(&data_url("image/tiff", &tiff), &[]).is_err());
+
+        // 截断的 JPEG 同样不会原样返回
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(4, 4))
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+        let mut truncated = jpeg[..2].to_vec();
+        truncated.extend(segment(0xE1, &exif_block()));
+        truncated.extend_from_slice(&jpeg[2..jpeg.len() / 2]);
+        assert!(strip_metadata(&truncated, &[]).is_none());
+        let result = strip_image_metadata(&data_url("image/jpeg", &truncated), &[]);
+        assert!(result.map_or(true, |output| output != data_url("image/jpeg", &truncated)));
+    }
+}
@@ -0,0 +1,129 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// 缩略图 JPEG 编码质量
+const THUMBNAIL_QUALITY: u8 = 80;
+
+/// 缩略图规格，数值为长边像素
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailSize {
+    Small,
+    Medium,
+    Large,
+}
+
+/// 全部缩略图规格
+pub const THUMBNAIL_SIZES: [ThumbnailSize; 3] = [ThumbnailSize::Small, ThumbnailSize::Medium, ThumbnailSize::Large];
+
+impl ThumbnailSize {
+    pub fn edge(&self) -> u32 {
+        match self {
+            ThumbnailSize::Small => 128,
+            ThumbnailSize::Medium => 256,
+            ThumbnailSize::Large => 512,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThumbnailSize::Small => "small",
+            ThumbnailSize::Medium => "medium",
+            ThumbnailSize::Large => "large",
+        }
+    }
+
+    pub fn parse(size: &str) -> Option<Self> {
+        THUMBNAIL_SIZES.into_iter().find(|candidate| candidate.as_str() == size)
+    }
+}
+
+/// 按 EXIF 方向摆正后生成全部规格的缩略图，带透明度的图片编码为 PNG，其余为 JPEG
+pub fn generate_thumbnails(bytes: &[u8]) -> Result<Vec<(ThumbnailSize, Vec<u8>)>, String> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("读取图片失败: {}", e))?
+        .into_decoder()
+        .map_err(|e| format!("解码图片失败: {}", e))?;
+    let orientation = decoder.orientation().ok();
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| format!("解码图片失败: {}", e))?;
+    if let Some(orientation) = orientation {
+        image.apply_orientation(orientation);
+    }
+
+    // 从大到小依次缩放，每一级以上一级为输入以减少计算量；目标尺寸按原图比例计算，避免逐级累积取整误差
+    let (width, height) = (image.width(), image.height());
+    let mut thumbnails = Vec::with_capacity(THUMBNAIL_SIZES.len());
+    for size in THUMBNAIL_SIZES.into_iter().rev() {
+        let (target_width, target_height) = fit_within(width, height, size.edge());
+        if (target_width, target_height) != (image.width(), image.height()) {
+            image = image.resize_exact(target_width, target_height, FilterType::Triangle);
+        }
+        thumbnails.push((size, encode_thumbnail(&image)?));
+    }
+    thumbnails.reverse();
+    Ok(thumbnails)
+}
+
+// 按比例缩放到长边不超过 edge，不放大
+fn fit_within(width: u32, height: u32, edge: u32) -> (u32, u32) {
+    let long_edge = width.max(height);
+    if long_edge <= edge {
+        return (width, height);
+    }
+    let scale = |value: u32| ((value as f64 * edge as f64 / long_edge as f64).round() as u32).max(1);
+    (scale(width), scale(height))
+}
+
+fn encode_thumbnail(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let result = if image.color().has_alpha() {
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+    } else {
+        image.to_rgb8().write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, THUMBNAIL_QUALITY))
+    };
+    result.map_err(|e| format!("编码缩略图失败: {}", e))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage, RgbImage};
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_generate_thumbnails() {
+        let jpeg = encode(DynamicImage::ImageRgb8(RgbImage::new(1000, 400)), ImageFormat::Jpeg);
+        let thumbnails = generate_thumbnails(&jpeg).unwrap();
+        let sizes: Vec<_> = thumbnails
+            .iter()
+            .map(|(size, bytes)| {
+                assert_eq!(image::guess_format(bytes).unwrap(), ImageFormat::Jpeg);
+                let image = image::load_from_memory(bytes).unwrap();
+                (*size, image.width(), image.height())
+            })
+            .collect();
+        assert_eq!(sizes, vec![
+            (ThumbnailSize::Small, 128, 51),
+            (ThumbnailSize::Medium, 256, 102),
+            (ThumbnailSize::Large, 512, 205),
+        ]);
+
+        // 小图不放大，透明图编码为 PNG
+        let png = encode(DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 50, Rgba([0, 0, 0, 0]))), ImageFormat::Png);
+        let thumbnails = generate_thumbnails(&png).unwrap();
+        let image = image::load_from_memory(&thumbnails[2].1).unwrap();
+        assert_eq!((image.width(), image.height()), (100, 50));
+        assert_eq!(image::guess_format(&thumbnails[0].1).unwrap(), ImageFormat::Png);
+        assert!(generate_thumbnails(b"broken").is_err());
+    }
+}
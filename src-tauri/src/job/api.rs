@@ -0,0 +1,57 @@
+use tauri::{AppHandle, State};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use crate::database::{Database, Job};
+use crate::error::AppError;
+use super::service::JobService;
+
+/// 任务进度事件，任务状态或执行阶段变化时发送
+pub const JOB_PROGRESS_EVENT: &str = "job-progress";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub job_id: String,
+    pub gallery_id: String,
+    pub kind: String,
+    pub status: String,
+    /// 执行阶段：started/generating/saving/retrying/finished
+    pub stage: String,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListJobsRequest {
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+type DatabaseState = Mutex<Database>;
+
+/// 获取任务列表接口
+#[tauri::command]
+pub fn list_jobs(
+    db: State<'_, DatabaseState>,
+    request: ListJobsRequest,
+) -> Result<Vec<Job>, AppError> {
+    let service = JobService::new();
+    service.list_jobs(db, request)
+}
+
+/// 获取任务详情接口
+#[tauri::command]
+pub fn get_job(db: State<'_, DatabaseState>, id: String) -> Result<Option<Job>, AppError> {
+    let service = JobService::new();
+    service.get_job(db, &id)
+}
+
+/// 取消任务接口，id 可以是任务 ID、请求 ID 或图库 ID；按请求 ID 登记的对话请求（process_image）同样可以取消
+#[tauri::command]
+pub fn cancel_job(app: AppHandle, id: String) -> Result<bool, AppError> {
+    let service = JobService::new();
+    service.cancel_job(&app, &id)
+}
@@ -0,0 +1,266 @@
+use tauri::{AppHandle, Emitter, Manager};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use chrono::Utc;
+
+use crate::ai_service::{AIError, CancelRegistry, RetryConfig, TokenUsage};
+use crate::batch::service::emit_batch_progress;
+use crate::database::{
+    Database, Job, GALLERY_STATUS_PENDING, GALLERY_STATUS_PROCESSING, JOB_KIND_IMAGE_EDIT,
+    JOB_STATUS_CANCELLED, JOB_STATUS_FAILED, JOB_STATUS_PENDING, JOB_STATUS_RUNNING,
+    JOB_STATUS_SUCCEEDED,
+};
+use crate::error::AppError;
+use crate::gallery::service::{abort_edit_job, GalleryService};
+
+use super::api::{JobProgress, JOB_PROGRESS_EVENT};
+
+/// 任务执行阶段
+pub const JOB_STAGE_STARTED: &str = "started";
+pub const JOB_STAGE_GENERATING: &str = "generating";
+pub const JOB_STAGE_SAVING: &str = "saving";
+pub const JOB_STAGE_RETRYING: &str = "retrying";
+pub const JOB_STAGE_FINISHED: &str = "finished";
+
+/// 没有到期任务时工作线程的最长等待时间
+const IDLE_POLL_MS: i64 = 5000;
+
+type DatabaseState = Mutex<Database>;
+
+/// 任务执行成功的结果
+#[derive(Debug, Default)]
+pub struct JobOutput {
+    /// 写入 job.result 的 JSON
+    pub result: Option<String>,
+    pub usage: TokenUsage,
+}
+
+/// 任务执行失败的原因，决定任务重新排队还是直接结束
+#[derive(Debug)]
+pub enum JobError {
+    Cancelled,
+    /// retry_after_ms 为服务端要求的最短等待时间
+    Retryable { error: String, retry_after_ms: Option<u64> },
+    Fatal(String),
+}
+
+// 限流、网络等错误可以重试，数据库等内部错误重试也无法恢复
+impl From<AppError> for JobError {
+    fn from(error: AppError) -> Self {
+        match error {
+            AppError::Cancelled => JobError::Cancelled,
+            error if error.is_retryable() => JobError::Retryable {
+                retry_after_ms: error.retry_after_ms(),
+                error: error.to_string(),
+            },
+            error => JobError::Fatal(error.to_string()),
+        }
+    }
+}
+
+impl From<AIError> for JobError {
+    fn from(error: AIError) -> Self {
+        if error.error_type == "cancelled" {
+            JobError::Cancelled
+        } else if error.is_retryable() {
+            JobError::Retryable { error: error.message, retry_after_ms: error.retry_after_ms }
+        } else {
+            JobError::Fatal(error.message)
+        }
+    }
+}
+
+/// 基于 SQLite 的后台任务队列，任务状态持久化在 job 表中
+pub struct JobQueue {
+    notify: Arc<Notify>,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        Self {
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    /// 唤醒一个空闲的工作线程领取新任务
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    /// 恢复上次退出时未完成的任务并启动工作线程，线程数取自设置
+    pub fn start(&self, app: &AppHandle) -> Result<(), String> {
+        let workers = {
+            let db = app.state::<DatabaseState>();
+            let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+
+            let resumed = db.transaction(|db| {
+                let resumed = db.job().reset_running(Utc::now().timestamp_millis())
+                    .map_err(|e| format!("Failed to resume jobs: {}", e))?;
+                db.gallery().reset_processing()
+                    .map_err(|e| format!("Failed to resume galleries: {}", e))?;
+                Ok::<_, String>(resumed)
+            })?;
+            if resumed > 0 {
+                log::info!("恢复 {} 个未完成的任务", resumed);
+            }
+
+            db.setting().get_or_create_default()
+                .map_err(|e| format!("Failed to get settings: {}", e))?
+                .job_workers
+                .max(1)
+        };
+
+        for _ in 0..workers {
+            tauri::async_runtime::spawn(worker_loop(app.clone(), self.notify.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+/// 发送任务进度事件
+pub fn emit_progress(app: &AppHandle, job: &Job, status: &str, stage: &str, error: Option<String>) {
+    let _ = app.emit(JOB_PROGRESS_EVENT, JobProgress {
+        job_id: job.id.clone(),
+        gallery_id: job.gallery_id.clone(),
+        kind: job.kind.clone(),
+        status: status.to_string(),
+        stage: stage.to_string(),
+        attempt: job.attempts,
+        max_attempts: job.max_attempts,
+        error,
+    });
+}
+
+// 循环领取到期任务；队列为空时等待新任务通知或最近一个重试任务到期
+async fn worker_loop(app: AppHandle, notify: Arc<Notify>) {
+    loop {
+        let claimed = {
+            let db = app.state::<DatabaseState>();
+            let now = Utc::now().timestamp_millis();
+            db.lock()
+                .map_err(|e| format!("Database lock error: {}", e))
+                .and_then(|db| {
+                    let job = db.job().claim_next(now)
+                        .map_err(|e| format!("Failed to claim job: {}", e))?;
+                    let next_run_after = db.job().next_run_after()
+                        .map_err(|e| format!("Failed to get jobs: {}", e))?;
+                    Ok((job, next_run_after))
+                })
+        };
+
+        match claimed {
+            Ok((Some(job), _)) => run_job(&app, job).await,
+            Ok((None, next_run_after)) => {
+                let wait_ms = next_run_after
+                    .map(|run_after| run_after - Utc::now().timestamp_millis())
+                    .unwrap_or(IDLE_POLL_MS)
+                    .clamp(50, IDLE_POLL_MS);
+                tokio::select! {
+                    _ = notify.notified() => {}
+                    _ = tokio::time::sleep(Duration::from_millis(wait_ms as u64)) => {}
+                }
+            }
+            Err(e) => {
+                log::error!("任务队列出错: {}", e);
+                tokio::time::sleep(Duration::from_millis(IDLE_POLL_MS as u64)).await;
+            }
+        }
+    }
+}
+
+// 执行一个已领取的任务，根据结果结束任务或按 RetryConfig 退避后重新排队
+async fn run_job(app: &AppHandle, job: Job) {
+    // 重试次数在入队时按设置写入 max_attempts，这里只取退避间隔
+    let retry_config = RetryConfig::default();
+
+    let result = if job.attempts > job.max_attempts {
+        // 多次在执行中退出的任务不再继续尝试
+        Err(JobError::Fatal(job.error.clone().unwrap_or_else(|| "重试次数已用尽".to_string())))
+    } else {
+        emit_progress(app, &job, JOB_STATUS_RUNNING, JOB_STAGE_STARTED, None);
+
+        let mut cancel_keys = vec![job.id.clone(), job.gallery_id.clone()];
+        cancel_keys.extend(job.request_id.clone());
+        let cancel_registry = app.state::<CancelRegistry>();
+        let cancel_guard = cancel_registry.register(cancel_keys);
+
+        // 任务级重试会持久化并跨越重启，单次执行内不再重试请求
+        let attempt_retry_config = RetryConfig {
+            max_retries: 0,
+            ..retry_config.clone()
+        };
+        let result = match job.kind.as_str() {
+            JOB_KIND_IMAGE_EDIT => {
+                GalleryService::new()
+                    .run_edit_job(app, &job, cancel_guard.token(), attempt_retry_config)
+                    .await
+            }
+            kind => Err(JobError::Fatal(format!("未知的任务类型: {}", kind))),
+        };
+
+        if cancel_guard.is_cancelled() {
+            Err(JobError::Cancelled)
+        } else {
+            result
+        }
+    };
+
+    if let Err(e) = finish_job(app, &job, result, &retry_config) {
+        log::error!("更新任务 {} 状态失败: {}", job.id, e);
+    }
+}
+
+fn finish_job(
+    app: &AppHandle,
+    job: &Job,
+    result: Result<JobOutput, JobError>,
+    retry_config: &RetryConfig,
+) -> Result<(), String> {
+    let db = app.state::<DatabaseState>();
+    let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+    let now = Utc::now().timestamp_millis();
+
+    // 任务和图库记录的状态在同一事务中更新，事件在提交后发送
+    let (status, stage, error) = db.transaction(|db| {
+        let (status, error) = match result {
+            Ok(output) => {
+                db.job().add_tokens(&job.id, output.usage.input_tokens as i64, output.usage.output_tokens as i64)
+                    .map_err(|e| format!("Failed to update job: {}", e))?;
+                db.job().finish(&job.id, JOB_STATUS_SUCCEEDED, output.result.as_deref(), None, now)
+                    .map_err(|e| format!("Failed to update job: {}", e))?;
+                return Ok((JOB_STATUS_SUCCEEDED, JOB_STAGE_FINISHED, None));
+            }
+            Err(JobError::Retryable { error, retry_after_ms }) if job.attempts < job.max_attempts => {
+                let delay_ms = retry_config.retry_delay_ms(job.attempts.saturating_sub(1), retry_after_ms);
+                let run_after = now + delay_ms as i64;
+                db.job().schedule_retry(&job.id, &error, run_after, now)
+                    .map_err(|e| format!("Failed to update job: {}", e))?;
+                if job.kind == JOB_KIND_IMAGE_EDIT {
+                    db.gallery().transition_status(&job.gallery_id, GALLERY_STATUS_PROCESSING, GALLERY_STATUS_PENDING)
+                        .map_err(|e| format!("Failed to update gallery: {}", e))?;
+                }
+                return Ok((JOB_STATUS_PENDING, JOB_STAGE_RETRYING, Some(error)));
+            }
+            Err(JobError::Cancelled) => (JOB_STATUS_CANCELLED, None),
+            Err(JobError::Retryable { error, .. }) | Err(JobError::Fatal(error)) => (JOB_STATUS_FAILED, Some(error)),
+        };
+
+        db.job().finish(&job.id, status, None, error.as_deref(), now)
+            .map_err(|e| format!("Failed to update job: {}", e))?;
+        if job.kind == JOB_KIND_IMAGE_EDIT {
+            abort_edit_job(db, job, error.as_deref())?;
+        }
+        Ok::<_, String>((status, JOB_STAGE_FINISHED, error))
+    })?;
+
+    emit_progress(app, job, status, stage, error);
+    if status != JOB_STATUS_PENDING {
+        if let Some(batch_id) = &job.batch_id {
+            emit_batch_progress(app, &db, batch_id);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use tauri::{AppHandle, Manager, State};
+use std::sync::Mutex;
+use chrono::Utc;
+
+use crate::ai_service::CancelRegistry;
+use crate::database::{
+    Database, Job, JOB_KIND_IMAGE_EDIT, JOB_STATUS_CANCELLED, JOB_STATUS_PENDING,
+};
+use crate::error::AppError;
+use crate::gallery::service::abort_edit_job;
+
+use super::api::ListJobsRequest;
+use super::queue::{emit_progress, JOB_STAGE_FINISHED};
+
+/// 列表默认条数和上限
+const DEFAULT_JOB_LIMIT: u32 = 50;
+const MAX_JOB_LIMIT: u32 = 200;
+
+type DatabaseState = Mutex<Database>;
+
+pub struct JobService;
+
+impl JobService {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// 按创建时间倒序列出任务
+    pub fn list_jobs(
+        &self,
+        db: State<'_, DatabaseState>,
+        request: ListJobsRequest,
+    ) -> Result<Vec<Job>, AppError> {
+        let limit = request.limit.unwrap_or(DEFAULT_JOB_LIMIT).clamp(1, MAX_JOB_LIMIT);
+        let db = db.lock()?;
+        db.job().list(request.status.as_deref(), limit)
+            .map_err(|e| AppError::database("Failed to get jobs", e))
+    }
+
+    pub fn get_job(&self, db: State<'_, DatabaseState>, id: &str) -> Result<Option<Job>, AppError> {
+        let db = db.lock()?;
+        db.job().get_by_id(id)
+            .map_err(|e| AppError::database("Failed to get job", e))
+    }
+
+    /// 取消任务：执行中的任务通过取消令牌中断，由工作线程收尾；排队中的任务直接标记为已取消。
+    /// 同一个 key 可能同时对应执行中和排队中的任务（如批量编辑），两类都需要处理
+    pub fn cancel_job(&self, app: &AppHandle, key: &str) -> Result<bool, AppError> {
+        let registry_hit = app.state::<CancelRegistry>().cancel(key);
+
+        let db = app.state::<DatabaseState>();
+        let db = db.lock()?;
+        let jobs = db.job().find_unfinished(key)
+            .map_err(|e| AppError::database("Failed to get jobs", e))?;
+
+        let mut cancelled = false;
+        for mut job in jobs.into_iter().filter(|job| job.status == JOB_STATUS_PENDING) {
+            let updated = db.transaction(|db| {
+                let updated = db.job()
+                    .finish(&job.id, JOB_STATUS_CANCELLED, None, None, Utc::now().timestamp_millis())
+                    .map_err(|e| AppError::database("Failed to update job", e))?;
+                if updated && job.kind == JOB_KIND_IMAGE_EDIT {
+                    abort_edit_job(db, &job, None)?;
+                }
+                Ok::<_, AppError>(updated)
+            })?;
+            if !updated {
+                continue;
+            }
+            job.status = JOB_STATUS_CANCELLED.to_string();
+            emit_progress(app, &job, JOB_STATUS_CANCELLED, JOB_STAGE_FINISHED, None);
+            cancelled = true;
+        }
+
+        Ok(registry_hit || cancelled)
+    }
+}
@@ -4,12 +4,30 @@ mod gallery;
 mod style;
 mod setting;
 mod ai;
+mod job;
+mod batch;
+mod image_ops;
+mod error;
 
 use database::Database;
-use gallery::api::{edit_image, get_all_images, batch_delete_images, generate_style_from_message};
+use gallery::api::{
+    edit_image, get_all_images, batch_delete_images, generate_style_from_message, get_image_data,
+    list_images, get_gallery_messages, delete_message, edit_message, regenerate_from_message,
+    list_versions, checkout_version, undo_version, redo_version, branch_from_version, diff_versions,
+    promote_candidate, apply_local_ops, regenerate_thumbnails, retry_edit,
+};
 use style::api::{get_all_styles, add_style, delete_style};
-use setting::api::{save_setting, get_setting, get_daily_token_usage, get_monthly_token_usage, get_yearly_token_usage};
+use setting::api::{
+    save_setting, get_setting, get_daily_token_usage, get_monthly_token_usage, get_yearly_token_usage,
+    get_token_usage_in_range, get_token_usage_series, get_preprocess_settings, save_preprocess_setting, reset_preprocess_setting,
+};
 use ai::api::{process_image, generate_style};
+use job::api::{list_jobs, get_job, cancel_job};
+use batch::api::{batch_edit, get_batch_summary, cancel_batch};
+use job::queue::JobQueue;
+use gallery::thumbnail::ThumbnailQueue;
+use ai_service::CancelRegistry;
+use gallery::protocol::GALLERY_PROTOCOL;
 use std::sync::Mutex;
 use tauri::Manager;
 
@@ -17,6 +35,15 @@ use tauri::Manager;
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        // 日志同时输出到终端和应用日志目录
+        .plugin(tauri_plugin_log::Builder::new().level(log::LevelFilter::Info).build())
+        // 图库图片通过自定义协议按需读取，避免经 IPC 传输 base64
+        .register_asynchronous_uri_scheme_protocol(GALLERY_PROTOCOL, |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                responder.respond(gallery::protocol::handle(&app, &request));
+            });
+        })
         .setup(|app| {
             // 获取应用数据目录
             let app_data_dir = app
@@ -30,20 +57,50 @@ pub fn run() {
             // 创建数据库文件路径
             let db_path = app_data_dir.join("app.db");
 
-            // 初始化数据库
-            let database = Database::new(db_path).expect("Failed to initialize database");
+            // 初始化数据库，图片文件单独存放在 blobs 目录
+            let database = Database::new(db_path, app_data_dir.join("blobs"))
+                .expect("Failed to initialize database");
 
             // 将数据库添加到应用状态
             app.manage(Mutex::new(database));
 
+            // 进行中的AI请求登记表，用于取消请求
+            app.manage(CancelRegistry::new());
+
+            // 启动后台任务队列，继续上次退出时未完成的编辑任务
+            app.manage(JobQueue::new());
+            app.state::<JobQueue>()
+                .start(app.handle())
+                .expect("Failed to start job queue");
+
+            // 启动缩略图后台生成，补齐已有记录缺少的缩略图
+            app.manage(ThumbnailQueue::new());
+            app.state::<ThumbnailQueue>().start(app.handle());
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             // Gallery module endpoints
             edit_image,
             get_all_images,
+            list_images,
             batch_delete_images,
             generate_style_from_message,
+            get_image_data,
+            get_gallery_messages,
+            delete_message,
+            edit_message,
+            regenerate_from_message,
+            list_versions,
+            checkout_version,
+            undo_version,
+            redo_version,
+            branch_from_version,
+            diff_versions,
+            promote_candidate,
+            apply_local_ops,
+            regenerate_thumbnails,
+            retry_edit,
 
             // Style module endpoints
             get_all_styles,
@@ -56,10 +113,25 @@ pub fn run() {
             get_daily_token_usage,
             get_monthly_token_usage,
             get_yearly_token_usage,
+            get_token_usage_in_range,
+            get_token_usage_series,
+            get_preprocess_settings,
+            save_preprocess_setting,
+            reset_preprocess_setting,
 
             // AI module endpoints
             process_image,
-            generate_style
+            generate_style,
+
+            // Job module endpoints
+            list_jobs,
+            get_job,
+            cancel_job,
+
+            // Batch module endpoints
+            batch_edit,
+            get_batch_summary,
+            cancel_batch
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
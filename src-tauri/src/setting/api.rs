@@ -2,14 +2,33 @@ use tauri::State;
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 
-use crate::database::Database;
+use chrono::NaiveDate;
+
+use crate::database::{Database, UsageGroupBy};
+use crate::error::AppError;
+use crate::image_ops::preprocess::PreprocessOptions;
+use crate::image_ops::privacy::MetadataKind;
 use super::service::SettingService;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SaveSettingRequest {
+    #[serde(default)]
+    pub provider: Option<String>,
     pub api_url: String,
     pub api_key: String,
     pub model: String,
+    #[serde(default)]
+    pub image_model: Option<String>,
+    #[serde(default)]
+    pub job_workers: Option<u32>,
+    #[serde(default)]
+    pub job_max_retries: Option<u32>,
+    /// 上传前保留的元数据类别，为空数组时全部移除
+    #[serde(default)]
+    pub metadata_whitelist: Option<Vec<MetadataKind>>,
+    /// 用量统计使用的 IANA 时区名，如 Asia/Shanghai，空字符串表示使用系统时区
+    #[serde(default)]
+    pub timezone: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,8 +39,16 @@ pub struct SaveSettingResponse {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetSettingResponse {
+    pub provider: String,
     pub api_url: String,
     pub model: String,
+    pub image_model: String,
+    pub job_workers: u32,
+    pub job_max_retries: u32,
+    pub metadata_whitelist: Vec<MetadataKind>,
+    /// 设置的时区，为空时使用系统时区
+    pub timezone: String,
+    pub system_timezone: String,
     pub has_api_key: bool,
 }
 
@@ -32,6 +59,62 @@ pub struct TokenUsageResponse {
     pub yearly: i64,
 }
 
+/// 用量查询的日期范围，日期为设置时区内的闭区间
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenUsageRangeRequest {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+/// 用量序列查询条件，日期为设置时区内的闭区间，默认最近 30 天
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TokenUsageSeriesRequest {
+    #[serde(default)]
+    pub start_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub end_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub group_by: UsageGroupBy,
+}
+
+/// 一个分组的按天用量，各数组与 dates 一一对应
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenUsageSeries {
+    /// 分组值，不分组时为空字符串
+    pub key: String,
+    pub calls: Vec<i64>,
+    pub input_tokens: Vec<i64>,
+    pub output_tokens: Vec<i64>,
+    /// 当天平均耗时（毫秒）
+    pub latency_ms: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenUsageSeriesResponse {
+    /// 范围内的全部日期，格式为 YYYY-MM-DD
+    pub dates: Vec<String>,
+    pub series: Vec<TokenUsageSeries>,
+    /// 划分日期所用的时区
+    pub timezone: String,
+}
+
+/// 某个供应商的图片预处理配置
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreprocessSettingResponse {
+    pub provider: String,
+    #[serde(flatten)]
+    pub options: PreprocessOptions,
+    /// 是否保存过自定义配置，否则为供应商默认配置
+    pub customized: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavePreprocessSettingRequest {
+    pub provider: String,
+    #[serde(flatten)]
+    pub options: PreprocessOptions,
+}
+
 type DatabaseState = Mutex<Database>;
 
 /// 保存设置接口
@@ -39,35 +122,84 @@ type DatabaseState = Mutex<Database>;
 pub fn save_setting(
     db: State<'_, DatabaseState>,
     request: SaveSettingRequest,
-) -> Result<SaveSettingResponse, String> {
+) -> Result<SaveSettingResponse, AppError> {
     let service = SettingService::new();
     service.save_setting(db, request)
 }
 
 /// 获取设置接口
 #[tauri::command]
-pub fn get_setting(db: State<'_, DatabaseState>) -> Result<GetSettingResponse, String> {
+pub fn get_setting(db: State<'_, DatabaseState>) -> Result<GetSettingResponse, AppError> {
     let service = SettingService::new();
     service.get_setting(db)
 }
 
 /// 获取token使用量（日度）接口
 #[tauri::command]
-pub fn get_daily_token_usage(db: State<'_, DatabaseState>) -> Result<i64, String> {
+pub fn get_daily_token_usage(db: State<'_, DatabaseState>) -> Result<i64, AppError> {
     let service = SettingService::new();
     service.get_daily_token_usage(db)
 }
 
 /// 获取token使用量（月度）接口
 #[tauri::command]
-pub fn get_monthly_token_usage(db: State<'_, DatabaseState>) -> Result<i64, String> {
+pub fn get_monthly_token_usage(db: State<'_, DatabaseState>) -> Result<i64, AppError> {
     let service = SettingService::new();
     service.get_monthly_token_usage(db)
 }
 
 /// 获取token使用量（年度）接口
 #[tauri::command]
-pub fn get_yearly_token_usage(db: State<'_, DatabaseState>) -> Result<i64, String> {
+pub fn get_yearly_token_usage(db: State<'_, DatabaseState>) -> Result<i64, AppError> {
     let service = SettingService::new();
     service.get_yearly_token_usage(db)
-}
\ No newline at end of file
+}
+
+/// 获取任意日期范围的token使用量接口
+#[tauri::command]
+pub fn get_token_usage_in_range(
+    db: State<'_, DatabaseState>,
+    request: TokenUsageRangeRequest,
+) -> Result<i64, AppError> {
+    let service = SettingService::new();
+    service.get_token_usage_in_range(db, request)
+}
+
+/// 获取按天汇总的token使用量序列接口
+#[tauri::command]
+pub fn get_token_usage_series(
+    db: State<'_, DatabaseState>,
+    request: Option<TokenUsageSeriesRequest>,
+) -> Result<TokenUsageSeriesResponse, AppError> {
+    let service = SettingService::new();
+    service.get_token_usage_series(db, request.unwrap_or_default())
+}
+
+/// 获取全部供应商的图片预处理配置接口
+#[tauri::command]
+pub fn get_preprocess_settings(
+    db: State<'_, DatabaseState>,
+) -> Result<Vec<PreprocessSettingResponse>, AppError> {
+    let service = SettingService::new();
+    service.get_preprocess_settings(db)
+}
+
+/// 保存供应商的图片预处理配置接口
+#[tauri::command]
+pub fn save_preprocess_setting(
+    db: State<'_, DatabaseState>,
+    request: SavePreprocessSettingRequest,
+) -> Result<PreprocessSettingResponse, AppError> {
+    let service = SettingService::new();
+    service.save_preprocess_setting(db, request)
+}
+
+/// 恢复供应商默认的图片预处理配置接口
+#[tauri::command]
+pub fn reset_preprocess_setting(
+    db: State<'_, DatabaseState>,
+    provider: String,
+) -> Result<PreprocessSettingResponse, AppError> {
+    let service = SettingService::new();
+    service.reset_preprocess_setting(db, provider)
+}
@@ -1,2 +1,3 @@
 pub mod api;
-pub mod service;
\ No newline at end of file
+pub mod service;
+pub mod timezone;
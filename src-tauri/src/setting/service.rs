@@ -1,11 +1,31 @@
 use tauri::State;
 use std::sync::Mutex;
-use chrono::Utc;
-use chrono::Datelike;
+use chrono::{Datelike, Days, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
 
-use crate::database::{Database, Setting};
+use crate::ai_service::ProviderKind;
+use crate::database::{Database, DailyUsage, PreprocessSetting, Setting, UsageBucket};
+use crate::error::AppError;
+use crate::image_ops::preprocess::PreprocessOptions;
+use crate::database::setting_repository::{
+    DEFAULT_JOB_MAX_RETRIES, DEFAULT_JOB_WORKERS, DEFAULT_METADATA_WHITELIST,
+};
 
-use super::api::{SaveSettingRequest, SaveSettingResponse, GetSettingResponse};
+use super::api::{
+    SaveSettingRequest, SaveSettingResponse, GetSettingResponse, PreprocessSettingResponse,
+    SavePreprocessSettingRequest, TokenUsageRangeRequest, TokenUsageSeries, TokenUsageSeriesRequest,
+    TokenUsageSeriesResponse,
+};
+use super::timezone::{date_range, parse_timezone, resolve_timezone, today};
+
+/// 任务队列配置的取值上限
+const MAX_JOB_WORKERS: u32 = 8;
+const MAX_JOB_RETRIES: u32 = 10;
+
+/// 未指定范围时的统计天数
+const DEFAULT_SERIES_DAYS: u64 = 30;
+/// 单次统计的最大天数
+const MAX_SERIES_DAYS: u64 = 366;
 
 type DatabaseState = Mutex<Database>;
 
@@ -21,32 +41,76 @@ impl SettingService {
         &self,
         db: State<'_, DatabaseState>,
         request: SaveSettingRequest,
-    ) -> Result<SaveSettingResponse, String> {
-        let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+    ) -> Result<SaveSettingResponse, AppError> {
+        // 校验供应商取值，统一保存为小写标识
+        let provider = match &request.provider {
+            Some(provider) => Some(provider.parse::<ProviderKind>().map_err(AppError::Validation)?.as_str().to_string()),
+            None => None,
+        };
+
+        if request.job_workers.is_some_and(|workers| workers == 0 || workers > MAX_JOB_WORKERS) {
+            return Err(AppError::Validation(format!("工作线程数需在 1 到 {} 之间", MAX_JOB_WORKERS)));
+        }
+        if request.job_max_retries.is_some_and(|retries| retries > MAX_JOB_RETRIES) {
+            return Err(AppError::Validation(format!("重试次数不能超过 {}", MAX_JOB_RETRIES)));
+        }
+        // 时区统一保存为规范名称，空字符串表示使用系统时区
+        let timezone = match request.timezone.as_deref().map(str::trim) {
+            Some("") => Some(String::new()),
+            Some(timezone) => Some(parse_timezone(timezone).map_err(AppError::Validation)?.name().to_string()),
+            None => None,
+        };
+
+        let db = db.lock()?;
 
         let existing = db.setting().get()
-            .map_err(|e| format!("Failed to get settings: {}", e))?;
+            .map_err(|e| AppError::database("Failed to get settings", e))?;
 
         let setting = if let Some(mut existing) = existing {
             // 更新现有设置
+            if let Some(provider) = provider {
+                existing.provider = provider;
+            }
             existing.api_url = request.api_url;
             existing.api_key = request.api_key;
             existing.model = request.model;
+            if let Some(image_model) = request.image_model {
+                existing.image_model = image_model;
+            }
+            if let Some(job_workers) = request.job_workers {
+                existing.job_workers = job_workers;
+            }
+            if let Some(job_max_retries) = request.job_max_retries {
+                existing.job_max_retries = job_max_retries;
+            }
+            if let Some(metadata_whitelist) = request.metadata_whitelist {
+                existing.metadata_whitelist = metadata_whitelist;
+            }
+            if let Some(timezone) = timezone {
+                existing.timezone = timezone;
+            }
             existing.update_at = Utc::now().timestamp_millis();
             existing
         } else {
             // 创建新设置
             Setting {
                 id: uuid::Uuid::new_v4().to_string(),
+                provider: provider.unwrap_or_else(|| ProviderKind::OpenAI.as_str().to_string()),
                 api_url: request.api_url,
                 api_key: request.api_key,
                 model: request.model,
+                image_model: request.image_model.unwrap_or_else(|| "gpt-image-1".to_string()),
+                job_workers: request.job_workers.unwrap_or(DEFAULT_JOB_WORKERS),
+                job_max_retries: request.job_max_retries.unwrap_or(DEFAULT_JOB_MAX_RETRIES),
+                metadata_whitelist: request.metadata_whitelist
+                    .unwrap_or_else(|| DEFAULT_METADATA_WHITELIST.to_vec()),
+                timezone: timezone.unwrap_or_default(),
                 update_at: Utc::now().timestamp_millis(),
             }
         };
 
         db.setting().update(&setting)
-            .map_err(|e| format!("Failed to save settings: {}", e))?;
+            .map_err(|e| AppError::database("Failed to save settings", e))?;
 
         Ok(SaveSettingResponse {
             success: true,
@@ -58,89 +122,248 @@ impl SettingService {
     pub fn get_setting(
         &self,
         db: State<'_, DatabaseState>,
-    ) -> Result<GetSettingResponse, String> {
-        let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+    ) -> Result<GetSettingResponse, AppError> {
+        let db = db.lock()?;
 
         let setting = db.setting().get_or_create_default()
-            .map_err(|e| format!("Failed to get settings: {}", e))?;
+            .map_err(|e| AppError::database("Failed to get settings", e))?;
 
         Ok(GetSettingResponse {
+            provider: setting.provider,
             api_url: setting.api_url,
             model: setting.model,
+            image_model: setting.image_model,
+            job_workers: setting.job_workers,
+            job_max_retries: setting.job_max_retries,
+            metadata_whitelist: setting.metadata_whitelist,
+            system_timezone: resolve_timezone("").name().to_string(),
+            timezone: setting.timezone,
             has_api_key: !setting.api_key.is_empty(),
         })
     }
 
-    /// 获取日度token使用量
-    pub fn get_daily_token_usage(
+    /// 获取全部供应商的图片预处理配置
+    pub fn get_preprocess_settings(
         &self,
         db: State<'_, DatabaseState>,
-    ) -> Result<i64, String> {
-        let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+    ) -> Result<Vec<PreprocessSettingResponse>, AppError> {
+        let db = db.lock()?;
+
+        [ProviderKind::OpenAI, ProviderKind::Anthropic, ProviderKind::Gemini, ProviderKind::Ollama]
+            .into_iter()
+            .map(|kind| preprocess_setting_response(&db, kind))
+            .collect()
+    }
 
-        let today_start = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
-        let today_end = today_start + 24 * 60 * 60 * 1000;
+    /// 保存供应商的图片预处理配置
+    pub fn save_preprocess_setting(
+        &self,
+        db: State<'_, DatabaseState>,
+        request: SavePreprocessSettingRequest,
+    ) -> Result<PreprocessSettingResponse, AppError> {
+        let kind = request.provider.parse::<ProviderKind>().map_err(AppError::Validation)?;
+        request.options.validate(kind).map_err(AppError::Validation)?;
 
-        let galleries = db.gallery().get_all()
-            .map_err(|e| format!("Failed to get galleries: {}", e))?;
+        let db = db.lock()?;
 
-        let daily_usage: i64 = galleries
-            .iter()
-            .filter(|g| g.create_at >= today_start && g.create_at < today_end)
-            .map(|g| g.total_input_tokens + g.total_ouput_tokens)
-            .sum();
+        db.preprocess_setting().save(&PreprocessSetting {
+            provider: kind.as_str().to_string(),
+            options: request.options,
+            update_at: Utc::now().timestamp_millis(),
+        })
+        .map_err(|e| AppError::database("Failed to save preprocess settings", e))?;
 
-        Ok(daily_usage)
+        preprocess_setting_response(&db, kind)
     }
 
-    /// 获取月度token使用量
-    pub fn get_monthly_token_usage(
+    /// 删除供应商的自定义配置，恢复默认值
+    pub fn reset_preprocess_setting(
         &self,
         db: State<'_, DatabaseState>,
-    ) -> Result<i64, String> {
-        let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+        provider: String,
+    ) -> Result<PreprocessSettingResponse, AppError> {
+        let kind = provider.parse::<ProviderKind>().map_err(AppError::Validation)?;
 
-        let now = Utc::now();
-        let month_start = now.date_naive().with_day(1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
-        let next_month = if now.month() == 12 {
-            now.date_naive().with_year(now.year() + 1).unwrap().with_month(1).unwrap().with_day(1).unwrap()
-        } else {
-            now.date_naive().with_month(now.month() + 1).unwrap().with_day(1).unwrap()
-        };
-        let month_end = next_month.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
+        let db = db.lock()?;
+
+        db.preprocess_setting().delete(kind.as_str())
+            .map_err(|e| AppError::database("Failed to reset preprocess settings", e))?;
 
-        let galleries = db.gallery().get_all()
-            .map_err(|e| format!("Failed to get galleries: {}", e))?;
+        preprocess_setting_response(&db, kind)
+    }
 
-        let monthly_usage: i64 = galleries
-            .iter()
-            .filter(|g| g.create_at >= month_start && g.create_at < month_end)
-            .map(|g| g.total_input_tokens + g.total_ouput_tokens)
-            .sum();
+    /// 获取日度token使用量，按设置的时区划分日期
+    pub fn get_daily_token_usage(
+        &self,
+        db: State<'_, DatabaseState>,
+    ) -> Result<i64, AppError> {
+        let db = db.lock()?;
+        let tz = usage_timezone(&db)?;
+        let today = today(tz);
+        total_tokens(&db, tz, today, today)
+    }
 
-        Ok(monthly_usage)
+    /// 获取月度token使用量
+    pub fn get_monthly_token_usage(
+        &self,
+        db: State<'_, DatabaseState>,
+    ) -> Result<i64, AppError> {
+        let db = db.lock()?;
+        let tz = usage_timezone(&db)?;
+        let month_start = today(tz).with_day(1).unwrap();
+        total_tokens(&db, tz, month_start, month_start + Months::new(1) - Days::new(1))
     }
 
     /// 获取年度token使用量
     pub fn get_yearly_token_usage(
         &self,
         db: State<'_, DatabaseState>,
-    ) -> Result<i64, String> {
-        let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+    ) -> Result<i64, AppError> {
+        let db = db.lock()?;
+        let tz = usage_timezone(&db)?;
+        let year_start = today(tz).with_ordinal(1).unwrap();
+        total_tokens(&db, tz, year_start, year_start + Months::new(12) - Days::new(1))
+    }
+
+    /// 获取任意日期范围（闭区间）的token使用量
+    pub fn get_token_usage_in_range(
+        &self,
+        db: State<'_, DatabaseState>,
+        request: TokenUsageRangeRequest,
+    ) -> Result<i64, AppError> {
+        if request.start_date > request.end_date {
+            return Err(AppError::validation("开始日期不能晚于结束日期"));
+        }
+
+        let db = db.lock()?;
+        let tz = usage_timezone(&db)?;
+        total_tokens(&db, tz, request.start_date, request.end_date)
+    }
+
+    /// 按天汇总的用量序列，缺少记录的日期补零，便于直接绘图
+    pub fn get_token_usage_series(
+        &self,
+        db: State<'_, DatabaseState>,
+        request: TokenUsageSeriesRequest,
+    ) -> Result<TokenUsageSeriesResponse, AppError> {
+        let db = db.lock()?;
+        let tz = usage_timezone(&db)?;
+
+        let end_date = request.end_date.unwrap_or_else(|| today(tz));
+        let start_date = request.start_date
+            .unwrap_or_else(|| end_date - Days::new(DEFAULT_SERIES_DAYS - 1));
+        if start_date > end_date {
+            return Err(AppError::validation("开始日期不能晚于结束日期"));
+        }
+        if (end_date - start_date).num_days() >= MAX_SERIES_DAYS as i64 {
+            return Err(AppError::Validation(format!("统计范围不能超过 {} 天", MAX_SERIES_DAYS)));
+        }
 
-        let now = Utc::now();
-        let year_start = now.date_naive().with_month(1).unwrap().with_day(1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
-        let year_end = now.date_naive().with_year(now.year() + 1).unwrap().with_month(1).unwrap().with_day(1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
+        // 每天的起止时刻按时区单独计算，夏令时切换当天不是 24 小时
+        let buckets: Vec<UsageBucket> = start_date
+            .iter_days()
+            .take_while(|date| *date <= end_date)
+            .map(|date| {
+                let (start, end) = date_range(date, date, tz);
+                UsageBucket { day: date.format("%Y-%m-%d").to_string(), start, end }
+            })
+            .collect();
+        let usages = db.usage().daily_usage(&buckets, request.group_by)
+            .map_err(|e| AppError::database("Failed to get token usage", e))?;
 
-        let galleries = db.gallery().get_all()
-            .map_err(|e| format!("Failed to get galleries: {}", e))?;
+        let dates: Vec<String> = buckets.into_iter().map(|bucket| bucket.day).collect();
+        Ok(TokenUsageSeriesResponse {
+            series: build_series(&dates, usages),
+            dates,
+            timezone: tz.name().to_string(),
+        })
+    }
+}
+
+// 设置中的时区，未设置时为系统时区
+fn usage_timezone(db: &Database) -> Result<Tz, AppError> {
+    let setting = db.setting().get_or_create_default()
+        .map_err(|e| AppError::database("Failed to get settings", e))?;
+    Ok(resolve_timezone(&setting.timezone))
+}
+
+// 时区内日期闭区间 [start, end] 的 token 总量
+fn total_tokens(db: &Database, tz: Tz, start: NaiveDate, end: NaiveDate) -> Result<i64, AppError> {
+    let (start, end) = date_range(start, end, tz);
+    db.usage().total_tokens(start, end)
+        .map_err(|e| AppError::database("Failed to get token usage", e))
+}
+
+// 按分组拆成与 dates 等长的序列，分组按总用量降序排列
+fn build_series(dates: &[String], usages: Vec<DailyUsage>) -> Vec<TokenUsageSeries> {
+    let mut series: Vec<TokenUsageSeries> = Vec::new();
+    for usage in usages {
+        let Some(index) = dates.iter().position(|date| *date == usage.day) else {
+            continue;
+        };
+        let position = match series.iter().position(|s| s.key == usage.key) {
+            Some(position) => position,
+            None => {
+                series.push(TokenUsageSeries {
+                    key: usage.key.clone(),
+                    calls: vec![0; dates.len()],
+                    input_tokens: vec![0; dates.len()],
+                    output_tokens: vec![0; dates.len()],
+                    latency_ms: vec![0; dates.len()],
+                });
+                series.len() - 1
+            }
+        };
+        let entry = &mut series[position];
+        entry.calls[index] = usage.calls;
+        entry.input_tokens[index] = usage.input_tokens;
+        entry.output_tokens[index] = usage.output_tokens;
+        entry.latency_ms[index] = usage.latency_ms;
+    }
+
+    let total = |s: &TokenUsageSeries| -> i64 { s.input_tokens.iter().chain(&s.output_tokens).sum() };
+    series.sort_by_key(|s| std::cmp::Reverse(total(s)));
+    series
+}
+
+fn preprocess_setting_response(db: &Database, kind: ProviderKind) -> Result<PreprocessSettingResponse, AppError> {
+    let saved = db.preprocess_setting().get(kind.as_str())
+        .map_err(|e| AppError::database("Failed to get preprocess settings", e))?;
 
-        let yearly_usage: i64 = galleries
-            .iter()
-            .filter(|g| g.create_at >= year_start && g.create_at < year_end)
-            .map(|g| g.total_input_tokens + g.total_ouput_tokens)
-            .sum();
+    Ok(PreprocessSettingResponse {
+        provider: kind.as_str().to_string(),
+        customized: saved.is_some(),
+        options: saved
+            .map(|setting| setting.options)
+            .unwrap_or_else(|| PreprocessOptions::for_provider(kind)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_series() {
+        let dates: Vec<String> = ["2026-10-01", "2026-10-02", "2026-10-03"].iter().map(|d| d.to_string()).collect();
+        let usage = |day: &str, key: &str, tokens| DailyUsage {
+            day: day.to_string(),
+            key: key.to_string(),
+            calls: 1,
+            input_tokens: tokens,
+            output_tokens: 0,
+            latency_ms: 100,
+        };
 
-        Ok(yearly_usage)
+        let series = build_series(&dates, vec![
+            usage("2026-10-01", "gpt-4o", 10),
+            usage("2026-10-03", "gpt-4o", 30),
+            usage("2026-10-02", "gpt-image-1", 100),
+        ]);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].key, "gpt-image-1");
+        assert_eq!(series[0].input_tokens, vec![0, 100, 0]);
+        assert_eq!(series[1].input_tokens, vec![10, 0, 30]);
+        assert_eq!(series[1].calls, vec![1, 0, 1]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,76 @@
+use chrono::{Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// 查找夏令时跳过的零点时的步长（分钟）
+const GAP_STEP_MINUTES: i64 = 15;
+
+/// 校验设置中的 IANA 时区名
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.trim().parse::<Tz>().map_err(|_| format!("无法识别的时区: {}", name))
+}
+
+/// 用量统计使用的时区：设置为空时使用系统时区，系统时区无法识别时退回 UTC
+pub fn resolve_timezone(configured: &str) -> Tz {
+    if !configured.trim().is_empty() {
+        if let Ok(tz) = parse_timezone(configured) {
+            return tz;
+        }
+    }
+    iana_time_zone::get_timezone()
+        .ok()
+        .and_then(|name| name.parse().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+/// 时区内的当前日期
+pub fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+/// 时区内某天开始时刻的毫秒时间戳。夏令时切换在零点时，零点不存在则取当天第一个有效时刻，
+/// 零点出现两次则取较早的一次
+pub fn day_start(date: NaiveDate, tz: Tz) -> i64 {
+    let midnight = date.and_time(NaiveTime::MIN);
+    (0..24 * 60 / GAP_STEP_MINUTES)
+        .find_map(|step| tz.from_local_datetime(&(midnight + Duration::minutes(step * GAP_STEP_MINUTES))).earliest())
+        .map(|start| start.timestamp_millis())
+        .unwrap_or_else(|| midnight.and_utc().timestamp_millis())
+}
+
+/// 本地日期闭区间 [start, end] 对应的毫秒时间范围 [start_ms, end_ms)
+pub fn date_range(start: NaiveDate, end: NaiveDate, tz: Tz) -> (i64, i64) {
+    (day_start(start, tz), day_start(end + Duration::days(1), tz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn utc_millis(value: &str) -> i64 {
+        chrono::DateTime::parse_from_rfc3339(value).unwrap().timestamp_millis()
+    }
+
+    #[test]
+    fn test_day_start() {
+        let shanghai = parse_timezone("Asia/Shanghai").unwrap();
+        assert_eq!(day_start(date("2026-10-17"), shanghai), utc_millis("2026-10-16T16:00:00Z"));
+
+        // 纽约夏令时开始当天只有 23 小时，结束当天有 25 小时
+        let new_york = parse_timezone("America/New_York").unwrap();
+        let (start, end) = date_range(date("2026-03-08"), date("2026-03-08"), new_york);
+        assert_eq!(end - start, 23 * 60 * 60 * 1000);
+        let (start, end) = date_range(date("2026-11-01"), date("2026-11-01"), new_york);
+        assert_eq!(end - start, 25 * 60 * 60 * 1000);
+
+        // 古巴在零点切换夏令时，当天从 01:00 开始
+        let havana = parse_timezone("America/Havana").unwrap();
+        assert_eq!(day_start(date("2026-03-08"), havana), utc_millis("2026-03-08T05:00:00Z"));
+
+        assert!(parse_timezone("Mars/Olympus").is_err());
+        assert_eq!(resolve_timezone("Asia/Shanghai"), shanghai);
+    }
+}
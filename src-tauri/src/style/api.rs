@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 
 use crate::database::Database;
+use crate::error::AppError;
 use super::service::StyleService;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,9 +25,9 @@ type DatabaseState = Mutex<Database>;
 
 /// 获取全部风格接口
 #[tauri::command]
-pub fn get_all_styles(db: State<'_, DatabaseState>) -> Result<Vec<crate::database::Style>, String> {
-    let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
-    db.style().get_all().map_err(|e| format!("Failed to get styles: {}", e))
+pub fn get_all_styles(db: State<'_, DatabaseState>) -> Result<Vec<crate::database::Style>, AppError> {
+    let db = db.lock()?;
+    db.style().get_all().map_err(|e| AppError::database("Failed to get styles", e))
 }
 
 /// 添加风格接口
@@ -34,7 +35,7 @@ pub fn get_all_styles(db: State<'_, DatabaseState>) -> Result<Vec<crate::databas
 pub fn add_style(
     db: State<'_, DatabaseState>,
     request: CreateStyleRequest,
-) -> Result<CreateStyleResponse, String> {
+) -> Result<CreateStyleResponse, AppError> {
     let service = StyleService::new();
     service.create_style(db, request)
 }
@@ -44,7 +45,7 @@ pub fn add_style(
 pub fn delete_style(
     db: State<'_, DatabaseState>,
     id: String,
-) -> Result<(), String> {
-    let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
-    db.style().delete(&id).map_err(|e| format!("Failed to delete style: {}", e))
+) -> Result<(), AppError> {
+    let db = db.lock()?;
+    db.style().delete(&id).map_err(|e| AppError::database("Failed to delete style", e))
 }
\ No newline at end of file
@@ -5,6 +5,7 @@ use chrono::Utc;
 use serde_json;
 
 use crate::database::{Database, Style};
+use crate::error::AppError;
 
 use super::api::{CreateStyleRequest, CreateStyleResponse};
 
@@ -22,14 +23,14 @@ impl StyleService {
         &self,
         db: State<'_, DatabaseState>,
         request: CreateStyleRequest,
-    ) -> Result<CreateStyleResponse, String> {
-        let db = db.lock().map_err(|e| format!("Database lock error: {}", e))?;
+    ) -> Result<CreateStyleResponse, AppError> {
+        let db = db.lock()?;
 
         // 检查风格名称是否已存在
         match db.style().get_by_name(&request.name) {
-            Ok(Some(_)) => return Err("风格名称已存在".to_string()),
+            Ok(Some(_)) => return Err(AppError::validation("风格名称已存在")),
             Ok(None) => {},
-            Err(e) => return Err(format!("Failed to check style: {}", e)),
+            Err(e) => return Err(AppError::database("Failed to check style", e)),
         }
 
         let style = Style {
@@ -43,7 +44,7 @@ impl StyleService {
         };
 
         db.style().create(&style)
-            .map_err(|e| format!("Failed to create style: {}", e))?;
+            .map_err(|e| AppError::database("Failed to create style", e))?;
 
         Ok(CreateStyleResponse {
             success: true,
@@ -101,7 +101,7 @@ export default function Gallery() {
                 {/* 图片预览 */}
                 <div className="relative aspect-square bg-gray-100 rounded-lg overflow-hidden">
                   <img
-                    src={image.processedImage}
+                    src={image.thumbnailImage ?? image.processedImage}
                     alt={image.metadata.originalName || '处理后的图片'}
                     className="w-full h-full object-cover cursor-pointer hover:opacity-90 transition-opacity"
                     onClick={() => setSelectedImage(image)}
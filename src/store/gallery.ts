@@ -1,10 +1,11 @@
 import { create } from 'zustand'
-import { galleryAPI, ImageEditRequest, ImageEditResponse, GalleryItem } from '@/utils/backend-api'
+import { galleryAPI, galleryImageUrl, ImageEditRequest, ImageEditResponse, GalleryItem } from '@/utils/backend-api'
 
 export interface ImageHistory {
   id: string
   originalImage: string
   processedImage: string
+  thumbnailImage?: string
   prompt: string
   style: string
   createdAt: number
@@ -82,19 +83,20 @@ export const useGalleryStore = create<GalleryState>((set, get) => ({
       const images = await imageHistoryAPI.getAll()
       const formattedImages: ImageHistory[] = images.map(img => ({
         id: img.id,
-        originalImage: img.origin_image,
-        processedImage: img.effect_image,
+        originalImage: galleryImageUrl(img.id, 'origin'),
+        processedImage: img.effect_image ? galleryImageUrl(img.id, 'effect') : '',
+        thumbnailImage: galleryImageUrl(img.id, 'thumb'),
         prompt: '', // 后端没有存储prompt，需要从前端获取
         style: '', // 后端没有存储style信息
         createdAt: img.create_at,
         tags: [], // 后端没有tags字段
         metadata: {
           originalName: undefined,
-          originalSize: undefined,
-          processedSize: undefined,
-          dimensions: undefined,
+          originalSize: img.origin_image.byte_size,
+          processedSize: img.effect_image?.byte_size,
+          dimensions: { width: img.origin_image.width, height: img.origin_image.height },
           processingTime: undefined,
-          tokensUsed: img.total_input_tokens + img.total_ouput_tokens,
+          tokensUsed: img.total_input_tokens + img.total_output_tokens,
           model: undefined
         }
       }))
@@ -26,9 +26,34 @@ const tokenAPI = {
     return []
   },
 
-  async getStats(_days: number): Promise<TokenUsage[]> {
-    // 新的后端没有token统计API，返回空数组
-    return []
+  // 最近 days 天按模型汇总的用量，每个有用量的日期和模型一行
+  async getStats(days: number): Promise<TokenUsage[]> {
+    // 结束日期由后端取设置时区内的今天，开始日期按本机日期推算
+    const start = new Date()
+    start.setDate(start.getDate() - (days - 1))
+    const pad = (value: number) => value.toString().padStart(2, '0')
+    const { dates, series } = await settingAPI.getTokenUsageSeries({
+      start_date: `${start.getFullYear()}-${pad(start.getMonth() + 1)}-${pad(start.getDate())}`,
+      group_by: 'model'
+    })
+
+    return series.flatMap(item =>
+      dates.flatMap((date, index) => {
+        if (item.calls[index] === 0) {
+          return []
+        }
+        return [{
+          id: `${date}-${item.key}`,
+          date,
+          model: item.key,
+          input_tokens: item.input_tokens[index],
+          output_tokens: item.output_tokens[index],
+          total_tokens: item.input_tokens[index] + item.output_tokens[index],
+          conversation_count: item.calls[index],
+          created_at: new Date(date).getTime()
+        }]
+      })
+    )
   }
 }
 
@@ -177,6 +177,11 @@ export function getErrorMessage(error: unknown): string {
     return error.message
   }
 
+  // 后端命令返回的 AppError
+  if (error && typeof error === 'object' && 'code' in error && 'message' in error) {
+    return String(error.message)
+  }
+
   return '发生了未知错误'
 }
 
@@ -1,10 +1,17 @@
-import { invoke } from '@tauri-apps/api/core'
+import { convertFileSrc, invoke } from '@tauri-apps/api/core'
 
 // Gallery API interfaces
 export interface ImageEditRequest {
-  origin_image: string
+  // 新建编辑时必填；传入 gallery_id 时在该记录上继续编辑
+  origin_image?: string
+  gallery_id?: string
+  // 基于指定版本编辑，不传时使用当前版本
+  base_version_id?: string
   prompt: string
   style_name?: string
+  request_id?: string
+  // 候选结果数量，默认 1
+  n?: number
 }
 
 export interface ImageEditResponse {
@@ -12,15 +19,244 @@ export interface ImageEditResponse {
   effect_image?: string
   gallery_id: string
   message: string
+  candidate_version_ids: string[]
+  // 编辑在后台任务队列中执行，结果通过 JOB_PROGRESS_EVENT 通知
+  job_id?: string
+}
+
+// 流式输出事件，按 gallery_id 区分
+export const AI_STREAM_DELTA_EVENT = 'ai-stream-delta'
+export const AI_STREAM_DONE_EVENT = 'ai-stream-done'
+export const JOB_PROGRESS_EVENT = 'job-progress'
+
+export type JobStatus = 'pending' | 'running' | 'succeeded' | 'failed' | 'cancelled'
+
+export interface Job {
+  id: string
+  kind: string
+  gallery_id: string
+  request_id: string | null
+  payload: string
+  status: JobStatus
+  attempts: number
+  max_attempts: number
+  error: string | null
+  result: string | null
+  run_after: number
+  create_at: number
+  update_at: number
+}
+
+export interface JobProgress {
+  job_id: string
+  gallery_id: string
+  kind: string
+  status: JobStatus
+  stage: 'started' | 'generating' | 'saving' | 'retrying' | 'finished'
+  attempt: number
+  max_attempts: number
+  error: string | null
+}
+
+export interface ListJobsRequest {
+  status?: JobStatus
+  limit?: number
+}
+
+export const BATCH_PROGRESS_EVENT = 'batch-progress'
+
+export interface BatchEditRequest {
+  // 本地图片路径，每个文件生成一条新的图库记录
+  paths?: string[]
+  // 已有图库记录，以其原图生成一条新的图库记录，原记录不受影响
+  gallery_ids?: string[]
+  prompt: string
+  style_name?: string
+  concurrency?: number
+}
+
+export interface BatchEditResponse {
+  batch_id: string
+  total: number
+  queued: number
+  failed: number
+}
+
+export interface BatchProgress {
+  batch_id: string
+  total: number
+  pending: number
+  running: number
+  succeeded: number
+  failed: number
+  cancelled: number
+  input_tokens: number
+  output_tokens: number
+}
+
+export interface BatchFailure {
+  position: number
+  input: string
+  gallery_id: string | null
+  job_id: string | null
+  error: string | null
+}
+
+export interface BatchSummary {
+  batch: {
+    id: string
+    prompt: string
+    style_name: string | null
+    concurrency: number
+    total: number
+    create_at: number
+  }
+  progress: BatchProgress
+  failures: BatchFailure[]
+}
+
+export interface AIStreamDelta {
+  gallery_id: string
+  delta: string
+}
+
+export interface TokenUsage {
+  input_tokens: number
+  output_tokens: number
+  cached_input_tokens: number
+  image_input_tokens: number
+}
+
+export interface AIStreamDone {
+  gallery_id: string
+  content: string
+  tokens_used: number
+  usage: TokenUsage
+}
+
+export interface StoredImage {
+  hash: string
+  mime_type: string
+  width: number
+  height: number
+  byte_size: number
 }
 
 export interface GalleryItem {
   id: string
-  origin_image: string
-  effect_image: string
+  origin_image: StoredImage
+  effect_image: StoredImage | null
   total_input_tokens: number
-  total_ouput_tokens: number
+  total_output_tokens: number
+  // pending 为编辑任务排队中，processing 为执行中
+  status: 'pending' | 'processing' | 'succeeded' | 'failed' | 'cancelled'
+  style_name: string | null
   create_at: number
+  current_version_id: string | null
+  metadata: ImageMetadata
+  // 最近一次编辑失败的原因
+  error: string | null
+}
+
+// 导入时从原图解析的元数据
+export interface ImageMetadata {
+  color_type: string | null
+  // EXIF 拍摄时间，YYYY-MM-DDTHH:MM:SS 格式的相机本地时间
+  taken_at: string | null
+  camera_model: string | null
+  // EXIF 方向，1-8
+  orientation: number | null
+}
+
+export interface GalleryVersion {
+  id: string
+  gallery_id: string
+  parent_id: string | null
+  source: 'origin' | 'ai' | 'local'
+  prompt: string | null
+  style_name: string | null
+  model: string | null
+  params: string | null
+  image: StoredImage
+  input_tokens: number
+  output_tokens: number
+  create_at: number
+}
+
+export interface VersionListResponse {
+  current_version_id: string | null
+  versions: GalleryVersion[]
+}
+
+export interface BranchVersionRequest {
+  gallery_id: string
+  version_id: string
+  prompt: string
+  style_name?: string
+  request_id?: string
+  n?: number
+}
+
+// 本地图片操作，按顺序应用，不消耗 token
+export type LocalOp =
+  | { op: 'crop'; x: number; y: number; width: number; height: number }
+  | { op: 'crop_aspect'; ratio_width: number; ratio_height: number }
+  | { op: 'resize'; width?: number; height?: number }
+  | { op: 'rotate'; degrees: number }
+  | { op: 'flip'; direction: 'horizontal' | 'vertical' }
+  | { op: 'brightness'; value: number }
+  | { op: 'contrast'; value: number }
+  | { op: 'saturation'; value: number }
+  | { op: 'hue'; degrees: number }
+  | { op: 'blur'; sigma: number }
+  | { op: 'sharpen'; sigma: number; threshold: number }
+  | { op: 'grayscale' }
+  | { op: 'sepia' }
+  | { op: 'levels'; black: number; white: number; gamma: number }
+
+export interface ApplyLocalOpsRequest {
+  gallery_id: string
+  base_version_id?: string
+  ops: LocalOp[]
+}
+
+export interface ApplyLocalOpsResponse {
+  gallery: GalleryItem
+  version: GalleryVersion
+}
+
+export interface VersionChange {
+  field: string
+  from: string | null
+  to: string | null
+}
+
+export interface VersionDiffResponse {
+  from: GalleryVersion
+  to: GalleryVersion
+  common_ancestor_id: string | null
+  changes: VersionChange[]
+}
+
+export interface GalleryMessage {
+  id: string
+  gallery_id: string
+  role: 'user' | 'assistant'
+  content: string
+  create_at: number
+  base_version_id: string | null
+  style_name: string | null
+}
+
+export interface EditMessageRequest {
+  id: string
+  content: string
+}
+
+export interface RegenerateRequest {
+  message_id: string
+  request_id?: string
+  n?: number
 }
 
 export interface StyleGenerateRequest {
@@ -59,10 +295,24 @@ export interface CreateStyleResponse {
 }
 
 // Setting API interfaces
+export type AIProviderKind = 'openai' | 'anthropic' | 'gemini' | 'ollama'
+
+// 上传给 AI 供应商前可保留的元数据类别，其余类别（如 GPS、设备信息）一律移除
+export type MetadataKind = 'exif' | 'xmp' | 'iptc' | 'icc_profile' | 'comment'
+
 export interface SaveSettingRequest {
+  provider?: AIProviderKind
   api_url: string
   api_key: string
   model: string
+  image_model?: string
+  // 任务队列工作线程数，重启后生效
+  job_workers?: number
+  job_max_retries?: number
+  // 上传前保留的元数据类别，空数组表示全部移除
+  metadata_whitelist?: MetadataKind[]
+  // 用量统计使用的 IANA 时区名，空字符串表示使用系统时区
+  timezone?: string
 }
 
 export interface SaveSettingResponse {
@@ -71,29 +321,94 @@ export interface SaveSettingResponse {
 }
 
 export interface GetSettingResponse {
+  provider: AIProviderKind
   api_url: string
   model: string
+  image_model: string
+  job_workers: number
+  job_max_retries: number
+  metadata_whitelist: MetadataKind[]
+  // 设置的时区，为空时使用系统时区
+  timezone: string
+  system_timezone: string
   has_api_key: boolean
 }
 
+// 用量统计，日期为设置时区内的 YYYY-MM-DD 闭区间，序列默认最近 30 天
+export interface TokenUsageRangeRequest {
+  start_date: string
+  end_date: string
+}
+
+export type UsageGroupBy = 'none' | 'provider' | 'model' | 'operation'
+
+export interface TokenUsageSeriesRequest {
+  start_date?: string
+  end_date?: string
+  group_by?: UsageGroupBy
+}
+
+// 一个分组的按天用量，各数组与 dates 一一对应
+export interface TokenUsageSeries {
+  key: string
+  calls: number[]
+  input_tokens: number[]
+  output_tokens: number[]
+  latency_ms: number[]
+}
+
+export interface TokenUsageSeriesResponse {
+  dates: string[]
+  series: TokenUsageSeries[]
+  // 划分日期所用的时区
+  timezone: string
+}
+
+// 上传给 AI 供应商前的图片预处理配置，每个供应商单独保存
+export type PreprocessOutputFormat = 'auto' | 'jpeg' | 'png' | 'webp'
+
+export interface PreprocessOptions {
+  sniff_mime: boolean
+  apply_orientation: boolean
+  // 长边上限，null 表示不缩放
+  max_edge: number | null
+  output_format: PreprocessOutputFormat
+  // JPEG 编码质量 1-100
+  quality: number
+}
+
+export interface PreprocessSetting extends PreprocessOptions {
+  provider: AIProviderKind
+  // 是否保存过自定义配置，否则为供应商默认配置
+  customized: boolean
+}
+
+export interface SavePreprocessSettingRequest extends PreprocessOptions {
+  provider: AIProviderKind
+}
+
 // AI API interfaces
 export interface AIProcessRequest {
   prompt: string
   image_data: string
+  provider?: AIProviderKind
   api_url: string
   api_key: string
   model: string
   style_prompt?: string
+  request_id?: string
 }
 
 export interface AIProcessResponse {
   content: string
   tokens_used: number
+  usage: TokenUsage
   model: string
 }
 
 export interface StyleGenerationRequest {
   content: string
+  provider?: AIProviderKind
   api_url: string
   api_key: string
   model: string
@@ -104,6 +419,56 @@ export interface StyleGenerationResponse {
   prompt: string
 }
 
+export type GallerySort = 'create_at_desc' | 'create_at_asc' | 'tokens_desc' | 'tokens_asc'
+
+export interface ListImagesRequest {
+  cursor?: string
+  limit?: number
+  start_time?: number
+  end_time?: number
+  style_name?: string
+  min_tokens?: number
+  max_tokens?: number
+  status?: GalleryItem['status']
+  // 原图长边像素范围
+  min_edge?: number
+  max_edge?: number
+  mime_type?: string
+  taken_year?: number
+  // 拍摄日期范围，YYYY-MM-DD（闭区间）
+  taken_from?: string
+  taken_to?: string
+  camera_model?: string
+  sort?: GallerySort
+}
+
+export type ThumbnailSize = 'small' | 'medium' | 'large'
+
+export interface GalleryThumbnail {
+  size: ThumbnailSize
+  image: StoredImage
+}
+
+// 列表项附带已生成的缩略图，尚未生成时为空数组
+export interface GalleryListItem extends GalleryItem {
+  thumbnails: GalleryThumbnail[]
+}
+
+export interface ListImagesResponse {
+  items: GalleryListItem[]
+  next_cursor: string | null
+  total: number
+}
+
+export type GalleryImageKind = 'origin' | 'effect' | 'thumb'
+
+// 图库图片通过 gallery:// 协议直接加载，可用于 <img src>
+// 缩略图可指定规格，缺省为 medium
+export function galleryImageUrl(id: string, kind: GalleryImageKind, size?: ThumbnailSize): string {
+  const path = kind === 'thumb' && size ? `${id}/${kind}/${size}` : `${id}/${kind}`
+  return convertFileSrc(path, 'gallery')
+}
+
 // Gallery API functions
 export const galleryAPI = {
   async editImage(request: ImageEditRequest): Promise<ImageEditResponse> {
@@ -114,10 +479,76 @@ export const galleryAPI = {
     return invoke('get_all_images')
   },
 
+  async listImages(request: ListImagesRequest = {}): Promise<ListImagesResponse> {
+    return invoke('list_images', { request })
+  },
+
   async batchDeleteImages(ids: string[]): Promise<void> {
     return invoke('batch_delete_images', { ids })
   },
 
+  async getImageData(hash: string): Promise<string> {
+    return invoke('get_image_data', { hash })
+  },
+
+  async getGalleryMessages(galleryId: string): Promise<GalleryMessage[]> {
+    return invoke('get_gallery_messages', { galleryId })
+  },
+
+  async deleteMessage(id: string): Promise<void> {
+    return invoke('delete_message', { id })
+  },
+
+  async editMessage(request: EditMessageRequest): Promise<GalleryMessage> {
+    return invoke('edit_message', { request })
+  },
+
+  async regenerateFromMessage(request: RegenerateRequest): Promise<ImageEditResponse> {
+    return invoke('regenerate_from_message', { request })
+  },
+
+  async listVersions(galleryId: string): Promise<VersionListResponse> {
+    return invoke('list_versions', { galleryId })
+  },
+
+  async checkoutVersion(galleryId: string, versionId: string): Promise<GalleryItem> {
+    return invoke('checkout_version', { galleryId, versionId })
+  },
+
+  async undoVersion(galleryId: string): Promise<GalleryItem> {
+    return invoke('undo_version', { galleryId })
+  },
+
+  async redoVersion(galleryId: string): Promise<GalleryItem> {
+    return invoke('redo_version', { galleryId })
+  },
+
+  async branchFromVersion(request: BranchVersionRequest): Promise<ImageEditResponse> {
+    return invoke('branch_from_version', { request })
+  },
+
+  async applyLocalOps(request: ApplyLocalOpsRequest): Promise<ApplyLocalOpsResponse> {
+    return invoke('apply_local_ops', { request })
+  },
+
+  // 按原参数重试编辑失败的图片
+  async retryEdit(galleryId: string): Promise<ImageEditResponse> {
+    return invoke('retry_edit', { galleryId })
+  },
+
+  // galleryIds 为空时重新生成全部缩略图
+  async regenerateThumbnails(galleryIds: string[] = []): Promise<void> {
+    return invoke('regenerate_thumbnails', { galleryIds })
+  },
+
+  async promoteCandidate(galleryId: string, versionId: string): Promise<GalleryItem> {
+    return invoke('promote_candidate', { galleryId, versionId })
+  },
+
+  async diffVersions(fromVersionId: string, toVersionId: string): Promise<VersionDiffResponse> {
+    return invoke('diff_versions', { fromVersionId, toVersionId })
+  },
+
   async generateStyleFromMessage(request: StyleGenerateRequest): Promise<StyleGenerateResponse> {
     return invoke('generate_style_from_message', { ...request })
   }
@@ -162,6 +593,26 @@ export const settingAPI = {
 
   async getYearlyTokenUsage(): Promise<number> {
     return invoke('get_yearly_token_usage')
+  },
+
+  async getTokenUsageInRange(request: TokenUsageRangeRequest): Promise<number> {
+    return invoke('get_token_usage_in_range', { request })
+  },
+
+  async getTokenUsageSeries(request: TokenUsageSeriesRequest = {}): Promise<TokenUsageSeriesResponse> {
+    return invoke('get_token_usage_series', { request })
+  },
+
+  async getPreprocessSettings(): Promise<PreprocessSetting[]> {
+    return invoke('get_preprocess_settings')
+  },
+
+  async savePreprocessSetting(request: SavePreprocessSettingRequest): Promise<PreprocessSetting> {
+    return invoke('save_preprocess_setting', { request })
+  },
+
+  async resetPreprocessSetting(provider: AIProviderKind): Promise<PreprocessSetting> {
+    return invoke('reset_preprocess_setting', { provider })
   }
 }
 
@@ -176,20 +627,88 @@ export const aiAPI = {
   }
 }
 
+// Job API functions
+export const jobAPI = {
+  async listJobs(request: ListJobsRequest = {}): Promise<Job[]> {
+    return invoke('list_jobs', { request })
+  },
+
+  async getJob(id: string): Promise<Job | null> {
+    return invoke('get_job', { id })
+  },
+
+  // id 可以是任务 ID、请求 ID 或图库 ID，进行中的 AI 请求和排队中的任务都会被取消，返回是否找到了未结束的请求
+  async cancelJob(id: string): Promise<boolean> {
+    return invoke('cancel_job', { id })
+  }
+}
+
+// Batch API functions
+export const batchAPI = {
+  async batchEdit(request: BatchEditRequest): Promise<BatchEditResponse> {
+    return invoke('batch_edit', { request })
+  },
+
+  async getBatchSummary(batchId: string): Promise<BatchSummary> {
+    return invoke('get_batch_summary', { batchId })
+  },
+
+  async cancelBatch(batchId: string): Promise<number> {
+    return invoke('cancel_batch', { batchId })
+  }
+}
+
 // Error handling
+export type AppErrorCode =
+  | 'database'
+  | 'lock'
+  | 'config'
+  | 'auth'
+  | 'quota'
+  | 'rate_limit'
+  | 'network'
+  | 'validation'
+  | 'not_found'
+  | 'provider'
+  | 'cancelled'
+  | 'internal'
+
+// 所有命令失败时返回的错误，message 可直接展示，details 为原始错误信息
+export interface AppError {
+  code: AppErrorCode
+  message: string
+  details: string | null
+  retryable: boolean
+  // 服务端要求的最短重试等待时间（毫秒）
+  retry_after_ms: number | null
+}
+
+export function isAppError(error: unknown): error is AppError {
+  return !!error && typeof error === 'object' && 'code' in error && 'message' in error
+}
+
 export class BackendAPIError extends Error {
-  constructor(message: string) {
-    super(message)
+  public code: AppErrorCode
+  public details: string | null
+  public retryable: boolean
+  public retryAfterMs: number | null
+
+  constructor(error: AppError) {
+    super(error.message)
     this.name = 'BackendAPIError'
+    this.code = error.code
+    this.details = error.details
+    this.retryable = error.retryable
+    this.retryAfterMs = error.retry_after_ms ?? null
   }
 }
 
 export function handleInvokeError(error: any): never {
-  if (typeof error === 'string') {
+  if (isAppError(error)) {
     throw new BackendAPIError(error)
-  } else if (error && typeof error === 'object' && 'message' in error) {
-    throw new BackendAPIError(String(error.message))
+  } else if (typeof error === 'string') {
+    throw new BackendAPIError({ code: 'internal', message: error, details: null, retryable: false, retry_after_ms: null })
   } else {
-    throw new BackendAPIError('未知错误')
+    throw new BackendAPIError({ code: 'internal', message: '未知错误', details: null, retryable: false, retry_after_ms: null })
   }
 }
\ No newline at end of file